@@ -0,0 +1,136 @@
+//! ACPI 表解析。
+//!
+//! 只实现 SMP 启动需要的部分：在 BIOS 区域中找到 RSDP，沿 RSDT/XSDT 找到 MADT，
+//! 再从中读出本地 APIC 的物理地址和所有处理器的 APIC ID。
+//! 所有表都通过物理内存映射读取，因此必须在 `memory::init` 之后调用。
+
+use crate::memory;
+use alloc::vec::Vec;
+
+const RSDP_SIGNATURE: &[u8; 8] = b"RSD PTR ";
+const MADT_SIGNATURE: &[u8; 4] = b"APIC";
+const SDT_HEADER_SIZE: u64 = 36;
+
+// MADT 条目类型
+const MADT_LOCAL_APIC: u8 = 0;
+const MADT_LOCAL_APIC_ADDRESS_OVERRIDE: u8 = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AcpiError {
+    /// BIOS 区域中没有 RSDP
+    RsdpNotFound,
+    /// 表的校验和不正确
+    BadChecksum,
+    /// RSDT/XSDT 中没有 MADT
+    MadtNotFound,
+}
+
+/// MADT 中 SMP 启动需要的信息
+#[derive(Debug, Clone)]
+pub struct MadtInfo {
+    /// 本地 APIC 寄存器的物理地址
+    pub local_apic_address: u64,
+    /// 已启用或可以上线的处理器的 APIC ID，顺序与 MADT 一致
+    pub processor_apic_ids: Vec<u8>,
+}
+
+fn read_phys<T: Copy>(address: u64) -> T {
+    let virt = memory::physical_memory_offset() + address;
+    unsafe { core::ptr::read_unaligned(virt.as_ptr::<T>()) }
+}
+
+fn phys_bytes(address: u64, len: u64) -> &'static [u8] {
+    let virt = memory::physical_memory_offset() + address;
+    unsafe { core::slice::from_raw_parts(virt.as_ptr::<u8>(), len as usize) }
+}
+
+fn checksum_ok(address: u64, len: u64) -> bool {
+    phys_bytes(address, len)
+        .iter()
+        .fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+        == 0
+}
+
+// 在 [start, end) 中按16字节边界查找 RSDP
+fn search_rsdp(start: u64, end: u64) -> Option<u64> {
+    (start..end)
+        .step_by(16)
+        .find(|&address| phys_bytes(address, 8) == RSDP_SIGNATURE && checksum_ok(address, 20))
+}
+
+/// 查找 RSDP 的物理地址：先找 EBDA 的前 1KiB，再找 0xE0000..0x100000
+pub fn find_rsdp() -> Option<u64> {
+    let ebda = u64::from(read_phys::<u16>(0x40e)) << 4;
+    if ebda != 0 {
+        if let Some(address) = search_rsdp(ebda, ebda + 1024) {
+            return Some(address);
+        }
+    }
+    search_rsdp(0xe0000, 0x100000)
+}
+
+// 在 RSDT（4字节表项）或 XSDT（8字节表项）中查找指定签名的表
+fn find_table(root: u64, entry_size: u64, signature: &[u8; 4]) -> Result<u64, AcpiError> {
+    let length = u64::from(read_phys::<u32>(root + 4));
+    if !checksum_ok(root, length) {
+        return Err(AcpiError::BadChecksum);
+    }
+    let entries = (length - SDT_HEADER_SIZE) / entry_size;
+    for index in 0..entries {
+        let entry = root + SDT_HEADER_SIZE + index * entry_size;
+        let table = match entry_size {
+            8 => read_phys::<u64>(entry),
+            _ => u64::from(read_phys::<u32>(entry)),
+        };
+        if phys_bytes(table, 4) == signature {
+            return Ok(table);
+        }
+    }
+    Err(AcpiError::MadtNotFound)
+}
+
+/// 解析 MADT，返回本地 APIC 地址和处理器列表
+pub fn parse_madt() -> Result<MadtInfo, AcpiError> {
+    let rsdp = find_rsdp().ok_or(AcpiError::RsdpNotFound)?;
+    let revision = read_phys::<u8>(rsdp + 15);
+    let madt = if revision >= 2 {
+        find_table(read_phys::<u64>(rsdp + 24), 8, MADT_SIGNATURE)?
+    } else {
+        find_table(u64::from(read_phys::<u32>(rsdp + 16)), 4, MADT_SIGNATURE)?
+    };
+
+    let length = u64::from(read_phys::<u32>(madt + 4));
+    if !checksum_ok(madt, length) {
+        return Err(AcpiError::BadChecksum);
+    }
+    let mut info = MadtInfo {
+        local_apic_address: u64::from(read_phys::<u32>(madt + SDT_HEADER_SIZE)),
+        processor_apic_ids: Vec::new(),
+    };
+
+    // 条目从本地 APIC 地址（4字节）和标志（4字节）之后开始
+    let mut entry = madt + SDT_HEADER_SIZE + 8;
+    while entry + 2 <= madt + length {
+        let entry_type = read_phys::<u8>(entry);
+        let entry_length = u64::from(read_phys::<u8>(entry + 1));
+        if entry_length < 2 {
+            break;
+        }
+        match entry_type {
+            MADT_LOCAL_APIC => {
+                let apic_id = read_phys::<u8>(entry + 3);
+                let flags = read_phys::<u32>(entry + 4);
+                // bit 0：已启用；bit 1：可以上线
+                if flags & 0b11 != 0 {
+                    info.processor_apic_ids.push(apic_id);
+                }
+            }
+            MADT_LOCAL_APIC_ADDRESS_OVERRIDE => {
+                info.local_apic_address = read_phys::<u64>(entry + 4);
+            }
+            _ => {}
+        }
+        entry += entry_length;
+    }
+    Ok(info)
+}
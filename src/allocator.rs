@@ -78,8 +78,43 @@ impl<A> Locked<A> {
         }
     }
 
-    pub fn lock(&self) -> spin::MutexGuard<A> {
-        self.inner.lock()
+    // 持锁期间屏蔽中断，避免中断处理程序（如 `Spawner`）分配内存时与被打断的代码死锁
+    pub fn lock(&self) -> LockedGuard<'_, A> {
+        let interrupts_enabled = x86_64::instructions::interrupts::are_enabled();
+        x86_64::instructions::interrupts::disable();
+        LockedGuard {
+            guard: Some(self.inner.lock()),
+            interrupts_enabled,
+        }
+    }
+}
+
+pub struct LockedGuard<'a, A> {
+    guard: Option<spin::MutexGuard<'a, A>>,
+    interrupts_enabled: bool,
+}
+
+impl<A> core::ops::Deref for LockedGuard<'_, A> {
+    type Target = A;
+
+    fn deref(&self) -> &A {
+        self.guard.as_ref().unwrap()
+    }
+}
+
+impl<A> core::ops::DerefMut for LockedGuard<'_, A> {
+    fn deref_mut(&mut self) -> &mut A {
+        self.guard.as_mut().unwrap()
+    }
+}
+
+impl<A> Drop for LockedGuard<'_, A> {
+    fn drop(&mut self) {
+        // 先释放锁再恢复中断
+        drop(self.guard.take());
+        if self.interrupts_enabled {
+            x86_64::instructions::interrupts::enable();
+        }
     }
 }
 
@@ -0,0 +1,143 @@
+//! 本地 APIC（xAPIC）。
+//!
+//! 本地 APIC 的寄存器是一页 MMIO，`init` 把它映射到固定的虚拟地址并关闭缓存；
+//! 每个 CPU 通过同一地址访问的都是自己的本地 APIC。
+
+use core::sync::atomic::{AtomicBool, Ordering};
+use x86_64::structures::paging::{
+    FrameAllocator, Mapper, OffsetPageTable, Page, PageTableFlags, PhysFrame, Size4KiB,
+};
+use x86_64::{PhysAddr, VirtAddr};
+
+/// 本地 APIC 寄存器页映射到的虚拟地址
+pub const LOCAL_APIC_START: u64 = 0x_5555_5555_0000;
+
+/// 伪中断使用的向量号
+pub const SPURIOUS_VECTOR: u8 = 0xff;
+/// 本地 APIC 定时器使用的向量号
+pub const TIMER_VECTOR: u8 = 0xf0;
+
+// 寄存器偏移
+const REG_ID: u64 = 0x20;
+const REG_EOI: u64 = 0xb0;
+const REG_SPURIOUS: u64 = 0xf0;
+const REG_ERROR_STATUS: u64 = 0x280;
+const REG_ICR_LOW: u64 = 0x300;
+const REG_ICR_HIGH: u64 = 0x310;
+const REG_LVT_TIMER: u64 = 0x320;
+const REG_TIMER_INITIAL: u64 = 0x380;
+const REG_TIMER_CURRENT: u64 = 0x390;
+const REG_TIMER_DIVIDE: u64 = 0x3e0;
+
+const SPURIOUS_APIC_ENABLE: u32 = 1 << 8;
+const ICR_DELIVERY_PENDING: u32 = 1 << 12;
+const ICR_INIT: u32 = 0x0000_4500;
+const ICR_STARTUP: u32 = 0x0000_4600;
+const LVT_MASKED: u32 = 1 << 16;
+// 分频寄存器编码：总线时钟除以 16
+const TIMER_DIVIDE_BY_16: u32 = 0b0011;
+
+static MAPPED: AtomicBool = AtomicBool::new(false);
+
+/// 映射本地 APIC 寄存器页，并启用当前 CPU 的本地 APIC
+pub fn init(
+    physical_address: u64,
+    mapper: &mut OffsetPageTable,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) -> Result<(), &'static str> {
+    if !MAPPED.load(Ordering::Acquire) {
+        let page = Page::<Size4KiB>::containing_address(VirtAddr::new(LOCAL_APIC_START));
+        let frame = PhysFrame::containing_address(PhysAddr::new(physical_address));
+        let flags = PageTableFlags::PRESENT
+            | PageTableFlags::WRITABLE
+            | PageTableFlags::NO_CACHE
+            | PageTableFlags::WRITE_THROUGH
+            | PageTableFlags::NO_EXECUTE;
+        unsafe {
+            mapper
+                .map_to(page, frame, flags, frame_allocator)
+                .map_err(|_| "failed to map local APIC")?
+                .flush();
+        }
+        MAPPED.store(true, Ordering::Release);
+    }
+    enable();
+    Ok(())
+}
+
+/// 本地 APIC 寄存器页是否已经映射
+pub fn is_mapped() -> bool {
+    MAPPED.load(Ordering::Acquire)
+}
+
+unsafe fn read(register: u64) -> u32 {
+    core::ptr::read_volatile((LOCAL_APIC_START + register) as *const u32)
+}
+
+unsafe fn write(register: u64, value: u32) {
+    core::ptr::write_volatile((LOCAL_APIC_START + register) as *mut u32, value);
+}
+
+/// 通过伪中断向量寄存器软件启用当前 CPU 的本地 APIC
+pub fn enable() {
+    unsafe {
+        write(
+            REG_SPURIOUS,
+            SPURIOUS_APIC_ENABLE | u32::from(SPURIOUS_VECTOR),
+        );
+    }
+}
+
+/// 当前 CPU 的 APIC ID
+pub fn id() -> u8 {
+    unsafe { (read(REG_ID) >> 24) as u8 }
+}
+
+/// 通知本地 APIC 当前中断已处理完毕
+pub fn end_of_interrupt() {
+    unsafe { write(REG_EOI, 0) };
+}
+
+// 写 ICR 发送处理器间中断，并等待投递完成
+fn send_ipi(apic_id: u8, command: u32) {
+    unsafe {
+        write(REG_ERROR_STATUS, 0);
+        write(REG_ICR_HIGH, u32::from(apic_id) << 24);
+        write(REG_ICR_LOW, command);
+        while read(REG_ICR_LOW) & ICR_DELIVERY_PENDING != 0 {
+            core::hint::spin_loop();
+        }
+    }
+}
+
+/// 向目标 CPU 发送 INIT IPI，使其进入等待 SIPI 的状态
+pub fn send_init(apic_id: u8) {
+    send_ipi(apic_id, ICR_INIT);
+}
+
+/// 向目标 CPU 发送 STARTUP IPI，它将从物理地址 `page_number * 4096` 处以实模式开始执行
+pub fn send_startup(apic_id: u8, page_number: u8) {
+    send_ipi(apic_id, ICR_STARTUP | u32::from(page_number));
+}
+
+/// 以单次模式启动当前 CPU 的本地 APIC 定时器，计数 `count` 次后触发 [`TIMER_VECTOR`]
+///
+/// `masked` 为真时只计数、不产生中断，用于校准。
+pub fn start_one_shot_timer(count: u32, masked: bool) {
+    let mask = if masked { LVT_MASKED } else { 0 };
+    unsafe {
+        write(REG_TIMER_DIVIDE, TIMER_DIVIDE_BY_16);
+        write(REG_LVT_TIMER, mask | u32::from(TIMER_VECTOR));
+        write(REG_TIMER_INITIAL, count);
+    }
+}
+
+/// 停止本地 APIC 定时器，返回停止前剩余的计数（已经触发时为 0）
+pub fn stop_timer() -> u32 {
+    unsafe {
+        let remaining = read(REG_TIMER_CURRENT);
+        write(REG_TIMER_INITIAL, 0);
+        write(REG_LVT_TIMER, LVT_MASKED | u32::from(TIMER_VECTOR));
+        remaining
+    }
+}
@@ -0,0 +1,424 @@
+//! ELF64 程序加载器。
+//!
+//! 解析 ELF64 可执行文件头和程序头，把 `PT_LOAD` 段按权限映射到新的地址空间，
+//! 再按 System V ABI 在用户栈上布置 `argc`/`argv`/`envp`，最后跳转到入口点执行。
+
+use crate::memory::{self, AddressSpace};
+use crate::usermode::{self, USER_SPACE_END, USER_SPACE_START};
+use alloc::vec;
+use alloc::vec::Vec;
+use x86_64::structures::paging::mapper::{MappedFrame, Translate, TranslateResult};
+use x86_64::structures::paging::{
+    FrameAllocator, FrameDeallocator, Mapper, OffsetPageTable, Page, PageTableFlags, Size4KiB,
+};
+use x86_64::VirtAddr;
+
+const ELF_MAGIC: [u8; 4] = [0x7f, b'E', b'L', b'F'];
+const ELF_CLASS_64: u8 = 2;
+const ELF_DATA_LITTLE_ENDIAN: u8 = 1;
+const ELF_TYPE_EXEC: u16 = 2;
+const ELF_MACHINE_X86_64: u16 = 0x3e;
+const ELF_HEADER_SIZE: usize = 64;
+const PROGRAM_HEADER_SIZE: usize = 56;
+
+const PT_LOAD: u32 = 1;
+const PF_X: u32 = 1;
+const PF_W: u32 = 2;
+
+/// 用户栈栈顶，与用户地址空间末尾之间留出一页空隙
+pub const USER_STACK_TOP: u64 = USER_SPACE_END - 4096;
+/// 用户栈大小（页数）
+pub const USER_STACK_PAGES: u64 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ElfError {
+    /// 文件比声明的结构短
+    Truncated,
+    BadMagic,
+    NotElf64,
+    NotLittleEndian,
+    NotExecutable,
+    UnsupportedMachine,
+    BadProgramHeader,
+    /// 段或入口点不在用户地址空间内
+    OutsideUserSpace,
+    /// `argv`/`envp` 放不进用户栈
+    ArgumentsTooLarge,
+    OutOfMemory,
+    MapFailed,
+}
+
+/// ELF 文件头中加载所需的字段
+#[derive(Debug, Clone, Copy)]
+pub struct ElfHeader {
+    pub entry: u64,
+    pub program_header_offset: u64,
+    pub program_header_count: u16,
+}
+
+/// 程序头中加载所需的字段
+#[derive(Debug, Clone, Copy)]
+pub struct ProgramHeader {
+    pub segment_type: u32,
+    pub flags: u32,
+    pub offset: u64,
+    pub virtual_address: u64,
+    pub file_size: u64,
+    pub memory_size: u64,
+}
+
+/// 已装入地址空间、尚未开始执行的程序
+pub struct LoadedProgram {
+    pub address_space: AddressSpace,
+    pub entry: VirtAddr,
+    pub stack_pointer: VirtAddr,
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> Result<u16, ElfError> {
+    bytes
+        .get(offset..offset + 2)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]))
+        .ok_or(ElfError::Truncated)
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Result<u32, ElfError> {
+    let mut raw = [0; 4];
+    raw.copy_from_slice(bytes.get(offset..offset + 4).ok_or(ElfError::Truncated)?);
+    Ok(u32::from_le_bytes(raw))
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> Result<u64, ElfError> {
+    let mut raw = [0; 8];
+    raw.copy_from_slice(bytes.get(offset..offset + 8).ok_or(ElfError::Truncated)?);
+    Ok(u64::from_le_bytes(raw))
+}
+
+/// 解析并校验 ELF 文件头
+pub fn parse_header(image: &[u8]) -> Result<ElfHeader, ElfError> {
+    if image.len() < ELF_HEADER_SIZE {
+        return Err(ElfError::Truncated);
+    }
+    if image[0..4] != ELF_MAGIC {
+        return Err(ElfError::BadMagic);
+    }
+    if image[4] != ELF_CLASS_64 {
+        return Err(ElfError::NotElf64);
+    }
+    if image[5] != ELF_DATA_LITTLE_ENDIAN {
+        return Err(ElfError::NotLittleEndian);
+    }
+    if read_u16(image, 16)? != ELF_TYPE_EXEC {
+        return Err(ElfError::NotExecutable);
+    }
+    if read_u16(image, 18)? != ELF_MACHINE_X86_64 {
+        return Err(ElfError::UnsupportedMachine);
+    }
+    if usize::from(read_u16(image, 54)?) != PROGRAM_HEADER_SIZE {
+        return Err(ElfError::BadProgramHeader);
+    }
+
+    Ok(ElfHeader {
+        entry: read_u64(image, 24)?,
+        program_header_offset: read_u64(image, 32)?,
+        program_header_count: read_u16(image, 56)?,
+    })
+}
+
+/// 解析第 `index` 个程序头
+pub fn program_header(
+    image: &[u8],
+    header: &ElfHeader,
+    index: u16,
+) -> Result<ProgramHeader, ElfError> {
+    let base = (header.program_header_offset as usize)
+        .checked_add(usize::from(index) * PROGRAM_HEADER_SIZE)
+        .ok_or(ElfError::Truncated)?;
+    Ok(ProgramHeader {
+        segment_type: read_u32(image, base)?,
+        flags: read_u32(image, base + 4)?,
+        offset: read_u64(image, base + 8)?,
+        virtual_address: read_u64(image, base + 16)?,
+        file_size: read_u64(image, base + 32)?,
+        memory_size: read_u64(image, base + 40)?,
+    })
+}
+
+fn in_user_space(start: u64, size: u64) -> bool {
+    match start.checked_add(size) {
+        Some(end) => start >= USER_SPACE_START && end <= USER_SPACE_END,
+        None => false,
+    }
+}
+
+/// 把 ELF 程序装入一个新的地址空间，并准备好带参数的用户栈
+///
+/// 失败时已经映射的段、用户栈和页表都会归还给 `frame_allocator`。
+pub fn load<A>(
+    image: &[u8],
+    argv: &[&str],
+    envp: &[&str],
+    frame_allocator: &mut A,
+) -> Result<LoadedProgram, ElfError>
+where
+    A: FrameAllocator<Size4KiB> + FrameDeallocator<Size4KiB>,
+{
+    let header = parse_header(image)?;
+    if !in_user_space(header.entry, 1) {
+        return Err(ElfError::OutsideUserSpace);
+    }
+
+    let mut address_space = AddressSpace::new(frame_allocator).ok_or(ElfError::OutOfMemory)?;
+    match populate(
+        &mut address_space,
+        image,
+        &header,
+        argv,
+        envp,
+        frame_allocator,
+    ) {
+        Ok(stack_pointer) => Ok(LoadedProgram {
+            address_space,
+            entry: VirtAddr::new(header.entry),
+            stack_pointer,
+        }),
+        Err(error) => {
+            // 地址空间从未激活，可以直接销毁
+            unsafe { address_space.destroy(frame_allocator) };
+            Err(error)
+        }
+    }
+}
+
+// 映射所有 `PT_LOAD` 段和用户栈，返回入口点应使用的栈指针
+fn populate<A>(
+    address_space: &mut AddressSpace,
+    image: &[u8],
+    header: &ElfHeader,
+    argv: &[&str],
+    envp: &[&str],
+    frame_allocator: &mut A,
+) -> Result<VirtAddr, ElfError>
+where
+    A: FrameAllocator<Size4KiB> + FrameDeallocator<Size4KiB>,
+{
+    {
+        let mut mapper = address_space.mapper();
+        for index in 0..header.program_header_count {
+            let program_header = program_header(image, header, index)?;
+            if program_header.segment_type == PT_LOAD {
+                load_segment(&mut mapper, frame_allocator, image, &program_header)?;
+            }
+        }
+    }
+
+    setup_user_stack(address_space, frame_allocator, argv, envp)
+}
+
+fn load_segment<A>(
+    mapper: &mut OffsetPageTable,
+    frame_allocator: &mut A,
+    image: &[u8],
+    program_header: &ProgramHeader,
+) -> Result<(), ElfError>
+where
+    A: FrameAllocator<Size4KiB> + FrameDeallocator<Size4KiB>,
+{
+    if program_header.memory_size == 0 {
+        return Ok(());
+    }
+    if program_header.file_size > program_header.memory_size {
+        return Err(ElfError::BadProgramHeader);
+    }
+    if !in_user_space(program_header.virtual_address, program_header.memory_size) {
+        return Err(ElfError::OutsideUserSpace);
+    }
+    let file_start = program_header.offset as usize;
+    let file_end = file_start
+        .checked_add(program_header.file_size as usize)
+        .ok_or(ElfError::Truncated)?;
+    let file_data = image.get(file_start..file_end).ok_or(ElfError::Truncated)?;
+
+    let mut flags = PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE;
+    if program_header.flags & PF_W != 0 {
+        flags |= PageTableFlags::WRITABLE;
+    }
+    if program_header.flags & PF_X == 0 {
+        flags |= PageTableFlags::NO_EXECUTE;
+    }
+
+    let start = VirtAddr::new(program_header.virtual_address);
+    let end = start + (program_header.memory_size - 1);
+    let pages = Page::range_inclusive(
+        Page::containing_address(start),
+        Page::containing_address(end),
+    );
+    map_user_pages(mapper, frame_allocator, pages, flags)?;
+    write_user_bytes(mapper, start, file_data)
+}
+
+/// 为一段页面分配清零的物理帧并映射；已映射的页面（相邻段共用的页）合并权限
+fn map_user_pages<A>(
+    mapper: &mut OffsetPageTable,
+    frame_allocator: &mut A,
+    pages: impl Iterator<Item = Page<Size4KiB>>,
+    flags: PageTableFlags,
+) -> Result<(), ElfError>
+where
+    A: FrameAllocator<Size4KiB> + FrameDeallocator<Size4KiB>,
+{
+    for page in pages {
+        if let TranslateResult::Mapped {
+            flags: existing, ..
+        } = mapper.translate(page.start_address())
+        {
+            let mut merged = existing | flags;
+            if !(existing & flags).contains(PageTableFlags::NO_EXECUTE) {
+                merged.remove(PageTableFlags::NO_EXECUTE);
+            }
+            unsafe {
+                mapper
+                    .update_flags(page, merged)
+                    .map_err(|_| ElfError::MapFailed)?
+                    .ignore();
+            }
+            continue;
+        }
+
+        let frame = frame_allocator
+            .allocate_frame()
+            .ok_or(ElfError::OutOfMemory)?;
+        unsafe {
+            core::ptr::write_bytes(
+                memory::frame_to_virt(frame).as_mut_ptr::<u8>(),
+                0,
+                page.size() as usize,
+            );
+            // 该地址空间尚未激活，不需要刷新 TLB
+            match mapper.map_to(page, frame, flags, frame_allocator) {
+                Ok(flush) => flush.ignore(),
+                Err(_) => {
+                    // 帧还没有挂进页表，销毁地址空间时不会归还它
+                    frame_allocator.deallocate_frame(frame);
+                    return Err(ElfError::MapFailed);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 通过物理内存映射把数据写入（可能未激活的）地址空间
+fn write_user_bytes(
+    mapper: &OffsetPageTable,
+    mut address: VirtAddr,
+    mut bytes: &[u8],
+) -> Result<(), ElfError> {
+    while !bytes.is_empty() {
+        let frame = match mapper.translate(address) {
+            TranslateResult::Mapped {
+                frame: MappedFrame::Size4KiB(frame),
+                ..
+            } => frame,
+            _ => return Err(ElfError::MapFailed),
+        };
+        let page_offset = u64::from(address.page_offset());
+        let chunk_len = bytes.len().min((4096 - page_offset) as usize);
+        unsafe {
+            core::ptr::copy_nonoverlapping(
+                bytes.as_ptr(),
+                (memory::frame_to_virt(frame) + page_offset).as_mut_ptr::<u8>(),
+                chunk_len,
+            );
+        }
+        address += chunk_len as u64;
+        bytes = &bytes[chunk_len..];
+    }
+    Ok(())
+}
+
+/// 映射用户栈并写入初始内容，返回入口点应使用的栈指针
+///
+/// 布局（由低到高）：`argc`、`argv[]`、NULL、`envp[]`、NULL、`AT_NULL` 辅助向量、字符串。
+fn setup_user_stack<A>(
+    address_space: &mut AddressSpace,
+    frame_allocator: &mut A,
+    argv: &[&str],
+    envp: &[&str],
+) -> Result<VirtAddr, ElfError>
+where
+    A: FrameAllocator<Size4KiB> + FrameDeallocator<Size4KiB>,
+{
+    let stack_size = USER_STACK_PAGES * 4096;
+    let stack_bottom = VirtAddr::new(USER_STACK_TOP - stack_size);
+
+    let strings_size: u64 = argv
+        .iter()
+        .chain(envp.iter())
+        .map(|s| s.len() as u64 + 1)
+        .sum();
+    let strings_start = (USER_STACK_TOP - strings_size) & !0xf;
+    let word_count = 1 + (argv.len() + 1) + (envp.len() + 1) + 2;
+    let stack_pointer = (strings_start - word_count as u64 * 8) & !0xf;
+    let image_size = USER_STACK_TOP - stack_pointer;
+    if image_size > stack_size {
+        return Err(ElfError::ArgumentsTooLarge);
+    }
+
+    let mut image = vec![0u8; image_size as usize];
+    let mut words = Vec::with_capacity(word_count);
+    let mut string_address = strings_start;
+    words.push(argv.len() as u64);
+    for strings in [argv, envp] {
+        for s in strings {
+            let offset = (string_address - stack_pointer) as usize;
+            image[offset..offset + s.len()].copy_from_slice(s.as_bytes());
+            words.push(string_address);
+            string_address += s.len() as u64 + 1;
+        }
+        words.push(0);
+    }
+    // 辅助向量只有 AT_NULL 结束项
+    words.extend_from_slice(&[0, 0]);
+    for (index, word) in words.iter().enumerate() {
+        image[index * 8..index * 8 + 8].copy_from_slice(&word.to_le_bytes());
+    }
+
+    let mut mapper = address_space.mapper();
+    let pages = Page::range(
+        Page::containing_address(stack_bottom),
+        Page::containing_address(VirtAddr::new(USER_STACK_TOP)),
+    );
+    let flags = PageTableFlags::PRESENT
+        | PageTableFlags::USER_ACCESSIBLE
+        | PageTableFlags::WRITABLE
+        | PageTableFlags::NO_EXECUTE;
+    map_user_pages(&mut mapper, frame_allocator, pages, flags)?;
+    write_user_bytes(&mapper, VirtAddr::new(stack_pointer), &image)?;
+
+    Ok(VirtAddr::new(stack_pointer))
+}
+
+/// 装入并运行 ELF 程序，返回其退出码
+///
+/// 程序运行期间 CR3 指向它自己的地址空间，退出后恢复原页表并归还地址空间占用的全部帧。
+pub fn run<A>(
+    image: &[u8],
+    argv: &[&str],
+    envp: &[&str],
+    frame_allocator: &mut A,
+) -> Result<u64, ElfError>
+where
+    A: FrameAllocator<Size4KiB> + FrameDeallocator<Size4KiB>,
+{
+    use x86_64::registers::control::{Cr3, Cr3Flags};
+
+    let program = load(image, argv, envp, frame_allocator)?;
+    let exit_code = unsafe {
+        let previous = program.address_space.activate();
+        let exit_code = usermode::enter_user_mode(program.entry, program.stack_pointer);
+        Cr3::write(previous, Cr3Flags::empty());
+        program.address_space.destroy(frame_allocator);
+        exit_code
+    };
+    Ok(exit_code)
+}
@@ -1,3 +1,4 @@
+use core::ptr::{addr_of, addr_of_mut};
 use lazy_static::lazy_static;
 use x86_64::structures::gdt::SegmentSelector;
 use x86_64::structures::gdt::{Descriptor, GlobalDescriptorTable};
@@ -6,28 +7,25 @@ use x86_64::VirtAddr;
 
 pub const DOUBLE_FAULT_IST_INDEX: u16 = 0;
 
-lazy_static! {
-    static ref TSS: TaskStateSegment = {
-        let mut tss = TaskStateSegment::new();
-        tss.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX as usize] = {
-            const STACK_SIZE: usize = 4096 * 5;
-            static mut STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
-
-            let stack_start = VirtAddr::from_ptr(core::ptr::addr_of!(STACK));
-            let stack_end = stack_start + STACK_SIZE;
-            // 将栈的高地址指针写入0号位，之所以这样做，
-            // 是因为 x86 的栈内存分配是从高地址到低地址的。
-            stack_end
-        };
-        tss
-    };
-}
+const DOUBLE_FAULT_STACK_SIZE: usize = 4096 * 5;
+// 从 ring 3 陷入 ring 0（中断或 syscall）时使用的内核栈
+const PRIVILEGE_STACK_SIZE: usize = 4096 * 5;
+
+static mut DOUBLE_FAULT_STACK: [u8; DOUBLE_FAULT_STACK_SIZE] = [0; DOUBLE_FAULT_STACK_SIZE];
+static mut PRIVILEGE_STACK: [u8; PRIVILEGE_STACK_SIZE] = [0; PRIVILEGE_STACK_SIZE];
+
+// TSS 需要在运行时更新 RSP0（切换用户态上下文时），因此不再放在 lazy_static 中，
+// GDT 中的描述符只引用它的地址。
+static mut TSS: TaskStateSegment = TaskStateSegment::new();
 
 // 实际上gdt成功加载后，还是会出现栈溢出，这是因为
 // GDT并未被激活，代码段寄存器和TSS实际上依然引用着旧的GDT
 // 需要：重载代码段寄存器、加载TSS、更新IDT条目
 struct Selectors {
     code_selector: SegmentSelector,
+    data_selector: SegmentSelector,
+    user_data_selector: SegmentSelector,
+    user_code_selector: SegmentSelector,
     tss_selector: SegmentSelector,
 }
 
@@ -35,21 +33,117 @@ lazy_static! {
     static ref GDT: (GlobalDescriptorTable, Selectors) = {
         let mut gdt = GlobalDescriptorTable::new();
         let code_selector = gdt.add_entry(Descriptor::kernel_code_segment());
-        let tss_selector = gdt.add_entry(Descriptor::tss_segment(&TSS));
-        // gdt.add_entry(Descriptor::kernel_code_segment());
-        // gdt.add_entry(Descriptor::tss_segment(&TSS));
-        //gdt
-        (gdt, Selectors { code_selector, tss_selector })
+        let data_selector = gdt.add_entry(Descriptor::kernel_data_segment());
+        // sysret 按 STAR 中的基址计算用户段：SS = 基址 + 8，CS = 基址 + 16，
+        // 所以用户数据段必须紧挨着放在用户代码段之前。
+        let user_data_selector = gdt.add_entry(Descriptor::user_data_segment());
+        let user_code_selector = gdt.add_entry(Descriptor::user_code_segment());
+        let tss_selector = gdt.add_entry(Descriptor::tss_segment(unsafe { &*addr_of!(TSS) }));
+        (
+            gdt,
+            Selectors {
+                code_selector,
+                data_selector,
+                user_data_selector,
+                user_code_selector,
+                tss_selector,
+            },
+        )
     };
 }
 
+fn stack_top(stack_start: VirtAddr, stack_size: usize) -> VirtAddr {
+    // x86 的栈内存分配是从高地址到低地址的，所以返回栈的高地址
+    stack_start + stack_size
+}
+
 pub fn init() {
-    use x86_64::instructions::segmentation::{Segment, CS};
+    use x86_64::instructions::segmentation::{Segment, CS, SS};
     use x86_64::instructions::tables::load_tss;
 
+    unsafe {
+        let tss = &mut *addr_of_mut!(TSS);
+        tss.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX as usize] = stack_top(
+            VirtAddr::from_ptr(addr_of!(DOUBLE_FAULT_STACK)),
+            DOUBLE_FAULT_STACK_SIZE,
+        );
+        tss.privilege_stack_table[0] = default_privilege_stack();
+    }
+
     GDT.0.load();
     unsafe {
         CS::set_reg(GDT.1.code_selector);
+        SS::set_reg(GDT.1.data_selector);
         load_tss(GDT.1.tss_selector);
     }
 }
+
+/// 为 AP（应用处理器）创建并加载独立的 GDT 和 TSS
+///
+/// 段的布局与启动处理器相同，因此各 CPU 上的选择子取值一致；
+/// TSS 在加载时会被标记为忙，所以每个 CPU 必须有自己的一份。
+pub fn init_ap(double_fault_stack_top: VirtAddr, privilege_stack_top: VirtAddr) {
+    use alloc::boxed::Box;
+    use x86_64::instructions::segmentation::{Segment, CS, SS};
+    use x86_64::instructions::tables::load_tss;
+
+    let tss: &'static mut TaskStateSegment = Box::leak(Box::new(TaskStateSegment::new()));
+    tss.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX as usize] = double_fault_stack_top;
+    tss.privilege_stack_table[0] = privilege_stack_top;
+
+    let gdt: &'static mut GlobalDescriptorTable = Box::leak(Box::new(GlobalDescriptorTable::new()));
+    let code_selector = gdt.add_entry(Descriptor::kernel_code_segment());
+    let data_selector = gdt.add_entry(Descriptor::kernel_data_segment());
+    gdt.add_entry(Descriptor::user_data_segment());
+    gdt.add_entry(Descriptor::user_code_segment());
+    let tss_selector = gdt.add_entry(Descriptor::tss_segment(tss));
+    debug_assert_eq!(code_selector, GDT.1.code_selector);
+
+    gdt.load();
+    unsafe {
+        CS::set_reg(code_selector);
+        SS::set_reg(data_selector);
+        load_tss(tss_selector);
+    }
+}
+
+/// 内核代码段选择子
+pub fn kernel_code_selector() -> SegmentSelector {
+    GDT.1.code_selector
+}
+
+/// 内核数据段选择子
+pub fn kernel_data_selector() -> SegmentSelector {
+    GDT.1.data_selector
+}
+
+/// 用户代码段选择子（RPL = 3）
+pub fn user_code_selector() -> SegmentSelector {
+    GDT.1.user_code_selector
+}
+
+/// 用户数据段选择子（RPL = 3）
+pub fn user_data_selector() -> SegmentSelector {
+    GDT.1.user_data_selector
+}
+
+/// 当前 TSS 中的 RSP0，即从 ring 3 陷入内核时使用的栈顶
+pub fn privilege_stack() -> VirtAddr {
+    unsafe { (*addr_of!(TSS)).privilege_stack_table[0] }
+}
+
+/// 内核启动时设置的 RSP0，不属于任何进程的用户程序使用这块栈
+pub fn default_privilege_stack() -> VirtAddr {
+    stack_top(
+        VirtAddr::from_ptr(addr_of!(PRIVILEGE_STACK)),
+        PRIVILEGE_STACK_SIZE,
+    )
+}
+
+/// 更新 TSS 中的 RSP0
+///
+/// # Safety
+/// 调用者必须保证 `stack_top` 指向一块足够大、且在用户态运行期间不会被其他代码使用的内核栈。
+pub unsafe fn set_privilege_stack(stack_top: VirtAddr) {
+    (*addr_of_mut!(TSS)).privilege_stack_table[0] = stack_top;
+}
@@ -27,6 +27,12 @@ lazy_static! {
         idt[InterruptIndex::Keyboard.as_usize()]
             .set_handler_fn(keyboard_interrupt_handler);
 
+        idt[usize::from(crate::apic::SPURIOUS_VECTOR)]
+            .set_handler_fn(spurious_interrupt_handler);
+
+        idt[usize::from(crate::apic::TIMER_VECTOR)]
+            .set_handler_fn(apic_timer_interrupt_handler);
+
         idt.page_fault.set_handler_fn(page_fault_handler);
 
         idt
@@ -86,16 +92,69 @@ extern "x86-interrupt" fn keyboard_interrupt_handler(_stack_frame: InterruptStac
     }
 }
 
-extern "x86-interrupt" fn timer_interrupt_handler(_stack_frame: InterruptStackFrame) {
+extern "x86-interrupt" fn timer_interrupt_handler(stack_frame: InterruptStackFrame) {
+    // 推进时间轮并唤醒到期的睡眠者必须留在中断中：工作者任务运行在某个执行器上，
+    // 该执行器在 `block_on` 中等待定时器，或者根本没有派生工作者时，推迟的唤醒永远不会发生；
+    // 无滴答空闲也依赖停机前时间轮已经推进。这部分工作有界（每批最多 32 个唤醒器，唤醒只是入队）
     crate::task::timer::tick();
 
+    // 打印需要获取 VGA 缓冲区的锁，推迟到工作队列中执行
     #[cfg(feature = "debug-timer-ticks")]
-    crate::print!(".");
+    crate::task::workqueue::SYSTEM.queue(crate::task::workqueue::Work::new(print_tick, 0));
 
     unsafe {
         PICS.lock()
             .notify_end_of_interrupt(InterruptIndex::Timer.as_u8());
     }
+
+    // 看门狗可能向串口输出报告，放在 EOI 之后、切换线程之前
+    crate::task::watchdog::on_timer_tick(stack_frame.instruction_pointer.as_u64());
+
+    // 可能切换到其他线程，所以放在 EOI 之后
+    crate::thread::on_timer_tick();
+}
+
+#[cfg(feature = "debug-timer-ticks")]
+fn print_tick(_: usize) {
+    crate::print!(".");
+}
+
+// 本地 APIC 的伪中断不需要 EOI
+extern "x86-interrupt" fn spurious_interrupt_handler(_stack_frame: InterruptStackFrame) {}
+
+// 单次定时只用于把 CPU 从空闲停机中唤醒，补 tick 的工作由空闲路径完成
+extern "x86-interrupt" fn apic_timer_interrupt_handler(_stack_frame: InterruptStackFrame) {
+    crate::apic::end_of_interrupt();
+}
+
+/// 屏蔽或恢复 PIT 时钟中断（主 PIC 的 0 号管脚）
+///
+/// 时钟中断处理程序发送 EOI 时也要获取 `PICS`，持锁期间关中断，以免死锁。
+pub(crate) fn set_timer_masked(masked: bool) {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let mut pics = PICS.lock();
+        unsafe {
+            let [primary, secondary] = pics.read_masks();
+            let primary = if masked { primary | 1 } else { primary & !1 };
+            pics.write_masks(primary, secondary);
+        }
+    });
+}
+
+/// PIT 时钟中断是否已经请求、尚未被处理（读取主 PIC 的 IRR）
+pub(crate) fn timer_interrupt_pending() -> bool {
+    use x86_64::instructions::port::Port;
+
+    // OCW3：下一次读命令端口得到中断请求寄存器
+    const READ_IRR: u8 = 0x0a;
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let _pics = PICS.lock();
+        let mut command: Port<u8> = Port::new(0x20);
+        unsafe {
+            command.write(READ_IRR);
+            command.read() & 1 != 0
+        }
+    })
 }
 
 extern "x86-interrupt" fn double_fault_handler(
@@ -5,9 +5,11 @@
 //! ## 主要特性
 //! - 基本的内存管理（分页、堆分配）
 //! - 中断处理（键盘、定时器）
-//! - 异步任务系统
+//! - 多处理器启动与每 CPU 数据
+//! - 异步任务系统与可抢占的内核线程
 //! - VGA文本模式输出
 //! - 串口通信
+//! - 用户态程序、进程与系统调用
 
 #![no_std]
 #![cfg_attr(test, no_main)]
@@ -17,8 +19,14 @@
 #![feature(abi_x86_interrupt)] // 用于 interrupt.rs
 #![feature(const_mut_refs)]
 
+/// ACPI 表解析（MADT）
+pub mod acpi;
 /// 堆内存分配器
 pub mod allocator;
+/// 本地 APIC
+pub mod apic;
+/// ELF64 程序加载器
+pub mod elf;
 /// 全局描述符表(GDT)和任务状态段(TSS)管理
 pub mod gdt;
 /// 输入子系统适配层
@@ -27,11 +35,21 @@ pub mod input;
 pub mod interrupts;
 /// 内存管理：分页、物理内存分配
 pub mod memory;
+/// 进程：独立地址空间、句柄表与父子关系
+pub mod process;
 /// 串口通信
 pub mod serial;
+/// 多处理器启动与每 CPU 数据
+pub mod smp;
+/// 系统调用入口与分发
+pub mod syscall;
 /// 异步任务系统
 pub mod task;
 mod testing;
+/// 可抢占的内核线程
+pub mod thread;
+/// 用户态（ring 3）程序的进入与返回
+pub mod usermode;
 /// VGA文本缓冲区
 pub mod vga_buffer;
 
@@ -50,6 +68,7 @@ pub fn hlt_loop() -> ! {
 // 封装一个加载gdt和idt的函数
 pub fn init() {
     gdt::init();
+    syscall::init();
     interrupts::init_idt();
     unsafe { interrupts::PICS.lock().initialize() };
     // x86_64 crate 中的 interrupts::enable 会执行特殊的 sti ("set interrupts") 指令来启用外部中断
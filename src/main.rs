@@ -9,8 +9,8 @@ extern crate alloc;
 use bootloader::{entry_point, BootInfo};
 use core::panic::PanicInfo;
 use os_by_rust::println;
-use os_by_rust::task::executor::Executor;
-use os_by_rust::task::{keyboard, sleep_ticks, Task, TaskPriority};
+use os_by_rust::task::executor::{self, Executor};
+use os_by_rust::task::{interval, keyboard, workqueue, Task, TaskPriority};
 
 // 确保入口点函数总是具有引导程序所期望的正确签名
 entry_point!(kernel_main);
@@ -33,24 +33,38 @@ fn kernel_main(boot_info: &'static BootInfo) -> ! {
     //let mut frame_allocator = memory::EmptyFrameAllocator;
 
     allocator::init_heap(&mut mapper, &mut frame_allocator).expect("heap initialization failed");
+    match os_by_rust::smp::init(&mut mapper, &mut frame_allocator) {
+        Ok(cpus) => println!("{} CPU(s) online", cpus),
+        Err(error) => println!("SMP bring-up failed: {:?}", error),
+    }
+    match os_by_rust::task::timer::enable_tickless() {
+        Ok(counts_per_tick) => println!("tickless idle: {} APIC counts per tick", counts_per_tick),
+        Err(error) => println!("tickless idle unavailable: {}", error),
+    }
+    memory::init_frame_allocator(frame_allocator);
 
     //let mut executor = SimpleExecutor::new();
     let mut executor = Executor::new();
     executor
-        .try_spawn(Task::new_with_priority(example_task(), TaskPriority::Normal))
+        .try_spawn(Task::new_with_priority(example_task(), TaskPriority::Normal).named("example"))
         .expect("failed to spawn example task");
     executor
-        .try_spawn(Task::new_with_priority(
-            keyboard::print_keypresses(),
-            TaskPriority::High,
-        ))
+        .try_spawn(
+            Task::new_with_priority(keyboard::print_keypresses(), TaskPriority::High)
+                .named("keyboard"),
+        )
         .expect("failed to spawn keyboard task");
     executor
-        .try_spawn(Task::new_with_priority(
-            diagnostics_task(),
-            TaskPriority::Normal,
-        ))
+        .try_spawn(
+            Task::new_with_priority(diagnostics_task(), TaskPriority::Normal).named("diagnostics"),
+        )
         .expect("failed to spawn diagnostics task");
+    executor
+        .try_spawn(
+            Task::new_with_priority(workqueue::SYSTEM.worker(), TaskPriority::High)
+                .named("kworker"),
+        )
+        .expect("failed to spawn work queue worker");
     #[cfg(test)]
     test_main();
 
@@ -67,19 +81,20 @@ async fn example_task() {
 }
 
 async fn diagnostics_task() {
+    let mut panel_interval = interval(200);
     loop {
-        sleep_ticks(200).await;
+        panel_interval.tick().await;
         #[cfg(feature = "diagnostic-panel")]
         {
             let stats = os_by_rust::task::executor::global_stats_snapshot();
             os_by_rust::serial_println!(
-                "[panel] tick={} active={} q_high={} q_normal={} wakers={} dropped_wakes={} dropped_scan={} uninit_scan={}",
+                "[panel] tick={} active={} queued={:?} wakers={} dropped_wakes={} p99_cycles={:?} dropped_scan={} uninit_scan={}",
                 os_by_rust::task::timer::current_tick(),
                 stats.active_tasks,
-                stats.queued_high_tasks,
-                stats.queued_normal_tasks,
+                stats.queued_tasks,
                 stats.cached_wakers,
                 stats.dropped_wakes,
+                stats.wake_latency.map(|latency| latency.percentile(99)),
                 os_by_rust::input::dropped_scancode_count(),
                 os_by_rust::input::uninitialized_scancode_count(),
             );
@@ -97,6 +112,7 @@ test
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
     println!("{}", info);
+    executor::dump_trace(executor::TraceFormat::Text);
     os_by_rust::hlt_loop();
     //loop {}
 }
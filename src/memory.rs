@@ -1,9 +1,16 @@
 use bootloader::bootinfo::{MemoryMap, MemoryRegionType};
+use core::sync::atomic::{AtomicU64, Ordering};
+use spin::Mutex;
+use x86_64::structures::paging::page_table::PageTableEntry;
 use x86_64::structures::paging::{
-    FrameAllocator, Mapper, OffsetPageTable, Page, PageTable, PhysFrame, Size4KiB,
+    FrameAllocator, FrameDeallocator, Mapper, OffsetPageTable, Page, PageTable, PhysFrame, Size4KiB,
 };
 use x86_64::{PhysAddr, VirtAddr};
 
+static PHYSICAL_MEMORY_OFFSET: AtomicU64 = AtomicU64::new(0);
+
+static FRAME_ALLOCATOR: Mutex<Option<BootInfoFrameAllocator>> = Mutex::new(None);
+
 // 返回一个对活动的4级表的可变引用。
 // 这个函数是不安全的，因为调用者必须保证完整的物理内存在传递的
 // `physical_memory_offset`处被映射到虚拟内存。另外，这个函数
@@ -58,10 +65,129 @@ fn translate_addr_inner(addr: VirtAddr, physical_memory_offset: VirtAddr) -> Opt
 }
 
 pub unsafe fn init(physical_memory_offset: VirtAddr) -> OffsetPageTable<'static> {
+    PHYSICAL_MEMORY_OFFSET.store(physical_memory_offset.as_u64(), Ordering::Relaxed);
     let level_4_table = active_level_4_table(physical_memory_offset);
     OffsetPageTable::new(level_4_table, physical_memory_offset)
 }
 
+/// 完整物理内存在虚拟地址空间中的映射起点，`init` 之前为0
+pub fn physical_memory_offset() -> VirtAddr {
+    VirtAddr::new(PHYSICAL_MEMORY_OFFSET.load(Ordering::Relaxed))
+}
+
+/// 返回物理帧在物理内存映射中的虚拟地址
+pub fn frame_to_virt(frame: PhysFrame) -> VirtAddr {
+    physical_memory_offset() + frame.start_address().as_u64()
+}
+
+// 用户地址空间在4级页表中占用的表项范围
+const USER_LEVEL_4_ENTRIES: core::ops::Range<usize> = {
+    use crate::usermode::{USER_SPACE_END, USER_SPACE_START};
+    (USER_SPACE_START >> 39) as usize..(USER_SPACE_END >> 39) as usize
+};
+
+/// 一个独立的地址空间（4级页表）
+///
+/// 内核使用的4级表项在创建时从当前页表复制，因此切换过去后内核代码、栈和堆仍然可用；
+/// 用户地址空间对应的表项则各自独立。
+pub struct AddressSpace {
+    level_4_frame: PhysFrame,
+}
+
+impl AddressSpace {
+    /// 基于当前活动页表创建新的地址空间
+    pub fn new(frame_allocator: &mut impl FrameAllocator<Size4KiB>) -> Option<Self> {
+        use x86_64::registers::control::Cr3;
+
+        let level_4_frame = frame_allocator.allocate_frame()?;
+        let (active_frame, _) = Cr3::read();
+
+        let new_table = unsafe { &mut *frame_to_virt(level_4_frame).as_mut_ptr::<PageTable>() };
+        let active_table = unsafe { &*frame_to_virt(active_frame).as_ptr::<PageTable>() };
+        new_table.zero();
+        for (index, entry) in active_table.iter().enumerate() {
+            if !USER_LEVEL_4_ENTRIES.contains(&index) {
+                new_table[index] = entry.clone();
+            }
+        }
+
+        Some(AddressSpace { level_4_frame })
+    }
+
+    /// 4级页表所在的物理帧
+    pub fn level_4_frame(&self) -> PhysFrame {
+        self.level_4_frame
+    }
+
+    /// 返回用于修改该地址空间映射的页表访问器
+    pub fn mapper(&mut self) -> OffsetPageTable<'_> {
+        let level_4_table = unsafe { &mut *frame_to_virt(self.level_4_frame).as_mut_ptr() };
+        unsafe { OffsetPageTable::new(level_4_table, physical_memory_offset()) }
+    }
+
+    /// 把该地址空间加载到 CR3，返回之前的4级页表帧
+    ///
+    /// # Safety
+    /// 调用者必须保证当前正在执行的代码和使用的栈在新地址空间中同样有效。
+    pub unsafe fn activate(&self) -> PhysFrame {
+        use x86_64::registers::control::{Cr3, Cr3Flags};
+
+        let (previous, _) = Cr3::read();
+        Cr3::write(self.level_4_frame, Cr3Flags::empty());
+        previous
+    }
+
+    /// 释放用户地址空间中映射的所有帧、各级页表以及4级页表本身
+    ///
+    /// 内核部分的表项与其他地址空间共享，不会被释放。
+    ///
+    /// # Safety
+    /// 该地址空间不能处于活动状态，用户部分映射的帧也不能被其他地址空间共享。
+    pub unsafe fn destroy(self, frame_deallocator: &mut impl FrameDeallocator<Size4KiB>) {
+        let level_4_table = &*frame_to_virt(self.level_4_frame).as_ptr::<PageTable>();
+        for index in USER_LEVEL_4_ENTRIES {
+            free_table_entry(&level_4_table[index], 3, frame_deallocator);
+        }
+        frame_deallocator.deallocate_frame(self.level_4_frame);
+    }
+}
+
+// 递归释放表项指向的帧；`level` 是该帧作为页表时的级别，0 表示普通数据帧
+unsafe fn free_table_entry(
+    entry: &PageTableEntry,
+    level: u8,
+    frame_deallocator: &mut impl FrameDeallocator<Size4KiB>,
+) {
+    // 用户地址空间不使用大页，`frame` 只会在表项为空时出错
+    let frame = match entry.frame() {
+        Ok(frame) => frame,
+        Err(_) => return,
+    };
+    if level > 0 {
+        let table = &*frame_to_virt(frame).as_ptr::<PageTable>();
+        for child in table.iter() {
+            free_table_entry(child, level - 1, frame_deallocator);
+        }
+    }
+    frame_deallocator.deallocate_frame(frame);
+}
+
+/// 把物理帧分配器交给内核统一管理，进程的创建与回收都从这里分配和归还帧
+pub fn init_frame_allocator(frame_allocator: BootInfoFrameAllocator) {
+    *FRAME_ALLOCATOR.lock() = Some(frame_allocator);
+}
+
+/// 在持有全局帧分配器的情况下执行 `f`
+///
+/// # Panics
+/// 如果还没有调用 [`init_frame_allocator`]。
+pub fn with_frame_allocator<R>(f: impl FnOnce(&mut BootInfoFrameAllocator) -> R) -> R {
+    let mut frame_allocator = FRAME_ALLOCATOR.lock();
+    f(frame_allocator
+        .as_mut()
+        .expect("frame allocator is not initialized"))
+}
+
 pub struct EmptyFrameAllocator;
 
 unsafe impl FrameAllocator<Size4KiB> for EmptyFrameAllocator {
@@ -87,9 +213,15 @@ pub fn create_example_mapping(
     map_to_result.expect("map_to failed").flush();
 }
 
+// 空闲链表的结束标记，不是合法的物理地址
+const FREE_LIST_END: u64 = u64::MAX;
+
 pub struct BootInfoFrameAllocator {
     memory_map: &'static MemoryMap,
     next: usize,
+    // 归还的帧组成单链表，每个帧的前8字节保存下一个空闲帧的物理地址
+    free_list: u64,
+    free_count: usize,
 }
 
 impl BootInfoFrameAllocator {
@@ -98,9 +230,16 @@ impl BootInfoFrameAllocator {
         BootInfoFrameAllocator {
             memory_map,
             next: 0,
+            free_list: FREE_LIST_END,
+            free_count: 0,
         }
     }
 
+    /// 当前已分配且尚未归还的帧数
+    pub fn allocated_frames(&self) -> usize {
+        self.next - self.free_count
+    }
+
     // 返回内存映射中指定的可用框架的迭代器
     // Bootloader会对所有可用的内存区域进行页对齐
     fn usable_frames(&self) -> impl Iterator<Item = PhysFrame> {
@@ -121,9 +260,24 @@ impl BootInfoFrameAllocator {
 
 unsafe impl FrameAllocator<Size4KiB> for BootInfoFrameAllocator {
     fn allocate_frame(&mut self) -> Option<PhysFrame> {
-        let frame = self.usable_frames().nth(self.next);
+        // 优先复用归还的帧
+        if self.free_list != FREE_LIST_END {
+            let frame = PhysFrame::containing_address(PhysAddr::new(self.free_list));
+            self.free_list = unsafe { *frame_to_virt(frame).as_ptr::<u64>() };
+            self.free_count -= 1;
+            return Some(frame);
+        }
+        let frame = self.usable_frames().nth(self.next)?;
         self.next += 1;
-        frame
+        Some(frame)
+    }
+}
+
+impl FrameDeallocator<Size4KiB> for BootInfoFrameAllocator {
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame) {
+        *frame_to_virt(frame).as_mut_ptr::<u64>() = self.free_list;
+        self.free_list = frame.start_address().as_u64();
+        self.free_count += 1;
     }
 }
 
@@ -0,0 +1,312 @@
+//! 进程抽象。
+//!
+//! 每个进程拥有自己的4级页表（内核部分与其他地址空间共享）、内核栈、句柄表和父子关系。
+//! 进程由执行器中的异步任务驱动：[`run`] 切换到进程的地址空间执行用户代码，
+//! 进程调用 `yield` 或 `sleep` 时任务随之挂起，让其他任务和进程运行；
+//! 进程退出后，地址空间占用的帧全部归还给全局帧分配器。
+
+use crate::elf::{self, ElfError};
+use crate::memory::{self, AddressSpace};
+use crate::task::{sleep_ticks, yield_now};
+use crate::usermode::{self, UserContext, UserExit};
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU64, Ordering};
+use spin::{Mutex, MutexGuard};
+use x86_64::VirtAddr;
+
+const KERNEL_STACK_SIZE: usize = 4096 * 4;
+
+static PROCESSES: Mutex<BTreeMap<Pid, Arc<Process>>> = Mutex::new(BTreeMap::new());
+// 正在用户态运行的进程，0 表示没有
+static CURRENT_PID: AtomicU64 = AtomicU64::new(0);
+
+/// 进程标识符，从1开始分配
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Pid(u64);
+
+impl Pid {
+    fn new() -> Pid {
+        static NEXT_PID: AtomicU64 = AtomicU64::new(1);
+        Pid(NEXT_PID.fetch_add(1, Ordering::Relaxed))
+    }
+
+    pub fn as_u64(self) -> u64 {
+        self.0
+    }
+}
+
+/// 文件描述符指向的内核对象
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Handle {
+    /// 控制台（VGA 文本缓冲区）
+    Console,
+}
+
+/// 进程的句柄表，文件描述符即表中的下标
+#[derive(Debug, Clone, Default)]
+pub struct HandleTable {
+    entries: Vec<Option<Handle>>,
+}
+
+impl HandleTable {
+    /// 空的句柄表
+    pub fn new() -> Self {
+        HandleTable::default()
+    }
+
+    /// 标准输入、标准输出和标准错误都指向控制台的句柄表
+    pub fn with_standard_streams() -> Self {
+        HandleTable {
+            entries: vec![Some(Handle::Console); 3],
+        }
+    }
+
+    /// 在最小的空闲描述符上打开 `handle`
+    pub fn open(&mut self, handle: Handle) -> u64 {
+        match self.entries.iter().position(Option::is_none) {
+            Some(index) => {
+                self.entries[index] = Some(handle);
+                index as u64
+            }
+            None => {
+                self.entries.push(Some(handle));
+                (self.entries.len() - 1) as u64
+            }
+        }
+    }
+
+    pub fn get(&self, fd: u64) -> Option<Handle> {
+        self.entries.get(fd as usize).copied().flatten()
+    }
+
+    /// 关闭描述符，返回它原来指向的句柄
+    pub fn close(&mut self, fd: u64) -> Option<Handle> {
+        self.entries.get_mut(fd as usize)?.take()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProcessState {
+    /// 可以继续执行（包括正在运行、让出或休眠中）
+    Runnable,
+    /// 已经退出，等待父进程回收退出码
+    Exited(u64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProcessError {
+    Load(ElfError),
+    /// 指定的父进程不存在或已经退出
+    ParentNotFound,
+}
+
+impl From<ElfError> for ProcessError {
+    fn from(error: ElfError) -> Self {
+        ProcessError::Load(error)
+    }
+}
+
+/// 一个用户进程
+pub struct Process {
+    pid: Pid,
+    parent: Mutex<Option<Pid>>,
+    children: Mutex<Vec<Pid>>,
+    state: Mutex<ProcessState>,
+    // 退出后地址空间被回收，这里变为 None
+    address_space: Mutex<Option<AddressSpace>>,
+    context: Mutex<UserContext>,
+    kernel_stack: Vec<u8>,
+    handles: Mutex<HandleTable>,
+}
+
+impl Process {
+    pub fn pid(&self) -> Pid {
+        self.pid
+    }
+
+    /// 父进程；父进程先退出后变为 None
+    pub fn parent(&self) -> Option<Pid> {
+        *self.parent.lock()
+    }
+
+    /// 尚未被回收的子进程
+    pub fn children(&self) -> Vec<Pid> {
+        self.children.lock().clone()
+    }
+
+    pub fn state(&self) -> ProcessState {
+        *self.state.lock()
+    }
+
+    pub fn handles(&self) -> MutexGuard<'_, HandleTable> {
+        self.handles.lock()
+    }
+
+    // 从 ring 3 陷入内核时使用的栈顶，按16字节对齐
+    fn kernel_stack_top(&self) -> VirtAddr {
+        let top = VirtAddr::from_ptr(self.kernel_stack.as_ptr()) + self.kernel_stack.len();
+        top.align_down(16u64)
+    }
+}
+
+/// 装入 ELF 程序并创建进程
+///
+/// 子进程继承父进程的句柄表；没有父进程时使用 [`HandleTable::with_standard_streams`]。
+/// 创建后进程不会自动运行，需要把 [`run`] 返回的 future 交给执行器。
+pub fn spawn(
+    image: &[u8],
+    argv: &[&str],
+    envp: &[&str],
+    parent: Option<Pid>,
+) -> Result<Pid, ProcessError> {
+    let parent_process = match parent {
+        Some(parent) => match get(parent) {
+            Some(process) if process.state() == ProcessState::Runnable => Some(process),
+            _ => return Err(ProcessError::ParentNotFound),
+        },
+        None => None,
+    };
+
+    let program = memory::with_frame_allocator(|frame_allocator| {
+        elf::load(image, argv, envp, frame_allocator)
+    })?;
+    let handles = match &parent_process {
+        Some(parent) => parent.handles().clone(),
+        None => HandleTable::with_standard_streams(),
+    };
+
+    let pid = Pid::new();
+    let process = Process {
+        pid,
+        parent: Mutex::new(parent),
+        children: Mutex::new(Vec::new()),
+        state: Mutex::new(ProcessState::Runnable),
+        address_space: Mutex::new(Some(program.address_space)),
+        context: Mutex::new(UserContext::new(program.entry, program.stack_pointer)),
+        kernel_stack: vec![0; KERNEL_STACK_SIZE],
+        handles: Mutex::new(handles),
+    };
+    PROCESSES.lock().insert(pid, Arc::new(process));
+    if let Some(parent) = parent_process {
+        parent.children.lock().push(pid);
+    }
+    Ok(pid)
+}
+
+/// 查找进程（包括已退出但尚未回收的进程）
+pub fn get(pid: Pid) -> Option<Arc<Process>> {
+    PROCESSES.lock().get(&pid).cloned()
+}
+
+/// 正在用户态运行的进程，只在系统调用处理中有意义
+pub fn current() -> Option<Arc<Process>> {
+    current_pid().and_then(get)
+}
+
+pub fn current_pid() -> Option<Pid> {
+    match CURRENT_PID.load(Ordering::Relaxed) {
+        0 => None,
+        pid => Some(Pid(pid)),
+    }
+}
+
+// 内核线程切换时恢复被切换线程的当前进程
+pub(crate) fn set_current_pid(pid: Option<Pid>) {
+    CURRENT_PID.store(pid.map_or(0, Pid::as_u64), Ordering::Relaxed);
+}
+
+/// 驱动进程执行直到退出，返回退出码；进程不存在时返回 None
+///
+/// 每个进程同一时间只能由一个 `run` 驱动。
+pub async fn run(pid: Pid) -> Option<u64> {
+    let process = get(pid)?;
+    if let ProcessState::Exited(code) = process.state() {
+        return Some(code);
+    }
+    loop {
+        match resume(&process) {
+            UserExit::Exited(code) => {
+                exit(&process, code);
+                return Some(code);
+            }
+            UserExit::Yielded => yield_now().await,
+            UserExit::Sleep(ticks) => sleep_ticks(ticks).await,
+        }
+    }
+}
+
+/// 回收已退出的子进程，返回其退出码
+///
+/// `child` 不是 `parent` 的子进程或尚未退出时返回 None。
+pub fn wait(parent: Pid, child: Pid) -> Option<u64> {
+    let mut processes = PROCESSES.lock();
+    let child_process = processes.get(&child)?;
+    if child_process.parent() != Some(parent) {
+        return None;
+    }
+    let code = match child_process.state() {
+        ProcessState::Exited(code) => code,
+        ProcessState::Runnable => return None,
+    };
+    processes.remove(&child);
+    if let Some(parent_process) = processes.get(&parent) {
+        parent_process.children.lock().retain(|&pid| pid != child);
+    }
+    Some(code)
+}
+
+// 切换到进程的地址空间和内核栈，执行到下一次交还控制权
+fn resume(process: &Process) -> UserExit {
+    use x86_64::registers::control::{Cr3, Cr3Flags};
+
+    let mut context = process.context.lock();
+    let address_space = process.address_space.lock();
+    let address_space = address_space
+        .as_ref()
+        .expect("cannot resume an exited process");
+
+    CURRENT_PID.store(process.pid.0, Ordering::Relaxed);
+    let exit = unsafe {
+        let previous = address_space.activate();
+        let exit = usermode::resume(&mut context, process.kernel_stack_top());
+        Cr3::write(previous, Cr3Flags::empty());
+        exit
+    };
+    CURRENT_PID.store(0, Ordering::Relaxed);
+    exit
+}
+
+// 记录退出码、回收地址空间，并处理父子关系
+fn exit(process: &Process, code: u64) {
+    *process.state.lock() = ProcessState::Exited(code);
+    if let Some(address_space) = process.address_space.lock().take() {
+        memory::with_frame_allocator(|frame_allocator| unsafe {
+            address_space.destroy(frame_allocator)
+        });
+    }
+
+    let mut processes = PROCESSES.lock();
+    // 已退出的子进程不会再有人回收，直接移除；仍在运行的子进程成为孤儿
+    for child in core::mem::take(&mut *process.children.lock()) {
+        let exited = match processes.get(&child) {
+            Some(child_process) => {
+                *child_process.parent.lock() = None;
+                child_process.state() != ProcessState::Runnable
+            }
+            None => false,
+        };
+        if exited {
+            processes.remove(&child);
+        }
+    }
+    // 没有父进程等待退出码时立即移除
+    let parent_alive = process
+        .parent()
+        .is_some_and(|parent| processes.contains_key(&parent));
+    if !parent_alive {
+        processes.remove(&process.pid);
+    }
+}
@@ -0,0 +1,369 @@
+//! 多处理器（SMP）启动与每 CPU 数据。
+//!
+//! 启动处理器（BSP）从 ACPI MADT 得到所有处理器的 APIC ID，把一段实模式跳板代码
+//! 复制到物理地址 [`AP_TRAMPOLINE_ADDRESS`]，再逐个向应用处理器（AP）发送 INIT-SIPI-SIPI。
+//! AP 在跳板中依次进入保护模式和长模式，使用内核页表跳转到 `ap_entry`，
+//! 在那里加载自己的 GDT/TSS、共享的 IDT，设置 GS 基址指向自己的 [`PerCpu`] 后报到。
+//!
+//! 目前只有 BSP 处理中断和运行任务，AP 报到后在关中断的 `hlt` 中待命。
+
+use crate::acpi::{self, AcpiError};
+use crate::{apic, gdt, interrupts, syscall};
+use alloc::boxed::Box;
+use core::arch::{asm, global_asm};
+use core::ptr::{addr_of, addr_of_mut};
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use x86_64::registers::model_specific::GsBase;
+use x86_64::structures::paging::{
+    FrameAllocator, Mapper, OffsetPageTable, PageTableFlags, PhysFrame, Size4KiB, Translate,
+};
+use x86_64::{PhysAddr, VirtAddr};
+
+/// 支持的最大 CPU 数（包括 BSP）
+pub const MAX_CPUS: usize = 8;
+
+/// AP 跳板代码所在的物理地址（也是恒等映射的虚拟地址），必须低于 1MiB 且按页对齐
+pub const AP_TRAMPOLINE_ADDRESS: u64 = 0x8000;
+
+const AP_STACK_SIZE: usize = 4096 * 4;
+const AP_DOUBLE_FAULT_STACK_SIZE: usize = 4096 * 2;
+// 等待 AP 报到的时钟 tick 数
+const AP_STARTUP_TIMEOUT_TICKS: u64 = 20;
+
+#[repr(C, align(16))]
+struct ApStacks {
+    kernel: [u8; AP_STACK_SIZE],
+    double_fault: [u8; AP_DOUBLE_FAULT_STACK_SIZE],
+}
+
+// 下标为 CPU 编号，0 号属于 BSP，不使用
+static mut AP_STACKS: [ApStacks; MAX_CPUS] = [const {
+    ApStacks {
+        kernel: [0; AP_STACK_SIZE],
+        double_fault: [0; AP_DOUBLE_FAULT_STACK_SIZE],
+    }
+}; MAX_CPUS];
+
+static ONLINE_CPUS: AtomicUsize = AtomicUsize::new(0);
+static CHECKED_IN: [AtomicBool; MAX_CPUS] = [const { AtomicBool::new(false) }; MAX_CPUS];
+static AP_APIC_IDS: [AtomicUsize; MAX_CPUS] = [const { AtomicUsize::new(0) }; MAX_CPUS];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SmpError {
+    Acpi(AcpiError),
+    /// 映射本地 APIC 或跳板页失败
+    MapFailed,
+    /// 某个 AP 在超时前没有报到，附带其 APIC ID
+    ApTimeout(u8),
+}
+
+impl From<AcpiError> for SmpError {
+    fn from(error: AcpiError) -> Self {
+        SmpError::Acpi(error)
+    }
+}
+
+/// 每个 CPU 独有的数据，通过 GS 基址访问
+#[repr(C)]
+pub struct PerCpu {
+    // 必须是第一个字段：`gs:[0]` 读出的就是结构体自身的地址
+    self_pointer: *const PerCpu,
+    cpu_index: usize,
+    apic_id: u8,
+}
+
+// 每个 PerCpu 只由所属 CPU 使用，创建后不再修改
+unsafe impl Sync for PerCpu {}
+
+impl PerCpu {
+    /// CPU 编号：BSP 为0，AP 按启动顺序从1开始
+    pub fn cpu_index(&self) -> usize {
+        self.cpu_index
+    }
+
+    pub fn apic_id(&self) -> u8 {
+        self.apic_id
+    }
+}
+
+// 为当前 CPU 创建 PerCpu 并写入 GS 基址
+fn init_per_cpu(cpu_index: usize, apic_id: u8) {
+    let per_cpu: &'static mut PerCpu = Box::leak(Box::new(PerCpu {
+        self_pointer: core::ptr::null(),
+        cpu_index,
+        apic_id,
+    }));
+    per_cpu.self_pointer = per_cpu;
+    GsBase::write(VirtAddr::from_ptr(per_cpu.self_pointer));
+}
+
+/// 当前 CPU 的 [`PerCpu`]；`smp::init` 之前（或 AP 尚未初始化时）返回 None
+pub fn current_cpu() -> Option<&'static PerCpu> {
+    if GsBase::read().is_null() {
+        return None;
+    }
+    let pointer: *const PerCpu;
+    unsafe {
+        asm!("mov {}, gs:[0]", out(reg) pointer, options(nostack, readonly, preserves_flags));
+        Some(&*pointer)
+    }
+}
+
+/// 已经上线的 CPU 数（包括 BSP）
+pub fn online_cpus() -> usize {
+    ONLINE_CPUS.load(Ordering::Acquire)
+}
+
+/// 编号为 `cpu_index` 的 CPU 是否已经报到
+pub fn is_online(cpu_index: usize) -> bool {
+    CHECKED_IN
+        .get(cpu_index)
+        .is_some_and(|checked_in| checked_in.load(Ordering::Acquire))
+}
+
+global_asm!(
+    ".section .rodata.ap_trampoline, \"a\"",
+    ".global ap_trampoline_start",
+    ".global ap_trampoline_end",
+    ".global ap_trampoline_cr3",
+    ".global ap_trampoline_stack",
+    ".global ap_trampoline_entry",
+    ".global ap_trampoline_argument",
+    ".code16",
+    "ap_trampoline_start:",
+    "cli",
+    "cld",
+    "xor ax, ax",
+    "mov ds, ax",
+    "lgdt [ap_gdt_pointer_address]",
+    "mov eax, cr0",
+    "or eax, 1",
+    "mov cr0, eax",
+    // ljmp 0x08:ap_protected_mode（32位偏移）
+    ".byte 0x66, 0xea",
+    ".long {base} + ap_protected_mode - ap_trampoline_start",
+    ".word 0x08",
+    ".code32",
+    "ap_protected_mode:",
+    "mov ax, 0x10",
+    "mov ds, ax",
+    "mov es, ax",
+    "mov ss, ax",
+    // CR4.PAE
+    "mov eax, cr4",
+    "or eax, 1 << 5",
+    "mov cr4, eax",
+    "mov eax, [ap_trampoline_cr3_address]",
+    "mov cr3, eax",
+    // EFER.LME | EFER.NXE：内核页表使用了 NO_EXECUTE 位
+    "mov ecx, 0xc0000080",
+    "rdmsr",
+    "or eax, (1 << 8) | (1 << 11)",
+    "wrmsr",
+    "mov eax, cr0",
+    "or eax, 1 << 31",
+    "mov cr0, eax",
+    // ljmp 0x18:ap_long_mode
+    ".byte 0xea",
+    ".long {base} + ap_long_mode - ap_trampoline_start",
+    ".word 0x18",
+    ".code64",
+    "ap_long_mode:",
+    "xor ax, ax",
+    "mov ds, ax",
+    "mov es, ax",
+    "mov ss, ax",
+    "mov rsp, [ap_trampoline_stack_address]",
+    "mov rdi, [ap_trampoline_argument_address]",
+    "mov rax, [ap_trampoline_entry_address]",
+    "call rax",
+    "ud2",
+    ".balign 8",
+    // 临时 GDT：空描述符、32位代码段、数据段、64位代码段
+    "ap_gdt:",
+    ".quad 0",
+    ".quad 0x00cf9a000000ffff",
+    ".quad 0x00cf92000000ffff",
+    ".quad 0x00af9a000000ffff",
+    "ap_gdt_pointer:",
+    ".word 31",
+    ".long {base} + ap_gdt - ap_trampoline_start",
+    ".balign 8",
+    // 由 BSP 在发送 SIPI 之前填写的参数
+    "ap_trampoline_cr3: .quad 0",
+    "ap_trampoline_stack: .quad 0",
+    "ap_trampoline_entry: .quad 0",
+    "ap_trampoline_argument: .quad 0",
+    "ap_trampoline_end:",
+    // 跳板运行在 {base}，这里换算出各参数被复制后的绝对地址
+    ".set ap_gdt_pointer_address, {base} + ap_gdt_pointer - ap_trampoline_start",
+    ".set ap_trampoline_cr3_address, {base} + ap_trampoline_cr3 - ap_trampoline_start",
+    ".set ap_trampoline_stack_address, {base} + ap_trampoline_stack - ap_trampoline_start",
+    ".set ap_trampoline_entry_address, {base} + ap_trampoline_entry - ap_trampoline_start",
+    ".set ap_trampoline_argument_address, {base} + ap_trampoline_argument - ap_trampoline_start",
+    ".previous",
+    base = const AP_TRAMPOLINE_ADDRESS,
+);
+
+extern "C" {
+    static ap_trampoline_start: u8;
+    static ap_trampoline_end: u8;
+    static ap_trampoline_cr3: u8;
+    static ap_trampoline_stack: u8;
+    static ap_trampoline_entry: u8;
+    static ap_trampoline_argument: u8;
+}
+
+// 跳板中某个符号相对起点的偏移
+fn trampoline_offset(symbol: *const u8) -> u64 {
+    symbol as u64 - addr_of!(ap_trampoline_start) as u64
+}
+
+/// 启动所有 AP，返回上线的 CPU 总数（包括 BSP）
+///
+/// 需要在 `crate::init` 和堆初始化之后、由 BSP 调用一次，并且要求中断已经开启（用时钟 tick 计时）。
+pub fn init(
+    mapper: &mut OffsetPageTable,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) -> Result<usize, SmpError> {
+    let madt = acpi::parse_madt()?;
+    apic::init(madt.local_apic_address, mapper, frame_allocator)
+        .map_err(|_| SmpError::MapFailed)?;
+
+    let bsp_apic_id = apic::id();
+    init_per_cpu(0, bsp_apic_id);
+    AP_APIC_IDS[0].store(usize::from(bsp_apic_id), Ordering::Relaxed);
+    CHECKED_IN[0].store(true, Ordering::Release);
+    ONLINE_CPUS.store(1, Ordering::Release);
+
+    install_trampoline(mapper, frame_allocator)?;
+
+    let ap_apic_ids = madt
+        .processor_apic_ids
+        .iter()
+        .copied()
+        .filter(|&apic_id| apic_id != bsp_apic_id)
+        .take(MAX_CPUS - 1);
+    for (offset, apic_id) in ap_apic_ids.enumerate() {
+        start_ap(offset + 1, apic_id)?;
+    }
+    Ok(online_cpus())
+}
+
+// 恒等映射跳板页并复制跳板代码
+fn install_trampoline(
+    mapper: &mut OffsetPageTable,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) -> Result<(), SmpError> {
+    let address = VirtAddr::new(AP_TRAMPOLINE_ADDRESS);
+    match mapper.translate_addr(address) {
+        Some(physical) if physical.as_u64() == AP_TRAMPOLINE_ADDRESS => {}
+        Some(_) => return Err(SmpError::MapFailed),
+        None => {
+            let frame: PhysFrame<Size4KiB> =
+                PhysFrame::containing_address(PhysAddr::new(AP_TRAMPOLINE_ADDRESS));
+            let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+            unsafe {
+                mapper
+                    .identity_map(frame, flags, frame_allocator)
+                    .map_err(|_| SmpError::MapFailed)?
+                    .flush();
+            }
+        }
+    }
+
+    unsafe {
+        let start = addr_of!(ap_trampoline_start);
+        let len = addr_of!(ap_trampoline_end) as usize - start as usize;
+        let destination = crate::memory::physical_memory_offset() + AP_TRAMPOLINE_ADDRESS;
+        core::ptr::copy_nonoverlapping(start, destination.as_mut_ptr::<u8>(), len);
+    }
+    Ok(())
+}
+
+// 写入跳板参数中的一个 u64
+fn write_trampoline_parameter(symbol: *const u8, value: u64) {
+    let address =
+        crate::memory::physical_memory_offset() + AP_TRAMPOLINE_ADDRESS + trampoline_offset(symbol);
+    unsafe { core::ptr::write_volatile(address.as_mut_ptr::<u64>(), value) };
+}
+
+// 用 INIT-SIPI-SIPI 启动一个 AP 并等待它报到
+fn start_ap(cpu_index: usize, apic_id: u8) -> Result<(), SmpError> {
+    use crate::task::timer;
+    use x86_64::registers::control::Cr3;
+
+    let stack_top = unsafe {
+        let stacks = &*addr_of!(AP_STACKS[cpu_index]);
+        VirtAddr::from_ptr(stacks.kernel.as_ptr()) + AP_STACK_SIZE
+    };
+    write_trampoline_parameter(
+        addr_of!(ap_trampoline_cr3),
+        Cr3::read().0.start_address().as_u64(),
+    );
+    write_trampoline_parameter(addr_of!(ap_trampoline_stack), stack_top.as_u64());
+    write_trampoline_parameter(addr_of!(ap_trampoline_entry), ap_entry as *const () as u64);
+    write_trampoline_parameter(addr_of!(ap_trampoline_argument), cpu_index as u64);
+    AP_APIC_IDS[cpu_index].store(usize::from(apic_id), Ordering::Relaxed);
+
+    let wait_ticks = |ticks: u64| {
+        let deadline = timer::current_tick() + ticks;
+        while timer::current_tick() < deadline && !is_online(cpu_index) {
+            x86_64::instructions::hlt();
+        }
+    };
+
+    let page_number = (AP_TRAMPOLINE_ADDRESS / 4096) as u8;
+    apic::send_init(apic_id);
+    // 规范要求 INIT 之后等待 10ms，一个 PIT tick 约 55ms
+    wait_ticks(1);
+    apic::send_startup(apic_id, page_number);
+    for _ in 0..10_000 {
+        if is_online(cpu_index) {
+            break;
+        }
+        core::hint::spin_loop();
+    }
+    if !is_online(cpu_index) {
+        apic::send_startup(apic_id, page_number);
+    }
+    wait_ticks(AP_STARTUP_TIMEOUT_TICKS);
+
+    if is_online(cpu_index) {
+        Ok(())
+    } else {
+        Err(SmpError::ApTimeout(apic_id))
+    }
+}
+
+// AP 离开跳板后执行的第一个 Rust 函数，运行在 `AP_STACKS[cpu_index].kernel` 上
+extern "C" fn ap_entry(cpu_index: usize) -> ! {
+    let (double_fault_stack_top, stack_top) = unsafe {
+        let stacks = &*addr_of_mut!(AP_STACKS[cpu_index]);
+        (
+            VirtAddr::from_ptr(stacks.double_fault.as_ptr()) + AP_DOUBLE_FAULT_STACK_SIZE,
+            VirtAddr::from_ptr(stacks.kernel.as_ptr()) + AP_STACK_SIZE,
+        )
+    };
+    gdt::init_ap(double_fault_stack_top, stack_top);
+    interrupts::init_idt();
+    syscall::init_cpu();
+    apic::enable();
+
+    let apic_id = apic::id();
+    debug_assert_eq!(
+        usize::from(apic_id),
+        AP_APIC_IDS[cpu_index].load(Ordering::Relaxed)
+    );
+    init_per_cpu(cpu_index, apic_id);
+
+    // 通过 GS 读回自己的编号报到，确认每 CPU 数据区可用
+    let per_cpu = current_cpu().expect("per-CPU data is not initialized");
+    ONLINE_CPUS.fetch_add(1, Ordering::AcqRel);
+    CHECKED_IN[per_cpu.cpu_index()].store(true, Ordering::Release);
+
+    loop {
+        x86_64::instructions::interrupts::disable();
+        x86_64::instructions::hlt();
+    }
+}
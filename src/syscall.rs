@@ -0,0 +1,211 @@
+//! 系统调用入口与分发。
+//!
+//! 用户态通过 `syscall` 指令陷入内核，调用约定与 Linux 保持一致：
+//! `rax` 为调用号，参数依次放在 `rdi`、`rsi`、`rdx`、`r10`、`r8`、`r9`，
+//! 返回值写回 `rax`，负数表示错误码。
+
+use crate::usermode::{self, UserExit};
+use core::arch::global_asm;
+use core::ptr::{addr_of, addr_of_mut};
+use x86_64::registers::model_specific::{Efer, EferFlags, LStar, SFMask, Star};
+use x86_64::registers::rflags::RFlags;
+use x86_64::VirtAddr;
+
+/// 系统调用号
+pub mod nr {
+    pub const WRITE: u64 = 0;
+    pub const EXIT: u64 = 1;
+    pub const YIELD: u64 = 2;
+    pub const SLEEP: u64 = 3;
+    pub const GETPID: u64 = 4;
+}
+
+/// 系统调用错误码（以负数形式返回给用户态）
+pub mod errno {
+    pub const EBADF: i64 = -9;
+    pub const EFAULT: i64 = -14;
+    pub const EINVAL: i64 = -22;
+    pub const ENOSYS: i64 = -38;
+}
+
+/// 入口汇编保存在内核栈上的用户寄存器，字段顺序与压栈顺序相反
+#[derive(Debug)]
+#[repr(C)]
+pub struct SyscallFrame {
+    pub number: u64,
+    pub arg0: u64,
+    pub arg1: u64,
+    pub arg2: u64,
+    pub arg3: u64,
+    pub arg4: u64,
+    pub arg5: u64,
+    // 被调用者保存的寄存器，用户程序被挂起时需要完整保存
+    pub rbx: u64,
+    pub rbp: u64,
+    pub r12: u64,
+    pub r13: u64,
+    pub r14: u64,
+    pub r15: u64,
+    /// `syscall` 指令保存在 `rcx` 中的用户态返回地址
+    pub user_rip: u64,
+    /// `syscall` 指令保存在 `r11` 中的用户态 RFLAGS
+    pub user_rflags: u64,
+    pub user_rsp: u64,
+}
+
+type SyscallHandler = fn(&mut SyscallFrame) -> i64;
+
+static SYSCALL_TABLE: [SyscallHandler; 5] = [sys_write, sys_exit, sys_yield, sys_sleep, sys_getpid];
+
+// 入口汇编使用的临时变量：`syscall` 不会切换栈，需要手动换到内核栈
+#[no_mangle]
+static mut SYSCALL_USER_RSP: u64 = 0;
+#[no_mangle]
+static mut SYSCALL_KERNEL_RSP: u64 = 0;
+
+global_asm!(
+    ".global syscall_entry",
+    "syscall_entry:",
+    "mov [rip + SYSCALL_USER_RSP], rsp",
+    "mov rsp, [rip + SYSCALL_KERNEL_RSP]",
+    "push [rip + SYSCALL_USER_RSP]",
+    "push r11",
+    "push rcx",
+    "push r15",
+    "push r14",
+    "push r13",
+    "push r12",
+    "push rbp",
+    "push rbx",
+    "push r9",
+    "push r8",
+    "push r10",
+    "push rdx",
+    "push rsi",
+    "push rdi",
+    "push rax",
+    "mov rdi, rsp",
+    "call {dispatch}",
+    // 跳过调用号，恢复其余参数寄存器，返回值已经在 rax 中
+    "add rsp, 8",
+    "pop rdi",
+    "pop rsi",
+    "pop rdx",
+    "pop r10",
+    "pop r8",
+    "pop r9",
+    "pop rbx",
+    "pop rbp",
+    "pop r12",
+    "pop r13",
+    "pop r14",
+    "pop r15",
+    "pop rcx",
+    "pop r11",
+    "pop rsp",
+    "sysretq",
+    dispatch = sym syscall_dispatch,
+);
+
+extern "C" {
+    fn syscall_entry();
+}
+
+/// 启用 `syscall`/`sysret` 并设置入口地址
+///
+/// 必须在 `gdt::init` 之后调用。
+pub fn init() {
+    use crate::gdt;
+
+    unsafe {
+        set_kernel_stack(gdt::privilege_stack());
+    }
+    init_cpu();
+}
+
+/// 在当前 CPU 上写入 `syscall` 相关的 MSR
+///
+/// 这些 MSR 每个 CPU 各有一份，AP 启动时需要单独调用。
+pub fn init_cpu() {
+    use crate::gdt;
+
+    unsafe {
+        Efer::update(|flags| {
+            flags.insert(EferFlags::SYSTEM_CALL_EXTENSIONS | EferFlags::NO_EXECUTE_ENABLE)
+        });
+    }
+    Star::write(
+        gdt::user_code_selector(),
+        gdt::user_data_selector(),
+        gdt::kernel_code_selector(),
+        gdt::kernel_data_selector(),
+    )
+    .expect("GDT layout is not compatible with syscall/sysret");
+    LStar::write(VirtAddr::new(syscall_entry as *const () as u64));
+    // 进入内核时屏蔽中断，入口汇编在内核栈切换完成之前不能被打断
+    SFMask::write(RFlags::INTERRUPT_FLAG | RFlags::DIRECTION_FLAG | RFlags::TRAP_FLAG);
+}
+
+/// 设置 `syscall` 入口切换到的内核栈顶
+///
+/// # Safety
+/// 与 [`crate::gdt::set_privilege_stack`] 相同，`stack_top` 必须指向一块专用的内核栈。
+pub unsafe fn set_kernel_stack(stack_top: VirtAddr) {
+    *addr_of_mut!(SYSCALL_KERNEL_RSP) = stack_top.as_u64();
+}
+
+/// 当前 `syscall` 入口使用的内核栈顶
+pub fn kernel_stack() -> VirtAddr {
+    VirtAddr::new(unsafe { *addr_of!(SYSCALL_KERNEL_RSP) })
+}
+
+extern "C" fn syscall_dispatch(frame: &mut SyscallFrame) -> i64 {
+    match SYSCALL_TABLE.get(frame.number as usize) {
+        Some(handler) => handler(frame),
+        None => errno::ENOSYS,
+    }
+}
+
+fn sys_write(frame: &mut SyscallFrame) -> i64 {
+    use crate::process::{self, Handle};
+
+    let (fd, buf, len) = (frame.arg0, frame.arg1, frame.arg2);
+    // 进程通过句柄表解析文件描述符，不属于进程的用户程序只能写标准输出和标准错误
+    let handle = match process::current() {
+        Some(process) => process.handles().get(fd),
+        None if fd == 1 || fd == 2 => Some(Handle::Console),
+        None => None,
+    };
+    if handle != Some(Handle::Console) {
+        return errno::EBADF;
+    }
+    let bytes = match usermode::user_slice(buf, len) {
+        Some(bytes) => bytes,
+        None => return errno::EFAULT,
+    };
+    match core::str::from_utf8(bytes) {
+        Ok(text) => {
+            crate::print!("{}", text);
+            usermode::capture_output(bytes);
+            len as i64
+        }
+        Err(_) => errno::EINVAL,
+    }
+}
+
+fn sys_exit(frame: &mut SyscallFrame) -> i64 {
+    unsafe { usermode::suspend(frame, 0, UserExit::Exited(frame.arg0)) }
+}
+
+fn sys_yield(frame: &mut SyscallFrame) -> i64 {
+    unsafe { usermode::suspend(frame, 0, UserExit::Yielded) }
+}
+
+fn sys_sleep(frame: &mut SyscallFrame) -> i64 {
+    unsafe { usermode::suspend(frame, 0, UserExit::Sleep(frame.arg0)) }
+}
+
+fn sys_getpid(_frame: &mut SyscallFrame) -> i64 {
+    // 不属于任何进程的用户程序得到 PID 0
+    crate::process::current_pid().map_or(0, |pid| pid.as_u64() as i64)
+}
@@ -0,0 +1,48 @@
+//! # 任务取消
+//!
+//! 每个 [`Task`](super::Task) 都带有一个 [`AbortHandle`]。调用 `abort` 后任务会被唤醒，
+//! 执行器在下一次调度到它时不再轮询，而是直接把它当作已完成移除：future 被丢弃，
+//! 唤醒器缓存与定时器登记随之清理，等待它的 `JoinHandle` 得到取消结果。
+
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::task::Waker;
+use futures_util::task::AtomicWaker;
+
+struct AbortState {
+    aborted: AtomicBool,
+    waker: AtomicWaker,
+}
+
+/// 取消任务的句柄，可以克隆，也可以在中断处理程序中使用
+#[derive(Clone)]
+pub struct AbortHandle {
+    state: Arc<AbortState>,
+}
+
+impl AbortHandle {
+    pub(crate) fn new() -> AbortHandle {
+        AbortHandle {
+            state: Arc::new(AbortState {
+                aborted: AtomicBool::new(false),
+                waker: AtomicWaker::new(),
+            }),
+        }
+    }
+
+    /// 请求取消任务；任务已经结束时没有效果
+    pub fn abort(&self) {
+        self.state.aborted.store(true, Ordering::Release);
+        self.state.waker.wake();
+    }
+
+    /// 是否已经请求取消
+    pub fn is_aborted(&self) -> bool {
+        self.state.aborted.load(Ordering::Acquire)
+    }
+
+    // 记录任务当前的唤醒器，`abort` 通过它让任务重新进入就绪队列
+    pub(crate) fn register(&self, waker: &Waker) {
+        self.state.waker.register(waker);
+    }
+}
@@ -0,0 +1,174 @@
+//! # 任务记账
+//!
+//! 执行器为每个任务记录轮询次数、累计轮询耗时（TSC 周期）、最近一次运行的 tick 和被唤醒次数。
+//! 所有执行器中的任务都登记在全局表中，[`task_list`] 列出它们，[`print_top`] 通过串口
+//! 输出类似 `top` 的视图。
+
+use super::{SharedPriority, TaskId, TaskPriority};
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::cmp::Reverse;
+use core::sync::atomic::{AtomicU64, Ordering};
+use spin::Mutex;
+
+// 还没有运行过的任务的 `last_run_tick`
+const NEVER_RAN: u64 = u64::MAX;
+// 自上次轮询以来没有入队过的任务的 `woken_at`
+const NOT_WOKEN: u64 = u64::MAX;
+
+static REGISTRY: Mutex<BTreeMap<TaskId, Arc<TaskAccounting>>> = Mutex::new(BTreeMap::new());
+
+/// 某个任务的记账快照
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TaskStats {
+    pub id: TaskId,
+    pub name: Option<&'static str>,
+    pub priority: TaskPriority,
+    /// 被轮询的次数
+    pub polls: u64,
+    /// 累计花在 `poll` 中的 TSC 周期数
+    pub poll_cycles: u64,
+    /// 单次 `poll` 的最长 TSC 周期数
+    pub max_poll_cycles: u64,
+    /// 单次 `poll` 超过执行器慢轮询阈值的次数
+    pub slow_polls: u64,
+    /// 被唤醒的次数（包括被去重的重复唤醒）
+    pub wakes: u64,
+    /// 最近一次被轮询时的 tick，尚未运行时为 `None`
+    pub last_run_tick: Option<u64>,
+}
+
+// 执行器、唤醒器与全局登记表共享的计数器
+pub(crate) struct TaskAccounting {
+    id: TaskId,
+    name: Option<&'static str>,
+    priority: SharedPriority,
+    polls: AtomicU64,
+    poll_cycles: AtomicU64,
+    max_poll_cycles: AtomicU64,
+    slow_polls: AtomicU64,
+    wakes: AtomicU64,
+    last_run_tick: AtomicU64,
+    // 最近一次入队时的 TSC，轮询时取出计算唤醒延迟
+    woken_at: AtomicU64,
+}
+
+impl TaskAccounting {
+    // 创建计数器并登记到全局表
+    pub(crate) fn register(
+        id: TaskId,
+        name: Option<&'static str>,
+        priority: SharedPriority,
+    ) -> Arc<TaskAccounting> {
+        let accounting = Arc::new(TaskAccounting {
+            id,
+            name,
+            priority,
+            polls: AtomicU64::new(0),
+            poll_cycles: AtomicU64::new(0),
+            max_poll_cycles: AtomicU64::new(0),
+            slow_polls: AtomicU64::new(0),
+            wakes: AtomicU64::new(0),
+            last_run_tick: AtomicU64::new(NEVER_RAN),
+            woken_at: AtomicU64::new(NOT_WOKEN),
+        });
+        REGISTRY.lock().insert(id, accounting.clone());
+        accounting
+    }
+
+    pub(crate) fn unregister(&self) {
+        REGISTRY.lock().remove(&self.id);
+    }
+
+    pub(crate) fn record_poll(&self, cycles: u64, tick: u64) {
+        self.polls.fetch_add(1, Ordering::Relaxed);
+        self.poll_cycles.fetch_add(cycles, Ordering::Relaxed);
+        self.max_poll_cycles.fetch_max(cycles, Ordering::Relaxed);
+        self.last_run_tick.store(tick, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_slow_poll(&self) {
+        self.slow_polls.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_wake(&self) {
+        self.wakes.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // 任务被放入就绪队列时调用
+    pub(crate) fn mark_woken(&self, tsc: u64) {
+        self.woken_at.store(tsc, Ordering::Relaxed);
+    }
+
+    // 轮询前调用，返回入队到现在经过的周期数
+    pub(crate) fn take_wake_latency(&self, now: u64) -> Option<u64> {
+        match self.woken_at.swap(NOT_WOKEN, Ordering::Relaxed) {
+            NOT_WOKEN => None,
+            woken_at => Some(now.saturating_sub(woken_at)),
+        }
+    }
+
+    pub(crate) fn snapshot(&self) -> TaskStats {
+        let last_run_tick = self.last_run_tick.load(Ordering::Relaxed);
+        TaskStats {
+            id: self.id,
+            name: self.name,
+            priority: self.priority.get(),
+            polls: self.polls.load(Ordering::Relaxed),
+            poll_cycles: self.poll_cycles.load(Ordering::Relaxed),
+            max_poll_cycles: self.max_poll_cycles.load(Ordering::Relaxed),
+            slow_polls: self.slow_polls.load(Ordering::Relaxed),
+            wakes: self.wakes.load(Ordering::Relaxed),
+            last_run_tick: (last_run_tick != NEVER_RAN).then_some(last_run_tick),
+        }
+    }
+}
+
+/// 读取时间戳计数器
+pub fn read_tsc() -> u64 {
+    unsafe { core::arch::x86_64::_rdtsc() }
+}
+
+/// 所有执行器中尚未结束的任务，按任务ID排序
+pub fn task_list() -> Vec<TaskStats> {
+    REGISTRY
+        .lock()
+        .values()
+        .map(|accounting| accounting.snapshot())
+        .collect()
+}
+
+/// 按累计轮询耗时从高到低，通过串口输出任务列表
+pub fn print_top() {
+    let mut tasks = task_list();
+    tasks.sort_by_key(|task| Reverse(task.poll_cycles));
+    crate::serial_println!(
+        "[top] {:>5} {:<16} {:<8} {:>8} {:>14} {:>8} {:>10}",
+        "id",
+        "name",
+        "prio",
+        "polls",
+        "cycles",
+        "wakes",
+        "last_tick"
+    );
+    for task in tasks {
+        let last_run = match task.last_run_tick {
+            Some(tick) => format!("{}", tick),
+            None => String::from("-"),
+        };
+        crate::serial_println!(
+            "[top] {:>5} {:<16} {:<8} {:>8} {:>14} {:>8} {:>10}",
+            task.id.as_u64(),
+            task.name.unwrap_or("-"),
+            format!("{:?}", task.priority),
+            task.polls,
+            task.poll_cycles,
+            task.wakes,
+            last_run,
+        );
+    }
+}
@@ -0,0 +1,90 @@
+//! # 协作式调度预算
+//!
+//! 执行器每次轮询任务前给它 [`POLL_BUDGET`] 个单位的预算，通道等资源每次就绪时消耗一个单位。
+//! 预算用完后，这些资源即使就绪也返回 `Pending` 并立即唤醒任务，于是一个不断从就绪的
+//! 内部 future 取得结果的循环也会回到执行器，让键盘等更高优先级的任务得到运行。
+//! 没有经过资源的计算循环可以用 [`yield_now`] 主动让出。
+//!
+//! 预算是全局的，执行器在轮询前后保存并恢复它；线程切换时预算随线程一起保存。
+//! 不在执行器轮询中（例如中断处理程序或启动代码）时预算不受限制。
+
+use core::future::Future;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use core::task::{Context, Poll};
+
+/// 每次轮询任务时的预算
+pub const POLL_BUDGET: u32 = 128;
+// 不在执行器轮询中
+pub(crate) const UNCONSTRAINED: u32 = u32::MAX;
+
+static BUDGET: AtomicU32 = AtomicU32::new(UNCONSTRAINED);
+static FORCED_YIELDS: AtomicU64 = AtomicU64::new(0);
+
+/// 检查当前任务是否还有预算；有则消耗一个单位，否则唤醒任务并返回 `Pending`
+///
+/// 资源在尝试取出结果之前调用，`Pending` 时直接返回，不要登记唤醒器。
+pub fn poll_proceed(context: &mut Context<'_>) -> Poll<()> {
+    let budget = BUDGET.load(Ordering::Relaxed);
+    if budget == UNCONSTRAINED {
+        return Poll::Ready(());
+    }
+    if budget == 0 {
+        FORCED_YIELDS.fetch_add(1, Ordering::Relaxed);
+        context.waker().wake_by_ref();
+        return Poll::Pending;
+    }
+    BUDGET.store(budget - 1, Ordering::Relaxed);
+    Poll::Ready(())
+}
+
+/// 本次轮询剩余的预算；不在执行器轮询中时为 `None`
+pub fn remaining_budget() -> Option<u32> {
+    Some(BUDGET.load(Ordering::Relaxed)).filter(|&budget| budget != UNCONSTRAINED)
+}
+
+/// 资源因预算耗尽而返回 `Pending` 的累计次数
+pub fn forced_yields() -> u64 {
+    FORCED_YIELDS.load(Ordering::Relaxed)
+}
+
+/// 让出一次执行权：第一次轮询时唤醒自己并返回 `Pending`，下一次轮询时完成
+pub fn yield_now() -> YieldNow {
+    YieldNow { yielded: false }
+}
+
+/// [`yield_now`] 返回的 future
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct YieldNow {
+    yielded: bool,
+}
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<()> {
+        if self.yielded {
+            return Poll::Ready(());
+        }
+        self.yielded = true;
+        context.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+// 以新的预算执行一次轮询，结束后恢复之前的预算（`block_on` 中可能嵌套）
+pub(crate) fn with_budget<R>(f: impl FnOnce() -> R) -> R {
+    let previous = BUDGET.swap(POLL_BUDGET, Ordering::Relaxed);
+    let result = f();
+    BUDGET.store(previous, Ordering::Relaxed);
+    result
+}
+
+// 线程切换时保存和恢复预算
+pub(crate) fn current_budget() -> u32 {
+    BUDGET.load(Ordering::Relaxed)
+}
+
+pub(crate) fn set_budget(budget: u32) {
+    BUDGET.store(budget, Ordering::Relaxed);
+}
@@ -2,43 +2,135 @@
 //!
 //! 这个执行器使用唤醒机制来避免不必要的轮询，提高性能。
 //! 只有当任务被唤醒时才会重新调度执行。
+//! 每个任务带有"已调度"标志，唤醒只在标志从假变真时入队，因此每个任务在就绪队列中至多
+//! 一项；任务数又不超过队列容量，入队永远不会失败，唤醒也就不会丢失。
+//! 运行中的任务和中断处理程序可以通过 [`Spawner`] 向执行器注入新任务。
+//! 队列容量、各优先级的任务数上限与可增长队列模式由 [`ExecutorConfig`] 配置。
+//!
+//! 每个优先级有独立的就绪队列。选择下一个任务时使用老化策略：
+//! 非空队列每被跳过 [`AGING_STEP`] 次，有效优先级提升一级，因此持续的高优先级负载
+//! 只会让低优先级任务变慢，而不会让它们饿死。
+//! 每轮调度最多轮询开始时已就绪的任务数，不断唤醒自己的任务也不会让一轮调度停不下来，
+//! `block_on` 的 future、`run_until` 的条件和注入的任务每轮都有机会运行。
+//!
+//! 任务从入队到被轮询的等待时间按优先级计入延迟直方图，见 [`LatencyHistogram`]。
+//! 派生、唤醒、轮询与完成等调度事件写入定长的跟踪缓冲区，可以用 [`dump_trace`] 输出。
+//! 每次轮询都带有协作预算（见 [`coop`](super::coop)），单次轮询超过配置阈值的任务计为慢轮询，
+//! 计入统计、跟踪缓冲区与 [`last_slow_poll`]；警告交给 [`workqueue::SYSTEM`](super::workqueue::SYSTEM)
+//! 输出，调度循环中不做串口输出。
+//! 执行器在看门狗（见 [`watchdog`](super::watchdog)）中登记当前状态，`poll` 卡住时由时钟中断报告。
+//!
+//! [`Executor::run`] 永不返回；测试与关机流程可以用 [`Executor::run_until_idle`]、
+//! [`Executor::block_on`] 与 [`Executor::run_until`]，在条件满足时把控制权交还给调用者。
+
+mod config;
+mod latency;
+mod trace;
+
+pub use config::{ExecutorConfig, QueueMode, DEFAULT_SLOW_POLL_CYCLES};
+pub use latency::{LatencyHistogram, FIRST_BUCKET_CYCLES, LATENCY_BUCKETS};
+pub use trace::{
+    dump_trace, set_tracing, trace_snapshot, TraceEvent, TraceFormat, TraceKind, TRACE_CAPACITY,
+};
 
-use super::{Task, TaskId, TaskPriority};
+use super::accounting::{self, TaskAccounting, TaskStats};
+use super::join::JoinHandle;
+use super::watchdog::ExecutorWatch;
+use super::workqueue::{self, Work};
+use super::{coop, timer, Locals, SharedPriority, Task, TaskId, TaskPriority};
 use alloc::task::Wake;
+use alloc::vec::Vec;
 use alloc::{collections::BTreeMap, sync::Arc};
-use core::sync::atomic::{AtomicU64, Ordering};
+use config::{Capacity, TaskQueue};
+use core::future::Future;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use core::task::{Context, Poll, Waker};
-use crossbeam_queue::ArrayQueue;
 
-const TASK_QUEUE_CAPACITY: usize = 100;
+/// 默认配置下每个执行器同时持有的任务数上限，也是每个就绪队列的容量
+pub const MAX_TASKS: usize = 100;
+/// 非空就绪队列每被跳过这么多次，有效优先级提升一级
+pub const AGING_STEP: u32 = 4;
 static DROPPED_WAKE_COUNT: AtomicU64 = AtomicU64::new(0);
 static LAST_ACTIVE_TASKS: AtomicU64 = AtomicU64::new(0);
-static LAST_QUEUED_HIGH_TASKS: AtomicU64 = AtomicU64::new(0);
-static LAST_QUEUED_NORMAL_TASKS: AtomicU64 = AtomicU64::new(0);
+static LAST_QUEUED_TASKS: [AtomicU64; TaskPriority::LEVELS] =
+    [const { AtomicU64::new(0) }; TaskPriority::LEVELS];
 static LAST_CACHED_WAKERS: AtomicU64 = AtomicU64::new(0);
+static SLOW_POLL_COUNT: AtomicU64 = AtomicU64::new(0);
+static LAST_SLOW_POLL: spin::Mutex<Option<SlowPoll>> = spin::Mutex::new(None);
+// 已经排队、尚未输出的慢轮询警告，排队期间的新记录只更新 `LAST_SLOW_POLL`
+static SLOW_POLL_WARNING_QUEUED: AtomicBool = AtomicBool::new(false);
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SpawnError {
     DuplicateTaskId,
+    /// 任务总数或该优先级的任务数已达到配置的上限
     QueueFull,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct ExecutorStats {
     pub active_tasks: usize,
-    pub queued_high_tasks: usize,
-    pub queued_normal_tasks: usize,
+    /// 各优先级就绪队列的长度，按 [`TaskPriority::level`] 索引
+    pub queued_tasks: [usize; TaskPriority::LEVELS],
     pub cached_wakers: usize,
     pub dropped_wakes: u64,
+    /// 单次轮询超过慢轮询阈值的次数
+    pub slow_polls: u64,
+    /// 资源因协作预算耗尽而返回 `Pending` 的次数
+    pub forced_yields: u64,
+    /// 各优先级从唤醒入队到被轮询的延迟，按 [`TaskPriority::level`] 索引
+    pub wake_latency: [LatencyHistogram; TaskPriority::LEVELS],
 }
 
 pub fn global_stats_snapshot() -> ExecutorStats {
     ExecutorStats {
         active_tasks: LAST_ACTIVE_TASKS.load(Ordering::Relaxed) as usize,
-        queued_high_tasks: LAST_QUEUED_HIGH_TASKS.load(Ordering::Relaxed) as usize,
-        queued_normal_tasks: LAST_QUEUED_NORMAL_TASKS.load(Ordering::Relaxed) as usize,
+        queued_tasks: core::array::from_fn(|level| {
+            LAST_QUEUED_TASKS[level].load(Ordering::Relaxed) as usize
+        }),
         cached_wakers: LAST_CACHED_WAKERS.load(Ordering::Relaxed) as usize,
         dropped_wakes: DROPPED_WAKE_COUNT.load(Ordering::Relaxed),
+        slow_polls: SLOW_POLL_COUNT.load(Ordering::Relaxed),
+        forced_yields: coop::forced_yields(),
+        wake_latency: latency::snapshot(),
+    }
+}
+
+/// 一次慢轮询的记录
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlowPoll {
+    pub task_id: TaskId,
+    pub name: Option<&'static str>,
+    /// 这次轮询花费的 TSC 周期数
+    pub cycles: u64,
+    pub tick: u64,
+}
+
+/// 所有执行器中最近一次慢轮询
+pub fn last_slow_poll() -> Option<SlowPoll> {
+    x86_64::instructions::interrupts::without_interrupts(|| *LAST_SLOW_POLL.lock())
+}
+
+fn record_slow_poll(slow_poll: SlowPoll) {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        *LAST_SLOW_POLL.lock() = Some(slow_poll);
+    });
+    if !SLOW_POLL_WARNING_QUEUED.swap(true, Ordering::AcqRel)
+        && !workqueue::SYSTEM.queue(Work::new(print_slow_poll, 0))
+    {
+        SLOW_POLL_WARNING_QUEUED.store(false, Ordering::Release);
+    }
+}
+
+fn print_slow_poll(_: usize) {
+    SLOW_POLL_WARNING_QUEUED.store(false, Ordering::Release);
+    if let Some(slow_poll) = last_slow_poll() {
+        crate::serial_println!(
+            "[coop] slow poll: task #{} ({}) ran {} cycles without yielding",
+            slow_poll.task_id.as_u64(),
+            slow_poll.name.unwrap_or("-"),
+            slow_poll.cycles,
+        );
     }
 }
 
@@ -48,124 +140,336 @@ pub fn global_stats_snapshot() -> ExecutorStats {
 /// 当没有任务需要执行时，CPU会进入休眠状态以节省电力。
 pub struct Executor {
     /// 存储所有任务的映射表
-    tasks: BTreeMap<TaskId, Task>,
-    /// 高优先级待执行任务队列
-    high_priority_task_queue: Arc<ArrayQueue<TaskId>>,
-    /// 普通优先级待执行任务队列
-    normal_priority_task_queue: Arc<ArrayQueue<TaskId>>,
+    tasks: BTreeMap<TaskId, TaskSlot>,
+    /// 各优先级的待执行任务队列
+    ready_queues: Arc<ReadyQueues>,
+    /// 各优先级队列连续被跳过的次数，用于老化
+    skipped_rounds: [u32; TaskPriority::LEVELS],
     /// 缓存每个任务的唤醒器
     waker_cache: BTreeMap<TaskId, Waker>,
+    /// `Spawner` 注入、尚未进入任务表的任务
+    injector: Arc<TaskQueue<InjectedTask>>,
+    /// 与 `Spawner` 共享的任务名额
+    capacity: Arc<Capacity>,
+    /// 单次轮询超过这么多 TSC 周期即为慢轮询
+    slow_poll_cycles: u64,
+    /// 看门狗中的观察槽
+    watch: ExecutorWatch,
+}
+
+// 任务表中的任务、它派生时占用名额的优先级，以及它的记账
+struct TaskSlot {
+    task: Task,
+    reserved: TaskPriority,
+    accounting: Arc<TaskAccounting>,
 }
 
+// 注入队列中的任务只由 `Spawner` 用 `Send` 的 future 构造，因此可以跨上下文移动；
+// 它们在注入前已经占用了名额
+struct InjectedTask {
+    task: Task,
+    reserved: TaskPriority,
+}
+
+unsafe impl Send for InjectedTask {}
+
 impl Executor {
-    /// 创建一个新的执行器
+    /// 以默认配置创建一个新的执行器
     pub fn new() -> Self {
+        Self::with_config(ExecutorConfig::new())
+    }
+
+    /// 按 `config` 创建执行器
+    pub fn with_config(config: ExecutorConfig) -> Self {
         Executor {
             tasks: BTreeMap::new(),
-            high_priority_task_queue: Arc::new(ArrayQueue::new(TASK_QUEUE_CAPACITY)),
-            normal_priority_task_queue: Arc::new(ArrayQueue::new(TASK_QUEUE_CAPACITY)),
+            ready_queues: Arc::new(ReadyQueues::new(&config)),
+            skipped_rounds: [0; TaskPriority::LEVELS],
             waker_cache: BTreeMap::new(),
+            injector: Arc::new(TaskQueue::new(&config)),
+            capacity: Arc::new(Capacity::new(&config)),
+            slow_poll_cycles: config.slow_poll_cycles,
+            watch: ExecutorWatch::claim(),
         }
     }
 
-    /// 添加一个新任务到执行器
+    /// 返回一个可以在任务或中断上下文中向本执行器添加任务的句柄
+    pub fn spawner(&self) -> Spawner {
+        Spawner {
+            injector: self.injector.clone(),
+            capacity: self.capacity.clone(),
+        }
+    }
+
+    /// 把 `future` 作为普通优先级任务添加到执行器，返回等待其输出的句柄
     ///
     /// # Panics
-    /// 如果任务ID已经存在或任务队列已满，会panic
-    pub fn spawn(&mut self, task: Task) {
+    /// 如果任务队列已满，会panic
+    pub fn spawn<F>(&mut self, future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + 'static,
+        F::Output: 'static,
+    {
+        self.spawn_with_priority(future, TaskPriority::Normal)
+    }
+
+    /// 同 [`Executor::spawn`]，但指定任务优先级
+    pub fn spawn_with_priority<F>(
+        &mut self,
+        future: F,
+        priority: TaskPriority,
+    ) -> JoinHandle<F::Output>
+    where
+        F: Future + 'static,
+        F::Output: 'static,
+    {
+        let (task, handle) = Task::joinable_with_priority(future, priority);
         self.try_spawn(task)
             .expect("failed to spawn task into executor");
+        handle
     }
 
     /// 尝试添加一个新任务到执行器，避免在容量压力下panic。
+    ///
+    /// 任务数达到配置的上限时返回 [`SpawnError::QueueFull`]。
     pub fn try_spawn(&mut self, task: Task) -> Result<(), SpawnError> {
-        let task_id = task.id;
-        let task_priority = task.priority;
-        if self.tasks.insert(task_id, task).is_some() {
+        if self.tasks.contains_key(&task.id) {
             return Err(SpawnError::DuplicateTaskId);
         }
+        let priority = task.priority();
+        if !self.capacity.try_reserve(priority) {
+            return Err(SpawnError::QueueFull);
+        }
+        self.insert_reserved(task, priority);
+        Ok(())
+    }
 
-        let queue_push_result = match task_priority {
-            TaskPriority::High => self.high_priority_task_queue.push(task_id),
-            TaskPriority::Normal => self.normal_priority_task_queue.push(task_id),
-        };
+    // 加入已占用名额的任务；就绪队列容量不小于任务上限，入队一定成功
+    fn insert_reserved(&mut self, task: Task, reserved: TaskPriority) {
+        let task_id = task.id;
+        let priority = task.priority();
+        let accounting = TaskAccounting::register(task_id, task.name(), task.shared_priority());
+        accounting.mark_woken(accounting::read_tsc());
+        trace::record(TraceKind::Spawn, task_id.as_u64());
+        self.tasks.insert(
+            task_id,
+            TaskSlot {
+                task,
+                reserved,
+                accounting,
+            },
+        );
+        if self.ready_queues.push(task_id, priority).is_err() {
+            unreachable!("ready queue capacity covers every reserved task");
+        }
+    }
 
-        if queue_push_result.is_err() {
-            self.tasks.remove(&task_id);
-            return Err(SpawnError::QueueFull);
+    /// 修改任务表中某个任务的优先级，任务不存在时返回 `false`
+    pub fn set_priority(&self, task_id: TaskId, priority: TaskPriority) -> bool {
+        match self.tasks.get(&task_id) {
+            Some(slot) => {
+                slot.task.set_priority(priority);
+                true
+            }
+            None => false,
         }
+    }
 
-        Ok(())
+    /// 本执行器中每个任务的记账快照，按任务ID排序
+    pub fn task_stats(&self) -> Vec<TaskStats> {
+        self.tasks
+            .values()
+            .map(|slot| slot.accounting.snapshot())
+            .collect()
+    }
+
+    // 把注入的任务移入任务表，它们已经在注入时占用了名额
+    fn accept_injected_tasks(&mut self) {
+        while let Some(InjectedTask { task, reserved }) = self.injector.pop() {
+            self.insert_reserved(task, reserved);
+        }
     }
 
     fn run_ready_tasks(&mut self) {
+        self.accept_injected_tasks();
+
         // destructure `self` to avoid borrow checker errors
         let Self {
             tasks,
-            high_priority_task_queue,
-            normal_priority_task_queue,
+            ready_queues,
+            skipped_rounds,
             waker_cache,
+            capacity,
+            slow_poll_cycles,
+            watch,
+            ..
         } = self;
 
-        while let Some(task_id) =
-            pop_next_task_id(high_priority_task_queue, normal_priority_task_queue)
-        {
-            let task = match tasks.get_mut(&task_id) {
-                Some(task) => task,
+        // 本轮中重新入队的任务留到下一轮
+        let round: usize = ready_queues.lens().iter().sum();
+        for _ in 0..round {
+            let Some(task_id) = pop_next_task_id(ready_queues, skipped_rounds) else {
+                break;
+            };
+            let slot = match tasks.get_mut(&task_id) {
+                Some(slot) => slot,
                 None => continue,
             };
-            let task_priority = task.priority;
+            let task_priority = slot.task.shared_priority();
+            let scheduled = slot.task.scheduled_flag();
 
-            let high_queue = high_priority_task_queue.clone();
-            let normal_queue = normal_priority_task_queue.clone();
+            let queues = ready_queues.clone();
+            let task_accounting = slot.accounting.clone();
             let waker = waker_cache.entry(task_id).or_insert_with(|| {
-                TaskWaker::new(task_id, task_priority, high_queue, normal_queue)
+                TaskWaker::new(
+                    task_id,
+                    task_priority,
+                    scheduled.clone(),
+                    task_accounting,
+                    queues,
+                )
             });
 
+            // 先清除标志再轮询：轮询期间的唤醒会重新入队一次
+            scheduled.store(false, Ordering::Release);
             let mut context = Context::from_waker(waker);
 
-            match task.poll(&mut context) {
+            let poll_start = accounting::read_tsc();
+            if let Some(cycles) = slot.accounting.take_wake_latency(poll_start) {
+                latency::record(slot.task.priority(), cycles);
+            }
+            trace::record(TraceKind::PollStart, task_id.as_u64());
+            watch.begin_poll(task_id, slot.task.name());
+            let poll_result = coop::with_budget(|| slot.task.poll(&mut context));
+            watch.scheduling();
+            let poll_cycles = accounting::read_tsc().wrapping_sub(poll_start);
+            slot.accounting
+                .record_poll(poll_cycles, timer::current_tick());
+            if poll_cycles > *slow_poll_cycles {
+                SLOW_POLL_COUNT.fetch_add(1, Ordering::Relaxed);
+                slot.accounting.record_slow_poll();
+                trace::record(TraceKind::SlowPoll, task_id.as_u64());
+                record_slow_poll(SlowPoll {
+                    task_id,
+                    name: slot.task.name(),
+                    cycles: poll_cycles,
+                    tick: timer::current_tick(),
+                });
+            }
+
+            match poll_result {
                 Poll::Ready(()) => {
-                    tasks.remove(&task_id);
+                    trace::record(TraceKind::Complete, task_id.as_u64());
+                    // 已结束的任务永久保持"已调度"，残留的唤醒器不会再入队
+                    scheduled.store(true, Ordering::Release);
+                    if let Some(slot) = tasks.remove(&task_id) {
+                        slot.accounting.unregister();
+                        capacity.release(slot.reserved);
+                    }
                     waker_cache.remove(&task_id);
                 }
-                Poll::Pending => {}
+                Poll::Pending => trace::record(TraceKind::PollEnd, task_id.as_u64()),
             }
         }
     }
 
     pub fn run(&mut self) -> ! {
         loop {
+            self.watch.scheduling();
+            self.run_ready_tasks();
+            self.update_stats_snapshot();
+            self.sleep_if_idle(|| false);
+        }
+    }
+
+    /// 运行到所有任务（包括注入中的任务）都结束后返回
+    ///
+    /// 永不结束的任务（例如工作队列的工作者）会让它一直运行下去。
+    pub fn run_until_idle(&mut self) {
+        self.run_until_state(|executor| executor.tasks.is_empty() && executor.injector.is_empty());
+    }
+
+    /// 每轮调度之后检查 `predicate`，返回 `true` 时停止运行，尚未结束的任务留在执行器中
+    pub fn run_until(&mut self, mut predicate: impl FnMut() -> bool) {
+        self.run_until_state(|_| predicate());
+    }
+
+    /// 在调度其他任务的同时驱动 `future` 直到完成，返回其输出
+    ///
+    /// `future` 不进入任务表，因此不需要 `'static`，也不占用任务名额；它返回时其他任务留在执行器中。
+    pub fn block_on<F: Future>(&mut self, future: F) -> F::Output {
+        let mut future = core::pin::pin!(future);
+        let woken = Arc::new(BlockOnWaker {
+            woken: AtomicBool::new(true),
+        });
+        let waker = Waker::from(woken.clone());
+        let mut context = Context::from_waker(&waker);
+        loop {
+            if woken.woken.swap(false, Ordering::AcqRel) {
+                self.watch.begin_block_on();
+                let poll_result = coop::with_budget(|| future.as_mut().poll(&mut context));
+                if let Poll::Ready(output) = poll_result {
+                    self.watch.idle();
+                    return output;
+                }
+            }
+            self.watch.scheduling();
+            self.run_ready_tasks();
+            self.update_stats_snapshot();
+            self.sleep_if_idle(|| woken.woken.load(Ordering::Acquire));
+        }
+    }
+
+    // 检查放在调度之后、停机之前，条件满足后不会再停机等待中断
+    fn run_until_state(&mut self, mut done: impl FnMut(&Self) -> bool) {
+        loop {
+            self.watch.scheduling();
             self.run_ready_tasks();
             self.update_stats_snapshot();
-            self.sleep_if_idle();
+            if done(self) {
+                // 控制权交还调用者后不再观察
+                self.watch.idle();
+                return;
+            }
+            self.sleep_if_idle(|| false);
         }
     }
 
     pub fn stats(&self) -> ExecutorStats {
         ExecutorStats {
             active_tasks: self.tasks.len(),
-            queued_high_tasks: self.high_priority_task_queue.len(),
-            queued_normal_tasks: self.normal_priority_task_queue.len(),
+            queued_tasks: self.ready_queues.lens(),
             cached_wakers: self.waker_cache.len(),
             dropped_wakes: DROPPED_WAKE_COUNT.load(Ordering::Relaxed),
+            slow_polls: SLOW_POLL_COUNT.load(Ordering::Relaxed),
+            forced_yields: coop::forced_yields(),
+            wake_latency: latency::snapshot(),
         }
     }
 
     fn update_stats_snapshot(&self) {
         LAST_ACTIVE_TASKS.store(self.tasks.len() as u64, Ordering::Relaxed);
-        LAST_QUEUED_HIGH_TASKS.store(self.high_priority_task_queue.len() as u64, Ordering::Relaxed);
-        LAST_QUEUED_NORMAL_TASKS
-            .store(self.normal_priority_task_queue.len() as u64, Ordering::Relaxed);
+        for (snapshot, len) in LAST_QUEUED_TASKS.iter().zip(self.ready_queues.lens()) {
+            snapshot.store(len as u64, Ordering::Relaxed);
+        }
         LAST_CACHED_WAKERS.store(self.waker_cache.len() as u64, Ordering::Relaxed);
     }
 
-    fn sleep_if_idle(&self) {
-        use x86_64::instructions::interrupts::{self, enable_and_hlt};
+    // `has_work` 在关中断后检查执行器之外的待办工作，例如 `block_on` 的 future 被唤醒
+    fn sleep_if_idle(&self, has_work: impl Fn() -> bool) {
+        use x86_64::instructions::interrupts;
 
         interrupts::disable();
-        if self.high_priority_task_queue.is_empty() && self.normal_priority_task_queue.is_empty() {
-            enable_and_hlt();
+        if self.ready_queues.is_empty() && self.injector.is_empty() && !has_work() {
+            self.watch.idle();
+            // 运行在内核线程上时，空闲的时间片让给其他就绪线程
+            if crate::thread::has_ready_threads() {
+                interrupts::enable();
+                crate::thread::yield_now();
+            } else {
+                // 启用无滴答空闲时，停机到最近的定时器期限
+                timer::idle();
+            }
         } else {
             interrupts::enable();
         }
@@ -179,48 +483,247 @@ impl Executor {
     // }
 }
 
+impl Drop for Executor {
+    // 执行器被丢弃时，其中的任务也从全局记账表中移除
+    fn drop(&mut self) {
+        for slot in self.tasks.values() {
+            slot.accounting.unregister();
+        }
+    }
+}
+
+/// 向执行器注入任务的句柄，可以克隆并在任意任务或中断处理程序中使用
+///
+/// 任务先占用执行器的名额，再进入无锁的注入队列，执行器在下一轮调度时把它们加入任务表。
+#[derive(Clone)]
+pub struct Spawner {
+    injector: Arc<TaskQueue<InjectedTask>>,
+    capacity: Arc<Capacity>,
+}
+
+impl Spawner {
+    /// 添加一个普通优先级任务，返回等待其输出的句柄
+    ///
+    /// # Panics
+    /// 如果执行器的任务数已达上限，会panic
+    pub fn spawn<F>(&self, future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        self.try_spawn(future)
+            .expect("failed to spawn task through spawner")
+    }
+
+    /// 尝试添加一个普通优先级任务，任务数已达上限时返回 [`SpawnError::QueueFull`]
+    pub fn try_spawn<F>(&self, future: F) -> Result<JoinHandle<F::Output>, SpawnError>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        self.try_spawn_with_priority(future, TaskPriority::Normal)
+    }
+
+    pub fn try_spawn_with_priority<F>(
+        &self,
+        future: F,
+        priority: TaskPriority,
+    ) -> Result<JoinHandle<F::Output>, SpawnError>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        self.try_spawn_with_locals(future, priority, Locals::new())
+    }
+
+    /// 同 [`Spawner::try_spawn_with_priority`]，新任务带有 `locals` 中的局部变量
+    pub fn try_spawn_with_locals<F>(
+        &self,
+        future: F,
+        priority: TaskPriority,
+        locals: Locals,
+    ) -> Result<JoinHandle<F::Output>, SpawnError>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        if !self.capacity.try_reserve(priority) {
+            return Err(SpawnError::QueueFull);
+        }
+        Ok(self.inject_reserved(future, priority, locals))
+    }
+
+    /// 添加一个普通优先级任务；任务数已达上限时异步等待其他任务结束，而不是失败
+    pub async fn spawn_when_available<F>(&self, future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        self.spawn_when_available_with_priority(future, TaskPriority::Normal)
+            .await
+    }
+
+    pub async fn spawn_when_available_with_priority<F>(
+        &self,
+        future: F,
+        priority: TaskPriority,
+    ) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        self.spawn_when_available_with_locals(future, priority, Locals::new())
+            .await
+    }
+
+    /// 同 [`Spawner::spawn_when_available_with_priority`]，新任务带有 `locals` 中的局部变量
+    pub async fn spawn_when_available_with_locals<F>(
+        &self,
+        future: F,
+        priority: TaskPriority,
+        locals: Locals,
+    ) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        core::future::poll_fn(|context| {
+            if self.capacity.try_reserve(priority) {
+                return Poll::Ready(());
+            }
+            self.capacity.register_waiter(context.waker());
+            // 登记之后再试一次，避免错过登记之前归还的名额
+            if self.capacity.try_reserve(priority) {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        })
+        .await;
+        self.inject_reserved(future, priority, locals)
+    }
+
+    /// 执行器当前持有（含尚未取出的注入任务）的任务数
+    pub fn live_tasks(&self) -> usize {
+        self.capacity.live_tasks()
+    }
+
+    fn inject_reserved<F>(
+        &self,
+        future: F,
+        priority: TaskPriority,
+        locals: Locals,
+    ) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        let (task, handle) = Task::joinable_with_priority(future, priority);
+        let injected = InjectedTask {
+            task: task.with_locals(locals),
+            reserved: priority,
+        };
+        // 注入队列容量不小于任务上限，占用名额后入队一定成功
+        if self.injector.push(injected).is_err() {
+            unreachable!("injector capacity covers every reserved task");
+        }
+        handle
+    }
+}
+
+// 每个优先级一个就绪队列，由执行器和所有唤醒器共享
+struct ReadyQueues {
+    levels: [TaskQueue<TaskId>; TaskPriority::LEVELS],
+}
+
+impl ReadyQueues {
+    fn new(config: &ExecutorConfig) -> Self {
+        ReadyQueues {
+            levels: core::array::from_fn(|_| TaskQueue::new(config)),
+        }
+    }
+
+    fn push(&self, task_id: TaskId, priority: TaskPriority) -> Result<(), TaskId> {
+        self.levels[priority.level()].push(task_id)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.levels.iter().all(|queue| queue.is_empty())
+    }
+
+    fn lens(&self) -> [usize; TaskPriority::LEVELS] {
+        core::array::from_fn(|level| self.levels[level].len())
+    }
+}
+
 // TaskWaker is a waker that sends the task_id to the task_queue when woken.
 struct TaskWaker {
     task_id: TaskId,
-    task_priority: TaskPriority,
-    high_priority_task_queue: Arc<ArrayQueue<TaskId>>,
-    normal_priority_task_queue: Arc<ArrayQueue<TaskId>>,
+    task_priority: SharedPriority,
+    scheduled: Arc<AtomicBool>,
+    accounting: Arc<TaskAccounting>,
+    ready_queues: Arc<ReadyQueues>,
 }
 
 impl TaskWaker {
     fn new(
         task_id: TaskId,
-        task_priority: TaskPriority,
-        high_priority_task_queue: Arc<ArrayQueue<TaskId>>,
-        normal_priority_task_queue: Arc<ArrayQueue<TaskId>>,
+        task_priority: SharedPriority,
+        scheduled: Arc<AtomicBool>,
+        accounting: Arc<TaskAccounting>,
+        ready_queues: Arc<ReadyQueues>,
     ) -> Waker {
         Waker::from(Arc::new(TaskWaker {
             task_id,
             task_priority,
-            high_priority_task_queue,
-            normal_priority_task_queue,
+            scheduled,
+            accounting,
+            ready_queues,
         }))
     }
 
     fn wake_task(&self) {
-        // 任务已经在队列中时，重复唤醒可安全丢弃，避免队列满导致panic。
-        let push_result = match self.task_priority {
-            TaskPriority::High => self.high_priority_task_queue.push(self.task_id),
-            TaskPriority::Normal => self.normal_priority_task_queue.push(self.task_id),
-        };
-        if push_result.is_err() {
+        self.accounting.record_wake();
+        // 任务已经在队列中（或已结束）时，这次唤醒已被覆盖，无需再入队
+        if self.scheduled.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        self.accounting.mark_woken(accounting::read_tsc());
+        // 每个任务至多一项且任务数不超过容量，正常情况下不会失败
+        if self
+            .ready_queues
+            .push(self.task_id, self.task_priority.get())
+            .is_err()
+        {
             DROPPED_WAKE_COUNT.fetch_add(1, Ordering::Relaxed);
+            trace::record(TraceKind::DroppedWake, self.task_id.as_u64());
+        } else {
+            trace::record(TraceKind::Wake, self.task_id.as_u64());
         }
     }
 }
 
+// 选出有效优先级最高的非空队列：级别编号减去老化提升的级数，相同时取原本级别更高者。
+// 只有执行器会出队，所以选中的队列在出队时一定非空。
 fn pop_next_task_id(
-    high_priority_task_queue: &Arc<ArrayQueue<TaskId>>,
-    normal_priority_task_queue: &Arc<ArrayQueue<TaskId>>,
+    ready_queues: &ReadyQueues,
+    skipped_rounds: &mut [u32; TaskPriority::LEVELS],
 ) -> Option<TaskId> {
-    high_priority_task_queue
-        .pop()
-        .or_else(|| normal_priority_task_queue.pop())
+    let chosen = (0..TaskPriority::LEVELS)
+        .filter(|&level| !ready_queues.levels[level].is_empty())
+        .min_by_key(|&level| {
+            let boost = (skipped_rounds[level] / AGING_STEP) as i64;
+            (level as i64 - boost, level)
+        })?;
+
+    for (level, skipped) in skipped_rounds.iter_mut().enumerate() {
+        if level != chosen && !ready_queues.levels[level].is_empty() {
+            *skipped = skipped.saturating_add(1);
+        } else {
+            *skipped = 0;
+        }
+    }
+    ready_queues.levels[chosen].pop()
 }
 
 impl Wake for TaskWaker {
@@ -232,3 +735,18 @@ impl Wake for TaskWaker {
         self.wake_task();
     }
 }
+
+// `block_on` 驱动的 future 的唤醒器：只记录需要再次轮询，由 `block_on` 的循环检查
+struct BlockOnWaker {
+    woken: AtomicBool,
+}
+
+impl Wake for BlockOnWaker {
+    fn wake(self: Arc<Self>) {
+        self.woken.store(true, Ordering::Release);
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.woken.store(true, Ordering::Release);
+    }
+}
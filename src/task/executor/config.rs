@@ -0,0 +1,227 @@
+//! 执行器的容量配置。
+//!
+//! [`ExecutorConfig`] 决定就绪队列是定长还是可增长、最多容纳多少任务，以及每个优先级
+//! 最多同时存在多少任务。容量由执行器和所有 `Spawner` 共享的 [`Capacity`] 计数，
+//! 任务结束时归还名额并唤醒等待名额的派生者。
+
+use super::MAX_TASKS;
+use crate::task::TaskPriority;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use core::task::Waker;
+use crossbeam_queue::{ArrayQueue, SegQueue};
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+/// 就绪队列与注入队列的实现方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueueMode {
+    /// 定长无锁队列，入队不分配内存，可以在任何中断中唤醒任务
+    Bounded,
+    /// 按需增长的无锁队列，入队可能分配内存
+    Growable,
+}
+
+/// 执行器配置
+///
+/// 各项设置与调用顺序无关，队列模式与任务上限在创建执行器时确定。
+///
+/// ```ignore
+/// let executor = ExecutorConfig::new()
+///     .queue_capacity(32)
+///     .priority_limit(TaskPriority::Idle, 4)
+///     .build();
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExecutorConfig {
+    pub(super) mode: QueueMode,
+    // 未设置时按模式取默认值，见 `task_limit`
+    pub(super) max_tasks: Option<usize>,
+    pub(super) priority_limits: [usize; TaskPriority::LEVELS],
+    pub(super) slow_poll_cycles: u64,
+}
+
+/// 默认的慢轮询阈值（TSC 周期），在 GHz 级的处理器上约为几十毫秒
+pub const DEFAULT_SLOW_POLL_CYCLES: u64 = 100_000_000;
+
+impl ExecutorConfig {
+    /// 默认配置：定长队列，最多 [`MAX_TASKS`] 个任务，各优先级不单独限制，
+    /// 慢轮询阈值为 [`DEFAULT_SLOW_POLL_CYCLES`]
+    pub fn new() -> Self {
+        ExecutorConfig {
+            mode: QueueMode::Bounded,
+            max_tasks: None,
+            priority_limits: [usize::MAX; TaskPriority::LEVELS],
+            slow_poll_cycles: DEFAULT_SLOW_POLL_CYCLES,
+        }
+    }
+
+    /// 使用定长队列，每个就绪队列的容量为 `capacity`，它同时是任务总数上限
+    pub fn queue_capacity(self, capacity: usize) -> Self {
+        let mut config = self.max_tasks(capacity);
+        config.mode = QueueMode::Bounded;
+        config
+    }
+
+    /// 使用可增长队列，任务总数默认不设上限（仍可由 `max_tasks` 限制）
+    pub fn growable(mut self) -> Self {
+        self.mode = QueueMode::Growable;
+        self
+    }
+
+    /// 任务总数上限；定长模式下它同时是每个就绪队列的容量
+    pub fn max_tasks(mut self, max_tasks: usize) -> Self {
+        assert!(max_tasks > 0, "task limit must be positive");
+        self.max_tasks = Some(max_tasks);
+        self
+    }
+
+    /// 限制以 `priority` 派生的任务同时存在的数量
+    pub fn priority_limit(mut self, priority: TaskPriority, limit: usize) -> Self {
+        self.priority_limits[priority.level()] = limit;
+        self
+    }
+
+    /// 单次轮询超过 `cycles` 个 TSC 周期的任务被计为慢轮询，由系统工作队列在串口输出一行警告
+    pub fn slow_poll_threshold(mut self, cycles: u64) -> Self {
+        self.slow_poll_cycles = cycles;
+        self
+    }
+
+    pub fn mode(&self) -> QueueMode {
+        self.mode
+    }
+
+    /// 任务总数上限：未设置时定长模式为 [`MAX_TASKS`]，可增长模式不设上限
+    pub fn task_limit(&self) -> usize {
+        match (self.max_tasks, self.mode) {
+            (Some(max_tasks), _) => max_tasks,
+            (None, QueueMode::Bounded) => MAX_TASKS,
+            (None, QueueMode::Growable) => usize::MAX,
+        }
+    }
+
+    pub fn build(self) -> super::Executor {
+        super::Executor::with_config(self)
+    }
+}
+
+impl Default for ExecutorConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// 按配置选择的无锁队列
+pub(super) enum TaskQueue<T> {
+    Bounded(ArrayQueue<T>),
+    Growable(SegQueue<T>),
+}
+
+impl<T> TaskQueue<T> {
+    pub(super) fn new(config: &ExecutorConfig) -> Self {
+        match config.mode {
+            QueueMode::Bounded => TaskQueue::Bounded(ArrayQueue::new(config.task_limit())),
+            QueueMode::Growable => TaskQueue::Growable(SegQueue::new()),
+        }
+    }
+
+    pub(super) fn push(&self, value: T) -> Result<(), T> {
+        match self {
+            TaskQueue::Bounded(queue) => queue.push(value),
+            TaskQueue::Growable(queue) => {
+                queue.push(value);
+                Ok(())
+            }
+        }
+    }
+
+    pub(super) fn pop(&self) -> Option<T> {
+        match self {
+            TaskQueue::Bounded(queue) => queue.pop(),
+            TaskQueue::Growable(queue) => queue.pop(),
+        }
+    }
+
+    pub(super) fn len(&self) -> usize {
+        match self {
+            TaskQueue::Bounded(queue) => queue.len(),
+            TaskQueue::Growable(queue) => queue.len(),
+        }
+    }
+
+    pub(super) fn is_empty(&self) -> bool {
+        match self {
+            TaskQueue::Bounded(queue) => queue.is_empty(),
+            TaskQueue::Growable(queue) => queue.is_empty(),
+        }
+    }
+}
+
+// 执行器与 Spawner 共享的任务名额
+pub(super) struct Capacity {
+    max_tasks: usize,
+    priority_limits: [usize; TaskPriority::LEVELS],
+    live_tasks: AtomicUsize,
+    live_by_priority: [AtomicUsize; TaskPriority::LEVELS],
+    // 等待名额的派生者，名额归还时全部唤醒
+    waiters: Mutex<Vec<Waker>>,
+}
+
+impl Capacity {
+    pub(super) fn new(config: &ExecutorConfig) -> Self {
+        Capacity {
+            max_tasks: config.task_limit(),
+            priority_limits: config.priority_limits,
+            live_tasks: AtomicUsize::new(0),
+            live_by_priority: [const { AtomicUsize::new(0) }; TaskPriority::LEVELS],
+            waiters: Mutex::new(Vec::new()),
+        }
+    }
+
+    // 占用一个名额；总数或该优先级已满时返回 false。只使用原子操作，可以在中断中调用
+    pub(super) fn try_reserve(&self, priority: TaskPriority) -> bool {
+        if !increment_below(&self.live_tasks, self.max_tasks) {
+            return false;
+        }
+        let level = priority.level();
+        if !increment_below(&self.live_by_priority[level], self.priority_limits[level]) {
+            self.live_tasks.fetch_sub(1, Ordering::AcqRel);
+            return false;
+        }
+        true
+    }
+
+    pub(super) fn release(&self, priority: TaskPriority) {
+        self.live_by_priority[priority.level()].fetch_sub(1, Ordering::AcqRel);
+        self.live_tasks.fetch_sub(1, Ordering::AcqRel);
+        let waiters = interrupts::without_interrupts(|| core::mem::take(&mut *self.waiters.lock()));
+        for waker in waiters {
+            waker.wake();
+        }
+    }
+
+    pub(super) fn register_waiter(&self, waker: &Waker) {
+        interrupts::without_interrupts(|| {
+            let mut waiters = self.waiters.lock();
+            if !waiters.iter().any(|waiter| waiter.will_wake(waker)) {
+                waiters.push(waker.clone());
+            }
+        });
+    }
+
+    pub(super) fn live_tasks(&self) -> usize {
+        self.live_tasks.load(Ordering::Acquire)
+    }
+}
+
+fn increment_below(counter: &AtomicUsize, limit: usize) -> bool {
+    let mut value = counter.load(Ordering::Acquire);
+    while value < limit {
+        match counter.compare_exchange_weak(value, value + 1, Ordering::AcqRel, Ordering::Acquire) {
+            Ok(_) => return true,
+            Err(current) => value = current,
+        }
+    }
+    false
+}
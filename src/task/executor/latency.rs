@@ -0,0 +1,106 @@
+//! 唤醒到轮询的延迟直方图。
+//!
+//! 唤醒器把任务放入就绪队列时记录 TSC，执行器轮询前再读一次，两者之差按任务优先级
+//! 计入全局直方图。桶按 2 的幂划分，第一个桶覆盖不到 [`FIRST_BUCKET_CYCLES`] 个周期，
+//! 之后每个桶的上界翻倍，最后一个桶收纳所有更长的延迟。
+
+use crate::task::TaskPriority;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// 直方图的桶数
+pub const LATENCY_BUCKETS: usize = 20;
+/// 第一个桶的上界（TSC 周期，不含）
+pub const FIRST_BUCKET_CYCLES: u64 = 1 << 10;
+
+static WAKE_LATENCY: [AtomicHistogram; TaskPriority::LEVELS] =
+    [const { AtomicHistogram::new() }; TaskPriority::LEVELS];
+
+/// 某个优先级的唤醒延迟分布，单位为 TSC 周期
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LatencyHistogram {
+    /// 第 `i` 个桶统计落在 `[bucket_upper_bound(i - 1), bucket_upper_bound(i))` 的样本
+    pub buckets: [u64; LATENCY_BUCKETS],
+    pub count: u64,
+    pub total_cycles: u64,
+    pub max_cycles: u64,
+}
+
+impl LatencyHistogram {
+    /// 第 `index` 个桶的上界（不含）；最后一个桶没有上界，返回 `u64::MAX`
+    pub fn bucket_upper_bound(index: usize) -> u64 {
+        if index + 1 >= LATENCY_BUCKETS {
+            u64::MAX
+        } else {
+            FIRST_BUCKET_CYCLES << index
+        }
+    }
+
+    pub fn mean_cycles(&self) -> u64 {
+        self.total_cycles.checked_div(self.count).unwrap_or(0)
+    }
+
+    /// 至少 `percent`% 的样本小于返回值；取桶的上界，最后一个桶取最大值。没有样本时返回 0
+    pub fn percentile(&self, percent: u64) -> u64 {
+        if self.count == 0 {
+            return 0;
+        }
+        let target = (self.count * percent.min(100)).div_ceil(100).max(1);
+        let mut seen = 0;
+        for (index, &count) in self.buckets.iter().enumerate() {
+            seen += count;
+            if seen >= target {
+                return Self::bucket_upper_bound(index).min(self.max_cycles);
+            }
+        }
+        self.max_cycles
+    }
+}
+
+struct AtomicHistogram {
+    buckets: [AtomicU64; LATENCY_BUCKETS],
+    count: AtomicU64,
+    total_cycles: AtomicU64,
+    max_cycles: AtomicU64,
+}
+
+impl AtomicHistogram {
+    const fn new() -> Self {
+        AtomicHistogram {
+            buckets: [const { AtomicU64::new(0) }; LATENCY_BUCKETS],
+            count: AtomicU64::new(0),
+            total_cycles: AtomicU64::new(0),
+            max_cycles: AtomicU64::new(0),
+        }
+    }
+
+    fn record(&self, cycles: u64) {
+        self.buckets[bucket_index(cycles)].fetch_add(1, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.total_cycles.fetch_add(cycles, Ordering::Relaxed);
+        self.max_cycles.fetch_max(cycles, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> LatencyHistogram {
+        LatencyHistogram {
+            buckets: core::array::from_fn(|index| self.buckets[index].load(Ordering::Relaxed)),
+            count: self.count.load(Ordering::Relaxed),
+            total_cycles: self.total_cycles.load(Ordering::Relaxed),
+            max_cycles: self.max_cycles.load(Ordering::Relaxed),
+        }
+    }
+}
+
+fn bucket_index(cycles: u64) -> usize {
+    let doublings = (cycles / FIRST_BUCKET_CYCLES)
+        .checked_ilog2()
+        .map_or(0, |log| log as usize + 1);
+    doublings.min(LATENCY_BUCKETS - 1)
+}
+
+pub(super) fn record(priority: TaskPriority, cycles: u64) {
+    WAKE_LATENCY[priority.level()].record(cycles);
+}
+
+pub(super) fn snapshot() -> [LatencyHistogram; TaskPriority::LEVELS] {
+    core::array::from_fn(|level| WAKE_LATENCY[level].snapshot())
+}
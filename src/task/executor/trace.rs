@@ -0,0 +1,199 @@
+//! 调度事件跟踪。
+//!
+//! 执行器与唤醒器把调度事件（派生、唤醒、轮询开始/结束、完成、丢弃的唤醒）连同 tick 和任务ID
+//! 写入定长的环形缓冲区，最多保留最近 [`TRACE_CAPACITY`] 条。写入不加锁、不分配内存，
+//! 可以在中断处理程序中进行：每条记录先写内容、最后写序号，读取时序号前后一致才算有效。
+//! [`dump_trace`] 把缓冲区以文本或二进制格式输出到串口，内核 panic 时也会输出一次。
+
+use crate::task::timer;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use x86_64::instructions::interrupts;
+
+/// 环形缓冲区保留的事件数
+pub const TRACE_CAPACITY: usize = 256;
+// 二进制格式的文件头
+const BINARY_MAGIC: &[u8; 4] = b"TRC1";
+// 序号为 0 的槽位还没有写入过
+const EMPTY: u64 = 0;
+
+static ENABLED: AtomicBool = AtomicBool::new(true);
+// 下一条事件的序号，从 1 开始
+static NEXT_SEQUENCE: AtomicU64 = AtomicU64::new(1);
+static SLOTS: [Slot; TRACE_CAPACITY] = [const { Slot::new() }; TRACE_CAPACITY];
+
+/// 调度事件的种类
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum TraceKind {
+    Spawn = 1,
+    Wake,
+    PollStart,
+    PollEnd,
+    Complete,
+    /// 唤醒时就绪队列已满，任务没有入队
+    DroppedWake,
+    /// 单次轮询超过慢轮询阈值
+    SlowPoll,
+}
+
+impl TraceKind {
+    fn from_u8(value: u8) -> Option<TraceKind> {
+        Some(match value {
+            1 => TraceKind::Spawn,
+            2 => TraceKind::Wake,
+            3 => TraceKind::PollStart,
+            4 => TraceKind::PollEnd,
+            5 => TraceKind::Complete,
+            6 => TraceKind::DroppedWake,
+            7 => TraceKind::SlowPoll,
+            _ => return None,
+        })
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            TraceKind::Spawn => "spawn",
+            TraceKind::Wake => "wake",
+            TraceKind::PollStart => "poll",
+            TraceKind::PollEnd => "pend",
+            TraceKind::Complete => "done",
+            TraceKind::DroppedWake => "drop",
+            TraceKind::SlowPoll => "slow",
+        }
+    }
+}
+
+/// 一条调度事件
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceEvent {
+    /// 全局递增的序号，序号不连续说明中间的事件已被覆盖
+    pub sequence: u64,
+    pub tick: u64,
+    pub kind: TraceKind,
+    pub task_id: u64,
+}
+
+/// [`dump_trace`] 的输出格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceFormat {
+    /// 每行一条：`[trace] 序号 t=tick 事件 #任务ID`
+    Text,
+    /// `TRC1`、小端 `u32` 事件数，然后每条事件为小端的 `u64` 序号、`u64` tick、
+    /// `u8` 事件种类与 `u64` 任务ID
+    Binary,
+}
+
+struct Slot {
+    sequence: AtomicU64,
+    tick: AtomicU64,
+    // 高 8 位是事件种类，低 56 位是任务ID
+    packed: AtomicU64,
+}
+
+impl Slot {
+    const fn new() -> Self {
+        Slot {
+            sequence: AtomicU64::new(EMPTY),
+            tick: AtomicU64::new(0),
+            packed: AtomicU64::new(0),
+        }
+    }
+
+    fn read(&self) -> Option<TraceEvent> {
+        let sequence = self.sequence.load(Ordering::Acquire);
+        if sequence == EMPTY {
+            return None;
+        }
+        let tick = self.tick.load(Ordering::Relaxed);
+        let packed = self.packed.load(Ordering::Relaxed);
+        // 读取期间被覆盖的记录丢弃
+        if self.sequence.load(Ordering::Acquire) != sequence {
+            return None;
+        }
+        Some(TraceEvent {
+            sequence,
+            tick,
+            kind: TraceKind::from_u8((packed >> 56) as u8)?,
+            task_id: packed & ((1 << 56) - 1),
+        })
+    }
+}
+
+/// 开启或关闭事件记录，返回之前的状态
+pub fn set_tracing(enabled: bool) -> bool {
+    ENABLED.swap(enabled, Ordering::Relaxed)
+}
+
+pub(super) fn record(kind: TraceKind, task_id: u64) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    let sequence = NEXT_SEQUENCE.fetch_add(1, Ordering::Relaxed);
+    let slot = &SLOTS[(sequence % TRACE_CAPACITY as u64) as usize];
+    slot.sequence.store(EMPTY, Ordering::Release);
+    slot.tick.store(timer::current_tick(), Ordering::Relaxed);
+    slot.packed.store(
+        (kind as u64) << 56 | task_id & ((1 << 56) - 1),
+        Ordering::Relaxed,
+    );
+    slot.sequence.store(sequence, Ordering::Release);
+}
+
+/// 缓冲区中仍然保留的事件，按序号从旧到新排列
+///
+/// 需要分配内存，供测试检查；输出到串口请用不分配内存的 [`dump_trace`]。
+pub fn trace_snapshot() -> Vec<TraceEvent> {
+    events_in_order().collect()
+}
+
+// 从序号最小的槽位开始按环形顺序读取，槽位由序号对容量取模决定，因此顺序与序号一致
+fn events_in_order() -> impl Iterator<Item = TraceEvent> {
+    let oldest = SLOTS
+        .iter()
+        .enumerate()
+        .filter_map(|(index, slot)| slot.read().map(|event| (event.sequence, index)))
+        .min()
+        .map_or(0, |(_, index)| index);
+    (0..TRACE_CAPACITY).filter_map(move |offset| SLOTS[(oldest + offset) % TRACE_CAPACITY].read())
+}
+
+/// 把缓冲区输出到串口
+///
+/// 不分配内存，可以在 panic 处理程序中调用；输出期间暂停记录，事件数与内容保持一致。
+pub fn dump_trace(format: TraceFormat) {
+    let was_enabled = set_tracing(false);
+    let count = events_in_order().count();
+    match format {
+        TraceFormat::Text => {
+            crate::serial_println!("[trace] {} events", count);
+            for event in events_in_order() {
+                crate::serial_println!(
+                    "[trace] {} t={} {} #{}",
+                    event.sequence,
+                    event.tick,
+                    event.kind.label(),
+                    event.task_id,
+                );
+            }
+        }
+        // 与 `serial_print!` 一样在关中断时持有串口锁
+        TraceFormat::Binary => interrupts::without_interrupts(|| {
+            let mut serial = crate::serial::SERIAL1.lock();
+            let mut send = |bytes: &[u8]| {
+                for &byte in bytes {
+                    serial.send(byte);
+                }
+            };
+            send(BINARY_MAGIC);
+            send(&(count as u32).to_le_bytes());
+            for event in events_in_order().take(count) {
+                send(&event.sequence.to_le_bytes());
+                send(&event.tick.to_le_bytes());
+                send(&[event.kind as u8]);
+                send(&event.task_id.to_le_bytes());
+            }
+        }),
+    }
+    set_tracing(was_enabled);
+}
@@ -0,0 +1,165 @@
+//! # 任务组
+//!
+//! [`TaskGroup`] 通过 [`Spawner`] 派生一组子任务，并把它们的生命周期绑定在一起：
+//! 可以等待全部子任务结束（[`TaskGroup::join_all`]）、按完成顺序逐个取出结果
+//! （[`TaskGroup::join_next`]），或者在第一个失败时取消其余子任务（[`TaskGroup::try_join_all`]）。
+//! 任务组被丢弃时，尚未结束的子任务全部被取消，因此一组服务可以一起启动、一起拆除。
+
+use super::executor::{SpawnError, Spawner};
+use super::{JoinError, JoinHandle, Locals, TaskId, TaskPriority};
+use alloc::vec::Vec;
+use core::future::{poll_fn, Future};
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+/// [`TaskGroup::try_join_all`] 的失败原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GroupError<E> {
+    /// 某个子任务返回了错误
+    Failed(E),
+    /// 某个子任务在完成前被取消
+    Cancelled,
+}
+
+/// 一组一起等待、一起取消的子任务
+pub struct TaskGroup<T> {
+    spawner: Spawner,
+    // 尚未取出结果的子任务及其派生序号
+    children: Vec<(usize, JoinHandle<T>)>,
+    next_index: usize,
+}
+
+impl<T: Send + 'static> TaskGroup<T> {
+    /// 创建空的任务组，子任务通过 `spawner` 派生到对应的执行器
+    pub fn new(spawner: Spawner) -> Self {
+        TaskGroup {
+            spawner,
+            children: Vec::new(),
+            next_index: 0,
+        }
+    }
+
+    /// 派生一个普通优先级的子任务
+    ///
+    /// # Panics
+    /// 如果执行器的任务数已达上限，会panic
+    pub fn spawn<F>(&mut self, future: F) -> TaskId
+    where
+        F: Future<Output = T> + Send + 'static,
+    {
+        self.try_spawn_with_priority(future, TaskPriority::Normal)
+            .expect("failed to spawn task into group")
+    }
+
+    pub fn try_spawn_with_priority<F>(
+        &mut self,
+        future: F,
+        priority: TaskPriority,
+    ) -> Result<TaskId, SpawnError>
+    where
+        F: Future<Output = T> + Send + 'static,
+    {
+        self.try_spawn_with_locals(future, priority, Locals::new())
+    }
+
+    /// 同 [`TaskGroup::try_spawn_with_priority`]，子任务带有 `locals` 中的局部变量
+    pub fn try_spawn_with_locals<F>(
+        &mut self,
+        future: F,
+        priority: TaskPriority,
+        locals: Locals,
+    ) -> Result<TaskId, SpawnError>
+    where
+        F: Future<Output = T> + Send + 'static,
+    {
+        let handle = self
+            .spawner
+            .try_spawn_with_locals(future, priority, locals)?;
+        let id = handle.id();
+        self.children.push((self.next_index, handle));
+        self.next_index += 1;
+        Ok(id)
+    }
+
+    /// 尚未取出结果的子任务数
+    pub fn len(&self) -> usize {
+        self.children.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.children.is_empty()
+    }
+
+    /// 请求取消所有尚未取出结果的子任务，它们的结果随后为 [`JoinError::Cancelled`]
+    pub fn abort_all(&self) {
+        for (_, handle) in &self.children {
+            handle.abort();
+        }
+    }
+
+    /// 等待下一个结束的子任务并取出其结果；没有子任务时返回 `None`
+    pub async fn join_next(&mut self) -> Option<Result<T, JoinError>> {
+        poll_fn(|context| self.poll_join_next(context))
+            .await
+            .map(|(_, result)| result)
+    }
+
+    /// 等待所有子任务结束，结果按派生顺序排列
+    pub async fn join_all(mut self) -> Vec<Result<T, JoinError>> {
+        let mut results = Vec::with_capacity(self.children.len());
+        while let Some(finished) = poll_fn(|context| self.poll_join_next(context)).await {
+            results.push(finished);
+        }
+        results.sort_unstable_by_key(|(index, _)| *index);
+        results.into_iter().map(|(_, result)| result).collect()
+    }
+
+    // 轮询所有子任务，取出最先结束的一个及其派生序号
+    fn poll_join_next(
+        &mut self,
+        context: &mut Context<'_>,
+    ) -> Poll<Option<(usize, Result<T, JoinError>)>> {
+        if self.children.is_empty() {
+            return Poll::Ready(None);
+        }
+        for position in 0..self.children.len() {
+            if let Poll::Ready(result) = Pin::new(&mut self.children[position].1).poll(context) {
+                let (index, _) = self.children.remove(position);
+                return Poll::Ready(Some((index, result)));
+            }
+        }
+        Poll::Pending
+    }
+}
+
+impl<T: Send + 'static, E: Send + 'static> TaskGroup<Result<T, E>> {
+    /// 等待所有子任务成功，结果按派生顺序排列
+    ///
+    /// 任何一个子任务返回错误或被取消时，立即取消其余子任务并返回该失败。
+    pub async fn try_join_all(mut self) -> Result<Vec<T>, GroupError<E>> {
+        let mut outputs = Vec::with_capacity(self.children.len());
+        while let Some((index, result)) = poll_fn(|context| self.poll_join_next(context)).await {
+            match result {
+                Ok(Ok(output)) => outputs.push((index, output)),
+                Ok(Err(error)) => {
+                    self.abort_all();
+                    return Err(GroupError::Failed(error));
+                }
+                Err(JoinError::Cancelled) => {
+                    self.abort_all();
+                    return Err(GroupError::Cancelled);
+                }
+            }
+        }
+        outputs.sort_unstable_by_key(|(index, _)| *index);
+        Ok(outputs.into_iter().map(|(_, output)| output).collect())
+    }
+}
+
+impl<T> Drop for TaskGroup<T> {
+    fn drop(&mut self) {
+        for (_, handle) in &self.children {
+            handle.abort();
+        }
+    }
+}
@@ -0,0 +1,175 @@
+//! # 任务的 JoinHandle
+//!
+//! 把任意 `Future` 包装成执行器能调度的 [`Task`]，并返回一个 [`JoinHandle`]。
+//! 任务结束时输出写入共享状态并唤醒等待者；任务在完成前被丢弃（例如执行器被销毁）时，
+//! 等待者得到 [`JoinError::Cancelled`]。
+
+use super::{AbortHandle, SharedPriority, Task, TaskId, TaskPriority};
+use alloc::sync::Arc;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+use spin::Mutex;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JoinError {
+    /// 任务在完成前被丢弃
+    Cancelled,
+}
+
+struct JoinState<T> {
+    result: Option<Result<T, JoinError>>,
+    finished: bool,
+    // JoinHandle 被丢弃后不再保存输出
+    detached: bool,
+    waker: Option<Waker>,
+}
+
+type Shared<T> = Arc<Mutex<JoinState<T>>>;
+
+/// 等待任务结束并取得其输出的句柄
+///
+/// 句柄本身是一个 future；丢弃句柄（或调用 [`JoinHandle::detach`]）不会影响任务运行，
+/// 只是任务的输出会被直接丢弃。
+pub struct JoinHandle<T> {
+    id: TaskId,
+    state: Shared<T>,
+    abort: AbortHandle,
+    priority: SharedPriority,
+}
+
+impl<T> JoinHandle<T> {
+    /// 对应任务的ID
+    pub fn id(&self) -> TaskId {
+        self.id
+    }
+
+    /// 任务是否已经结束（正常完成或被取消）
+    pub fn is_finished(&self) -> bool {
+        self.state.lock().finished
+    }
+
+    /// 任务当前的优先级
+    pub fn priority(&self) -> TaskPriority {
+        self.priority.get()
+    }
+
+    /// 在任务运行期间修改其优先级，从任务下一次被唤醒开始生效
+    pub fn set_priority(&self, priority: TaskPriority) {
+        self.priority.set(priority);
+    }
+
+    /// 放弃等待，任务继续运行但输出被丢弃
+    pub fn detach(self) {}
+
+    /// 请求取消任务，等待者随后得到 [`JoinError::Cancelled`]
+    pub fn abort(&self) {
+        self.abort.abort();
+    }
+
+    /// 不依赖本句柄也能取消任务的句柄
+    pub fn abort_handle(&self) -> AbortHandle {
+        self.abort.clone()
+    }
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = Result<T, JoinError>;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.state.lock();
+        if let Some(result) = state.result.take() {
+            return Poll::Ready(result);
+        }
+        assert!(!state.finished, "JoinHandle polled after completion");
+        match &state.waker {
+            Some(waker) if waker.will_wake(context.waker()) => {}
+            _ => state.waker = Some(context.waker().clone()),
+        }
+        Poll::Pending
+    }
+}
+
+impl<T> Drop for JoinHandle<T> {
+    fn drop(&mut self) {
+        let mut state = self.state.lock();
+        state.detached = true;
+        state.result = None;
+        state.waker = None;
+    }
+}
+
+// 随任务一起存放；任务完成前被丢弃时把结果记为取消
+struct Completion<T> {
+    state: Option<Shared<T>>,
+}
+
+impl<T> Completion<T> {
+    fn finish(&mut self, result: Result<T, JoinError>) {
+        let state = match self.state.take() {
+            Some(state) => state,
+            None => return,
+        };
+        let waker = {
+            let mut state = state.lock();
+            state.finished = true;
+            if !state.detached {
+                state.result = Some(result);
+            }
+            state.waker.take()
+        };
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> Drop for Completion<T> {
+    fn drop(&mut self) {
+        self.finish(Err(JoinError::Cancelled));
+    }
+}
+
+impl Task {
+    /// 创建一个普通优先级的任务，并返回等待其输出的句柄
+    pub fn joinable<F>(future: F) -> (Task, JoinHandle<F::Output>)
+    where
+        F: Future + 'static,
+        F::Output: 'static,
+    {
+        Self::joinable_with_priority(future, TaskPriority::Normal)
+    }
+
+    pub fn joinable_with_priority<F>(
+        future: F,
+        priority: TaskPriority,
+    ) -> (Task, JoinHandle<F::Output>)
+    where
+        F: Future + 'static,
+        F::Output: 'static,
+    {
+        let state: Shared<F::Output> = Arc::new(Mutex::new(JoinState {
+            result: None,
+            finished: false,
+            detached: false,
+            waker: None,
+        }));
+        let mut completion = Completion {
+            state: Some(state.clone()),
+        };
+        let task = Task::new_with_priority(
+            async move {
+                let output = future.await;
+                completion.finish(Ok(output));
+            },
+            priority,
+        );
+        let handle = JoinHandle {
+            id: task.id,
+            abort: task.abort_handle(),
+            priority: task.shared_priority(),
+            state,
+        };
+        (task, handle)
+    }
+}
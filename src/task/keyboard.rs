@@ -5,8 +5,12 @@
 //! - 键盘事件解码和处理
 
 use crate::{input, print};
+use crate::task::accounting;
 use crate::task::executor;
 use crate::task::timer;
+use crate::task::watchdog;
+use crate::task::workqueue;
+use crate::task::TaskPriority;
 use core::{
     pin::Pin,
     task::{Context, Poll},
@@ -82,16 +86,78 @@ fn handle_debug_command(command: char) -> bool {
         's' | 'S' => {
             let stats = executor::global_stats_snapshot();
             crate::serial_println!(
-                "[diag] tick={} active={} q_high={} q_normal={} wakers={} dropped_wakes={} dropped_scan={} uninit_scan={}",
+                "[diag] tick={} active={} queued={:?} wakers={} dropped_wakes={} dropped_scan={} uninit_scan={}",
                 timer::current_tick(),
                 stats.active_tasks,
-                stats.queued_high_tasks,
-                stats.queued_normal_tasks,
+                stats.queued_tasks,
                 stats.cached_wakers,
                 stats.dropped_wakes,
                 input::dropped_scancode_count(),
                 input::uninitialized_scancode_count(),
             );
+            crate::serial_println!(
+                "[diag] coop: slow_polls={} forced_yields={}",
+                stats.slow_polls,
+                stats.forced_yields,
+            );
+            if let Some(slow_poll) = executor::last_slow_poll() {
+                crate::serial_println!(
+                    "[diag] last slow poll: task #{} ({}) {} cycles at tick {}",
+                    slow_poll.task_id.as_u64(),
+                    slow_poll.name.unwrap_or("-"),
+                    slow_poll.cycles,
+                    slow_poll.tick,
+                );
+            }
+            let watchdog = watchdog::stats();
+            crate::serial_println!(
+                "[diag] watchdog: stuck_polls={} executor_stalls={} missed_ticks={}",
+                watchdog.stuck_polls,
+                watchdog.executor_stalls,
+                watchdog.missed_ticks,
+            );
+            let idle = timer::idle_stats();
+            crate::serial_println!(
+                "[diag] idle: tickless={} halts={} tickless_halts={} idle_cycles={} skipped_ticks={}",
+                timer::is_tickless(),
+                idle.halts,
+                idle.tickless_halts,
+                idle.idle_cycles,
+                idle.skipped_ticks,
+            );
+            for (priority, latency) in TaskPriority::ALL.iter().zip(stats.wake_latency) {
+                if latency.count > 0 {
+                    crate::serial_println!(
+                        "[diag] latency {:?}: samples={} mean={} p99<={} max={} cycles",
+                        priority,
+                        latency.count,
+                        latency.mean_cycles(),
+                        latency.percentile(99),
+                        latency.max_cycles,
+                    );
+                }
+            }
+            for queue in workqueue::queue_stats() {
+                crate::serial_println!(
+                    "[diag] workqueue {}: queued={} completed={} dropped={} pending={} high={} max_delay={} run={} cycles",
+                    queue.name,
+                    queue.queued,
+                    queue.completed,
+                    queue.dropped,
+                    queue.pending,
+                    queue.high_watermark,
+                    queue.max_delay_cycles,
+                    queue.run_cycles,
+                );
+            }
+            true
+        }
+        't' | 'T' => {
+            accounting::print_top();
+            true
+        }
+        'd' | 'D' => {
+            executor::dump_trace(executor::TraceFormat::Text);
             true
         }
         'r' | 'R' => {
@@ -100,7 +166,9 @@ fn handle_debug_command(command: char) -> bool {
             true
         }
         'h' | 'H' => {
-            crate::serial_println!("[diag] commands: s=show stats, r=reset input counters, h=help");
+            crate::serial_println!(
+                "[diag] commands: s=show stats, t=show tasks, d=dump trace, r=reset input counters, h=help"
+            );
             true
         }
         _ => false,
@@ -0,0 +1,216 @@
+//! # 任务局部变量
+//!
+//! 用 [`task_local!`](crate::task_local) 声明 [`LocalKey`]，派生前用 [`Task::with_local`] 为任务设置值，
+//! 任务内的任意 future 都可以通过 [`LocalKey::with`] 读取，而不必把日志前缀、所属进程或取消令牌
+//! 一层层作为参数传下去。值随任务一起保存，任务结束（或被取消后移除）时析构。
+//!
+//! ```ignore
+//! task_local! {
+//!     static PREFIX: &'static str;
+//! }
+//!
+//! executor.try_spawn(Task::new(async { PREFIX.with(|prefix| serial_println!("{}", prefix)) })
+//!     .with_local(&PREFIX, "[net]"))?;
+//! ```
+//!
+//! 通过 [`Spawner`](super::executor::Spawner) 或 [`TaskGroup`](super::group::TaskGroup) 派生的任务
+//! 用 [`Locals`] 携带初始值，其中的值必须是 `Send`。子任务不会自动继承父任务的局部变量，
+//! 需要传递时用 [`Locals::inherit`] 显式复制当前任务的值。
+//!
+//! 执行器轮询任务时把它的局部变量表设为当前表，轮询结束后恢复；线程切换时当前表随线程保存。
+//! 中断处理程序可能打断任意任务，不应访问任务局部变量。
+
+use super::Task;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::any::Any;
+use core::marker::PhantomData;
+use core::ptr;
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+// 正在被轮询的任务的局部变量表
+static CURRENT: AtomicPtr<TaskLocals> = AtomicPtr::new(ptr::null_mut());
+
+/// 声明任务局部变量
+///
+/// ```ignore
+/// task_local! {
+///     /// 日志前缀
+///     pub static PREFIX: &'static str;
+///     static DEPTH: core::cell::Cell<u32>;
+/// }
+/// ```
+#[macro_export]
+macro_rules! task_local {
+    () => {};
+    ($(#[$attr:meta])* $vis:vis static $name:ident: $ty:ty; $($rest:tt)*) => {
+        $(#[$attr])*
+        $vis static $name: $crate::task::local::LocalKey<$ty> =
+            $crate::task::local::LocalKey::new(stringify!($name));
+        $crate::task_local!($($rest)*);
+    };
+}
+
+/// 当前任务没有设置这个变量，或者不在任务中
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AccessError;
+
+/// 任务局部变量的键，由 [`task_local!`](crate::task_local) 声明为 `static`
+///
+/// 值只能共享访问；需要修改时把 `Cell` 或 `RefCell` 作为值的类型。
+pub struct LocalKey<T: 'static> {
+    // 键以 `static` 的地址区分，名字同时保证它不是零大小类型
+    name: &'static str,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T: 'static> LocalKey<T> {
+    #[doc(hidden)]
+    pub const fn new(name: &'static str) -> Self {
+        LocalKey {
+            name,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn name(&'static self) -> &'static str {
+        self.name
+    }
+
+    /// 以当前任务中的值调用 `f`
+    ///
+    /// # Panics
+    /// 当前任务没有设置这个变量，或者不在任务中时panic
+    pub fn with<R>(&'static self, f: impl FnOnce(&T) -> R) -> R {
+        match self.try_with(f) {
+            Ok(result) => result,
+            Err(AccessError) => panic!("task-local `{}` is not set", self.name),
+        }
+    }
+
+    pub fn try_with<R>(&'static self, f: impl FnOnce(&T) -> R) -> Result<R, AccessError> {
+        let locals = CURRENT.load(Ordering::Relaxed);
+        // 表属于正在被轮询的任务，轮询期间不会被修改或移动
+        let locals = unsafe { locals.as_ref() }.ok_or(AccessError)?;
+        locals
+            .get(self.id())
+            .and_then(|value| value.downcast_ref::<T>())
+            .map(f)
+            .ok_or(AccessError)
+    }
+
+    /// 当前任务是否设置了这个变量
+    pub fn is_set(&'static self) -> bool {
+        self.try_with(|_| ()).is_ok()
+    }
+
+    fn id(&'static self) -> usize {
+        self as *const Self as usize
+    }
+}
+
+impl<T: Clone + 'static> LocalKey<T> {
+    /// 当前任务中的值的克隆
+    ///
+    /// # Panics
+    /// 同 [`LocalKey::with`]
+    pub fn get(&'static self) -> T {
+        self.with(T::clone)
+    }
+}
+
+// 任务持有的局部变量，按键的地址查找
+#[derive(Default)]
+pub(crate) struct TaskLocals {
+    values: Vec<(usize, Box<dyn Any>)>,
+}
+
+impl TaskLocals {
+    fn get(&self, id: usize) -> Option<&dyn Any> {
+        self.values
+            .iter()
+            .find(|(key, _)| *key == id)
+            .map(|(_, value)| value.as_ref())
+    }
+
+    fn set(&mut self, id: usize, value: Box<dyn Any>) {
+        match self.values.iter_mut().find(|(key, _)| *key == id) {
+            Some((_, slot)) => *slot = value,
+            None => self.values.push((id, value)),
+        }
+    }
+
+    // 在 `f` 执行期间把这张表设为当前表
+    pub(crate) fn enter<R>(&self, f: impl FnOnce() -> R) -> R {
+        let previous = CURRENT.swap(self as *const Self as *mut Self, Ordering::Relaxed);
+        let result = f();
+        CURRENT.store(previous, Ordering::Relaxed);
+        result
+    }
+}
+
+/// 派生时交给新任务的一组局部变量，可以在任务或中断处理程序之间传递
+///
+/// ```ignore
+/// spawner.try_spawn_with_locals(child(), TaskPriority::Normal, Locals::new().inherit(&PREFIX))?;
+/// ```
+#[derive(Default)]
+pub struct Locals(TaskLocals);
+
+// 只能通过要求 `T: Send` 的方法放入值
+unsafe impl Send for Locals {}
+
+impl Locals {
+    pub fn new() -> Self {
+        Locals::default()
+    }
+
+    /// 设置 `key` 的值，已经设置过时替换
+    pub fn with<T: Send + 'static>(mut self, key: &'static LocalKey<T>, value: T) -> Self {
+        self.0.set(key.id(), Box::new(value));
+        self
+    }
+
+    /// 复制当前任务中 `key` 的值；当前任务没有设置时不做任何事
+    pub fn inherit<T: Clone + Send + 'static>(self, key: &'static LocalKey<T>) -> Self {
+        match key.try_with(T::clone) {
+            Ok(value) => self.with(key, value),
+            Err(AccessError) => self,
+        }
+    }
+}
+
+// 线程切换时保存的当前表
+#[derive(Clone, Copy)]
+pub(crate) struct SavedLocals(*mut TaskLocals);
+
+// 只在切换线程时搬运，指向的表仍由原线程上的任务独占
+unsafe impl Send for SavedLocals {}
+
+impl SavedLocals {
+    pub(crate) const NONE: SavedLocals = SavedLocals(ptr::null_mut());
+
+    pub(crate) fn save() -> SavedLocals {
+        SavedLocals(CURRENT.load(Ordering::Relaxed))
+    }
+
+    pub(crate) fn restore(self) {
+        CURRENT.store(self.0, Ordering::Relaxed);
+    }
+}
+
+impl Task {
+    /// 为任务设置局部变量 `key` 的值，已经设置过时替换
+    pub fn with_local<T: 'static>(mut self, key: &'static LocalKey<T>, value: T) -> Task {
+        self.locals.set(key.id(), Box::new(value));
+        self
+    }
+
+    // 合并派生时携带的局部变量
+    pub(crate) fn with_locals(mut self, locals: Locals) -> Task {
+        for (id, value) in locals.0.values {
+            self.locals.set(id, value);
+        }
+        self
+    }
+}
@@ -2,30 +2,105 @@
 //!
 //! 这个模块实现了一个基本的异步任务系统，包括：
 //! - 任务抽象和唯一ID生成
+//! - 可等待任务输出的 JoinHandle 与任务取消
+//! - 一起等待、一起取消的任务组
+//! - 每个任务的轮询次数、耗时与唤醒记账
+//! - 协作式调度预算与主动让出
+//! - 随任务保存的任务局部变量
+//! - 可以跨 `.await` 持有的锁、信号量、通知与屏障
+//! - 中断处理程序推迟到任务中执行的工作队列
+//! - 发现卡住的任务与被屏蔽的时钟中断的看门狗
 //! - 简单的轮询执行器
 //! - 高效的唤醒机制执行器
 //! - 键盘输入的异步处理
 
 use alloc::boxed::Box;
-use core::sync::atomic::{AtomicU64, Ordering};
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering};
 use core::task::{Context, Poll};
 use core::{future::Future, pin::Pin};
+use local::TaskLocals;
 
+/// 任务取消句柄
+pub mod abort;
+/// 每个任务的运行记账
+pub mod accounting;
+/// 协作式调度预算
+pub mod coop;
 /// 高效的任务执行器（基于唤醒机制）
 pub mod executor;
+/// 结构化并发的任务组
+pub mod group;
+/// 任务输出与等待句柄
+pub mod join;
 /// 键盘输入异步处理
 pub mod keyboard;
+/// 任务局部变量
+pub mod local;
 /// 简单的任务执行器（轮询所有任务）
 pub mod simple_executor;
+/// 异步同步原语
+pub mod sync;
 /// 基于时钟tick的定时/休眠能力
 pub mod timer;
+/// 卡死任务与丢失中断的看门狗
+pub mod watchdog;
+/// 延迟工作队列（中断下半部）
+pub mod workqueue;
 
-pub use timer::sleep_ticks;
+pub use abort::AbortHandle;
+pub use coop::yield_now;
+pub use group::{GroupError, TaskGroup};
+pub use join::{JoinError, JoinHandle};
+pub use local::{AccessError, LocalKey, Locals};
+pub use timer::{interval, sleep_ticks, timeout};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// 任务优先级，声明顺序即从高到低
+///
+/// 执行器优先调度级别高的任务，但等待过久的低级别任务会逐步提升（见 `executor` 的老化策略）。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum TaskPriority {
+    Realtime,
     High,
     Normal,
+    Low,
+    Idle,
+}
+
+impl TaskPriority {
+    /// 优先级级别数
+    pub const LEVELS: usize = 5;
+    /// 从高到低的全部优先级
+    pub const ALL: [TaskPriority; TaskPriority::LEVELS] = [
+        TaskPriority::Realtime,
+        TaskPriority::High,
+        TaskPriority::Normal,
+        TaskPriority::Low,
+        TaskPriority::Idle,
+    ];
+
+    /// 级别编号，0 为最高
+    pub fn level(self) -> usize {
+        self as usize
+    }
+}
+
+// 任务、唤醒器与 JoinHandle 共享的优先级，运行时修改后下一次唤醒即按新级别入队
+#[derive(Clone)]
+pub(crate) struct SharedPriority(Arc<AtomicU8>);
+
+impl SharedPriority {
+    fn new(priority: TaskPriority) -> SharedPriority {
+        SharedPriority(Arc::new(AtomicU8::new(priority as u8)))
+    }
+
+    pub(crate) fn get(&self) -> TaskPriority {
+        TaskPriority::ALL[usize::from(self.0.load(Ordering::Relaxed))]
+    }
+
+    pub(crate) fn set(&self, priority: TaskPriority) {
+        self.0.store(priority as u8, Ordering::Relaxed);
+    }
 }
 
 /// 异步任务的抽象
@@ -33,8 +108,13 @@ pub enum TaskPriority {
 /// 每个任务都有一个唯一的ID和一个Future，可以被执行器调度执行
 pub struct Task {
     pub id: TaskId,
-    pub priority: TaskPriority,
+    name: Option<&'static str>,
+    priority: SharedPriority,
     future: Pin<Box<dyn Future<Output = ()>>>,
+    abort: AbortHandle,
+    locals: TaskLocals,
+    // 任务是否已在就绪队列中（或已结束），唤醒器据此保证每个任务至多一个队列项
+    scheduled: Arc<AtomicBool>,
 }
 
 impl Task {
@@ -49,14 +129,56 @@ impl Task {
     ) -> Task {
         Task {
             id: TaskId::new(),
-            priority,
+            name: None,
+            priority: SharedPriority::new(priority),
             future: Box::pin(future),
+            abort: AbortHandle::new(),
+            locals: TaskLocals::default(),
+            // 新任务随即由执行器入队
+            scheduled: Arc::new(AtomicBool::new(true)),
         }
     }
 
-    /// 允许执行器轮询存储的future
+    /// 为任务命名，名字出现在记账列表与诊断输出中
+    pub fn named(mut self, name: &'static str) -> Task {
+        self.name = Some(name);
+        self
+    }
+
+    pub fn name(&self) -> Option<&'static str> {
+        self.name
+    }
+
+    /// 任务当前的优先级
+    pub fn priority(&self) -> TaskPriority {
+        self.priority.get()
+    }
+
+    /// 修改任务优先级；任务已在就绪队列中时，从下一次唤醒开始生效
+    pub fn set_priority(&self, priority: TaskPriority) {
+        self.priority.set(priority);
+    }
+
+    pub(crate) fn shared_priority(&self) -> SharedPriority {
+        self.priority.clone()
+    }
+
+    pub(crate) fn scheduled_flag(&self) -> Arc<AtomicBool> {
+        self.scheduled.clone()
+    }
+
+    /// 取消该任务的句柄
+    pub fn abort_handle(&self) -> AbortHandle {
+        self.abort.clone()
+    }
+
+    /// 允许执行器轮询存储的future；已请求取消的任务直接报告完成，由执行器移除
     pub(crate) fn poll(&mut self, ctx: &mut Context) -> Poll<()> {
-        self.future.as_mut().poll(ctx)
+        if self.abort.is_aborted() {
+            return Poll::Ready(());
+        }
+        self.abort.register(ctx.waker());
+        self.locals.enter(|| self.future.as_mut().poll(ctx))
     }
 }
 
@@ -72,4 +194,8 @@ impl TaskId {
         static NEXT_ID: AtomicU64 = AtomicU64::new(0);
         TaskId(NEXT_ID.fetch_add(1, Ordering::Relaxed))
     }
+
+    pub fn as_u64(self) -> u64 {
+        self.0
+    }
 }
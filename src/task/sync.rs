@@ -0,0 +1,171 @@
+//! # 异步同步原语
+//!
+//! 与 `spin::Mutex` 不同，这里的类型在资源不可用时让出执行权：等待者把唤醒器登记到
+//! 原语的等待队列中，资源释放时由执行器重新调度，不会在 `.await` 期间阻塞整个执行器。
+//!
+//! - [`Semaphore`]：按到达顺序分配许可，[`Mutex`] 与 [`RwLock`] 都建立在它之上
+//! - [`Notify`]：一次性通知，可以在中断处理程序中调用 `notify_one`
+//! - [`Barrier`]：凑齐指定数量的任务后一起放行
+//! - [`mpsc`]、[`oneshot`]、[`broadcast`]：任务之间以及中断处理程序到任务的消息通道
+//!
+//! 等待中的 future 被丢弃时会从等待队列中移除，已经分到的许可或通知会转交给下一个等待者。
+//! 内部状态用屏蔽中断的自旋锁保护，唤醒总是在释放锁之后进行。
+//! 通道的接收与有界发送消耗协作预算（见 [`coop`](crate::task::coop)），预算用完时即使就绪也返回 `Pending`。
+
+mod barrier;
+pub mod broadcast;
+pub mod mpsc;
+mod mutex;
+mod notify;
+pub mod oneshot;
+mod rwlock;
+mod semaphore;
+
+pub use barrier::{Barrier, BarrierWait, BarrierWaitResult};
+pub use mutex::{Mutex, MutexGuard};
+pub use notify::{Notified, Notify};
+pub use rwlock::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+pub use semaphore::{Acquire, Semaphore, SemaphorePermit};
+
+use alloc::collections::VecDeque;
+use core::task::Waker;
+use x86_64::instructions::interrupts;
+
+// 每次持锁最多取出的唤醒器数
+const WAKE_BATCH: usize = 16;
+
+// 屏蔽中断的自旋锁：中断处理程序也会操作这些原语，持锁时被同一 CPU 上的中断打断会死锁
+struct IrqMutex<T>(spin::Mutex<T>);
+
+impl<T> IrqMutex<T> {
+    const fn new(value: T) -> Self {
+        IrqMutex(spin::Mutex::new(value))
+    }
+
+    fn lock<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        interrupts::without_interrupts(|| f(&mut self.0.lock()))
+    }
+}
+
+// 按到达顺序排列的等待者，每个等待者带有原语自己的数据
+struct WaiterList<D> {
+    waiters: VecDeque<Waiter<D>>,
+    next_id: u64,
+}
+
+struct Waiter<D> {
+    id: u64,
+    waker: Waker,
+    data: D,
+}
+
+impl<D> WaiterList<D> {
+    const fn new() -> Self {
+        WaiterList {
+            waiters: VecDeque::new(),
+            next_id: 0,
+        }
+    }
+
+    // 登记新的等待者，返回它的ID
+    fn push(&mut self, waker: &Waker, data: D) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.waiters.push_back(Waiter {
+            id,
+            waker: waker.clone(),
+            data,
+        });
+        id
+    }
+
+    fn find_mut(&mut self, id: u64) -> Option<&mut Waiter<D>> {
+        self.waiters.iter_mut().find(|waiter| waiter.id == id)
+    }
+
+    // 更新仍在队列中的等待者的唤醒器，返回它是否仍在队列中
+    fn update(&mut self, id: u64, waker: &Waker) -> bool {
+        match self.find_mut(id) {
+            Some(waiter) => {
+                if !waiter.waker.will_wake(waker) {
+                    waiter.waker = waker.clone();
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn remove(&mut self, id: u64) -> Option<Waiter<D>> {
+        let index = self.waiters.iter().position(|waiter| waiter.id == id)?;
+        self.waiters.remove(index)
+    }
+
+    fn front(&self) -> Option<&Waiter<D>> {
+        self.waiters.front()
+    }
+
+    fn pop_front(&mut self) -> Option<Waiter<D>> {
+        self.waiters.pop_front()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.waiters.is_empty()
+    }
+
+    fn len(&self) -> usize {
+        self.waiters.len()
+    }
+
+    fn next_id(&self) -> u64 {
+        self.next_id
+    }
+
+    fn iter_mut(&mut self) -> impl Iterator<Item = &mut Waiter<D>> {
+        self.waiters.iter_mut()
+    }
+}
+
+// 在锁内收集、在锁外唤醒的一批唤醒器，放在栈上，不分配内存
+struct WakeBatch {
+    wakers: [Option<Waker>; WAKE_BATCH],
+    len: usize,
+}
+
+impl WakeBatch {
+    fn new() -> Self {
+        WakeBatch {
+            wakers: [const { None }; WAKE_BATCH],
+            len: 0,
+        }
+    }
+
+    fn is_full(&self) -> bool {
+        self.len == WAKE_BATCH
+    }
+
+    fn push(&mut self, waker: Waker) {
+        self.wakers[self.len] = Some(waker);
+        self.len += 1;
+    }
+
+    fn wake_all(mut self) {
+        for waker in self.wakers.iter_mut().filter_map(Option::take) {
+            waker.wake();
+        }
+    }
+
+    // 反复持锁让 `fill` 装一批唤醒器、释放锁后唤醒，直到某一批没有装满；
+    // `fill` 在批次装满时必须停下，每一批都会重新调用它
+    fn drain<T>(lock: &IrqMutex<T>, mut fill: impl FnMut(&mut T, &mut WakeBatch)) {
+        loop {
+            let mut batch = WakeBatch::new();
+            lock.lock(|state| fill(state, &mut batch));
+            let more = batch.is_full();
+            batch.wake_all();
+            if !more {
+                break;
+            }
+        }
+    }
+}
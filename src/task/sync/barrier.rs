@@ -0,0 +1,136 @@
+//! 异步屏障。
+
+use super::{IrqMutex, WaiterList, WakeBatch};
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+/// 凑齐 `parties` 个等待者后一起放行，之后可以重复使用
+pub struct Barrier {
+    parties: usize,
+    state: IrqMutex<State>,
+}
+
+struct State {
+    arrived: usize,
+    // 每放行一轮加一
+    generation: u64,
+    waiters: WaiterList<()>,
+}
+
+/// 最后一个到达的等待者是这一轮的领导者
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BarrierWaitResult {
+    leader: bool,
+}
+
+impl BarrierWaitResult {
+    pub fn is_leader(&self) -> bool {
+        self.leader
+    }
+}
+
+impl Barrier {
+    pub const fn new(parties: usize) -> Self {
+        assert!(parties > 0, "barrier needs at least one party");
+        Barrier {
+            parties,
+            state: IrqMutex::new(State {
+                arrived: 0,
+                generation: 0,
+                waiters: WaiterList::new(),
+            }),
+        }
+    }
+
+    /// 到达屏障并等待本轮凑齐；返回的 future 被轮询时才算到达，完成前被丢弃则视为离开
+    pub fn wait(&self) -> BarrierWait<'_> {
+        BarrierWait {
+            barrier: self,
+            registration: None,
+        }
+    }
+
+    pub fn parties(&self) -> usize {
+        self.parties
+    }
+
+    // 放行在 `target` 之前登记的等待者
+    fn release(&self, target: u64) {
+        WakeBatch::drain(&self.state, |state, batch| {
+            while !batch.is_full() {
+                match state.waiters.front() {
+                    Some(waiter) if waiter.id < target => {}
+                    _ => break,
+                }
+                if let Some(waiter) = state.waiters.pop_front() {
+                    batch.push(waiter.waker);
+                }
+            }
+        });
+    }
+}
+
+/// [`Barrier::wait`] 返回的 future
+pub struct BarrierWait<'a> {
+    barrier: &'a Barrier,
+    // 登记的等待者ID与到达时的轮次
+    registration: Option<(u64, u64)>,
+}
+
+impl Future for BarrierWait<'_> {
+    type Output = BarrierWaitResult;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<BarrierWaitResult> {
+        let this = self.get_mut();
+        let parties = this.barrier.parties;
+        let mut released = None;
+        let ready = this.barrier.state.lock(|state| match this.registration {
+            None => {
+                state.arrived += 1;
+                if state.arrived == parties {
+                    state.arrived = 0;
+                    state.generation += 1;
+                    released = Some(state.waiters.next_id());
+                    Some(BarrierWaitResult { leader: true })
+                } else {
+                    let id = state.waiters.push(context.waker(), ());
+                    this.registration = Some((id, state.generation));
+                    None
+                }
+            }
+            Some((id, generation)) => {
+                if state.generation != generation {
+                    Some(BarrierWaitResult { leader: false })
+                } else {
+                    state.waiters.update(id, context.waker());
+                    None
+                }
+            }
+        });
+
+        if let Some(target) = released {
+            this.barrier.release(target);
+        }
+        match ready {
+            Some(result) => {
+                this.registration = None;
+                Poll::Ready(result)
+            }
+            None => Poll::Pending,
+        }
+    }
+}
+
+impl Drop for BarrierWait<'_> {
+    fn drop(&mut self) {
+        if let Some((id, generation)) = self.registration.take() {
+            self.barrier.state.lock(|state| {
+                if state.generation == generation {
+                    state.waiters.remove(id);
+                    state.arrived -= 1;
+                }
+            });
+        }
+    }
+}
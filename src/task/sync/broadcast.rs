@@ -0,0 +1,272 @@
+//! 广播通道。
+//!
+//! 每个接收端都会收到订阅之后发送的每一条消息（的克隆）。消息存放在创建时分配的环形缓冲区中，
+//! 发送从不等待：缓冲区满时覆盖最旧的消息，落后太多的接收端下一次接收得到
+//! [`RecvError::Lagged`]，并跳到仍然保留的最旧消息。
+
+use super::{IrqMutex, WaiterList, WakeBatch};
+use crate::task::coop;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::pin::Pin;
+use core::task::{ready, Context, Poll};
+use futures_util::sink::Sink;
+use futures_util::stream::Stream;
+
+/// 没有接收端，发送的值原样退回
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SendError<T>(pub T);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecvError {
+    /// 所有发送端都已经丢弃，并且没有更多消息
+    Closed,
+    /// 接收端落后，错过了这么多条消息
+    Lagged(u64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TryRecvError {
+    Empty,
+    Closed,
+    Lagged(u64),
+}
+
+/// 创建最多保留 `capacity` 条消息的广播通道
+pub fn channel<T: Clone>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    assert!(capacity > 0, "broadcast capacity must be positive");
+    let mut slots = Vec::with_capacity(capacity);
+    slots.resize_with(capacity, || None);
+    let shared = Arc::new(Shared {
+        state: IrqMutex::new(State {
+            slots,
+            tail: 0,
+            senders: 1,
+            receivers: 1,
+            waiters: WaiterList::new(),
+        }),
+    });
+    (
+        Sender {
+            shared: shared.clone(),
+        },
+        Receiver {
+            shared,
+            next: 0,
+            wait_id: None,
+        },
+    )
+}
+
+struct Shared<T> {
+    state: IrqMutex<State<T>>,
+}
+
+struct State<T> {
+    // 第 `n` 条消息放在 `slots[n % capacity]`
+    slots: Vec<Option<T>>,
+    // 下一条消息的序号
+    tail: u64,
+    senders: usize,
+    receivers: usize,
+    // 等待新消息的接收端；发送时全部移出队列并唤醒
+    waiters: WaiterList<()>,
+}
+
+impl<T> Shared<T> {
+    fn wake_receivers(&self) {
+        WakeBatch::drain(&self.state, |state, batch| {
+            while !batch.is_full() {
+                match state.waiters.pop_front() {
+                    Some(waiter) => batch.push(waiter.waker),
+                    None => break,
+                }
+            }
+        });
+    }
+}
+
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Sender<T> {
+    /// 发送给当前所有接收端，返回接收端数量；不分配内存，可以在中断处理程序中调用
+    ///
+    /// 被覆盖的旧消息在这里析构，在中断处理程序中发送时注意消息类型的析构开销。
+    pub fn send(&self, value: T) -> Result<usize, SendError<T>> {
+        let receivers = self.shared.state.lock(|state| {
+            if state.receivers == 0 {
+                return Err(SendError(value));
+            }
+            let capacity = state.slots.len() as u64;
+            state.slots[(state.tail % capacity) as usize] = Some(value);
+            state.tail += 1;
+            Ok(state.receivers)
+        })?;
+        self.shared.wake_receivers();
+        Ok(receivers)
+    }
+
+    /// 新的接收端，从下一条发送的消息开始接收
+    pub fn subscribe(&self) -> Receiver<T> {
+        let next = self.shared.state.lock(|state| {
+            state.receivers += 1;
+            state.tail
+        });
+        Receiver {
+            shared: self.shared.clone(),
+            next,
+            wait_id: None,
+        }
+    }
+
+    pub fn receiver_count(&self) -> usize {
+        self.shared.state.lock(|state| state.receivers)
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.shared.state.lock(|state| state.senders += 1);
+        Sender {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let last = self.shared.state.lock(|state| {
+            state.senders -= 1;
+            state.senders == 0
+        });
+        if last {
+            self.shared.wake_receivers();
+        }
+    }
+}
+
+impl<T> Sink<T> for Sender<T> {
+    type Error = SendError<T>;
+
+    fn poll_ready(
+        self: Pin<&mut Self>,
+        _context: &mut Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: T) -> Result<(), Self::Error> {
+        self.send(item).map(|_| ())
+    }
+
+    fn poll_flush(
+        self: Pin<&mut Self>,
+        _context: &mut Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(
+        self: Pin<&mut Self>,
+        _context: &mut Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// 广播接收端
+///
+/// 作为 `Stream` 时产出 `Result<T, RecvError>`，其中的错误只会是 [`RecvError::Lagged`]；
+/// 通道关闭后流结束。
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+    // 下一条要接收的消息序号
+    next: u64,
+    wait_id: Option<u64>,
+}
+
+impl<T: Clone> Receiver<T> {
+    /// 等待下一条消息
+    pub async fn recv(&mut self) -> Result<T, RecvError> {
+        core::future::poll_fn(|context| self.poll_recv(context)).await
+    }
+
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        let next = &mut self.next;
+        self.shared.state.lock(|state| match state.take(next) {
+            Some(Ok(value)) => Ok(value),
+            Some(Err(missed)) => Err(TryRecvError::Lagged(missed)),
+            None if state.senders == 0 => Err(TryRecvError::Closed),
+            None => Err(TryRecvError::Empty),
+        })
+    }
+
+    pub fn poll_recv(&mut self, context: &mut Context<'_>) -> Poll<Result<T, RecvError>> {
+        ready!(coop::poll_proceed(context));
+        let Receiver { next, wait_id, .. } = self;
+        self.shared.state.lock(|state| {
+            let result = match state.take(next) {
+                Some(Ok(value)) => Ok(value),
+                Some(Err(missed)) => Err(RecvError::Lagged(missed)),
+                None if state.senders == 0 => Err(RecvError::Closed),
+                None => {
+                    match *wait_id {
+                        Some(id) if state.waiters.update(id, context.waker()) => {}
+                        _ => *wait_id = Some(state.waiters.push(context.waker(), ())),
+                    }
+                    return Poll::Pending;
+                }
+            };
+            if let Some(id) = wait_id.take() {
+                state.waiters.remove(id);
+            }
+            Poll::Ready(result)
+        })
+    }
+}
+
+impl<T: Clone> State<T> {
+    // 取出序号为 `*next` 的消息；已经被覆盖时跳到最旧的消息并返回错过的条数
+    fn take(&self, next: &mut u64) -> Option<Result<T, u64>> {
+        if *next == self.tail {
+            return None;
+        }
+        let capacity = self.slots.len() as u64;
+        let oldest = self.tail.saturating_sub(capacity);
+        if *next < oldest {
+            let missed = oldest - *next;
+            *next = oldest;
+            return Some(Err(missed));
+        }
+        let value = self.slots[(*next % capacity) as usize].clone();
+        *next += 1;
+        value.map(Ok)
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        let wait_id = self.wait_id.take();
+        self.shared.state.lock(|state| {
+            state.receivers -= 1;
+            if let Some(id) = wait_id {
+                state.waiters.remove(id);
+            }
+        });
+    }
+}
+
+impl<T: Clone> Stream for Receiver<T> {
+    type Item = Result<T, RecvError>;
+
+    fn poll_next(self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut()
+            .poll_recv(context)
+            .map(|result| match result {
+                Err(RecvError::Closed) => None,
+                other => Some(other),
+            })
+    }
+}
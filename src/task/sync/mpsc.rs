@@ -0,0 +1,478 @@
+//! 多生产者单消费者通道。
+//!
+//! 有界通道在创建时一次分配全部容量，之后的 `try_send` 不分配内存，可以在中断处理程序中调用；
+//! 队列满时 `send().await` 按到达顺序排队等待空位。无界通道的发送从不等待，但可能分配内存，
+//! 不要在中断处理程序中使用。
+
+use super::{IrqMutex, WaiterList, WakeBatch};
+use crate::task::coop;
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use core::pin::Pin;
+use core::task::{ready, Context, Poll, Waker};
+use futures_util::sink::Sink;
+use futures_util::stream::{FusedStream, Stream};
+
+/// 接收端已经关闭，发送的值原样退回
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SendError<T>(pub T);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrySendError<T> {
+    /// 队列已满
+    Full(T),
+    /// 接收端已经关闭
+    Closed(T),
+}
+
+impl<T> TrySendError<T> {
+    /// 取回没有发送出去的值
+    pub fn into_inner(self) -> T {
+        match self {
+            TrySendError::Full(value) | TrySendError::Closed(value) => value,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TryRecvError {
+    /// 暂时没有消息
+    Empty,
+    /// 没有消息，并且所有发送端都已经丢弃
+    Disconnected,
+}
+
+/// 创建容量为 `capacity` 的有界通道
+pub fn channel<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    assert!(capacity > 0, "channel capacity must be positive");
+    let chan = Arc::new(Chan::new(VecDeque::with_capacity(capacity), capacity));
+    (
+        Sender {
+            chan: chan.clone(),
+            reserved: false,
+            wait_id: None,
+        },
+        Receiver { chan },
+    )
+}
+
+/// 创建无界通道
+pub fn unbounded<T>() -> (UnboundedSender<T>, Receiver<T>) {
+    let chan = Arc::new(Chan::new(VecDeque::new(), usize::MAX));
+    (UnboundedSender { chan: chan.clone() }, Receiver { chan })
+}
+
+struct Chan<T> {
+    state: IrqMutex<State<T>>,
+}
+
+struct State<T> {
+    queue: VecDeque<T>,
+    capacity: usize,
+    // 已经分给发送端、还没有填入消息的空位
+    reserved: usize,
+    senders: usize,
+    closed: bool,
+    receiver: Option<Waker>,
+    // 等待空位的发送端；被移出队列说明空位已经分给了它
+    send_waiters: WaiterList<()>,
+}
+
+impl<T> Chan<T> {
+    fn new(queue: VecDeque<T>, capacity: usize) -> Self {
+        Chan {
+            state: IrqMutex::new(State {
+                queue,
+                capacity,
+                reserved: 0,
+                senders: 1,
+                closed: false,
+                receiver: None,
+                send_waiters: WaiterList::new(),
+            }),
+        }
+    }
+
+    // 放入一条消息并取出接收端的唤醒器
+    fn push(&self, value: T, reserved: bool) -> Result<(), TrySendError<T>> {
+        let waker = self.state.lock(|state| {
+            if state.closed {
+                if reserved {
+                    state.reserved -= 1;
+                }
+                return Err(TrySendError::Closed(value));
+            }
+            if reserved {
+                state.reserved -= 1;
+            } else if !state.has_free_slot() {
+                return Err(TrySendError::Full(value));
+            }
+            state.queue.push_back(value);
+            Ok(state.receiver.take())
+        })?;
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+        Ok(())
+    }
+
+    // 归还空位，并按顺序分给等待的发送端
+    fn release_slots(&self, slots: usize) {
+        let mut released = slots;
+        WakeBatch::drain(&self.state, |state, batch| {
+            state.reserved -= core::mem::take(&mut released);
+            while !batch.is_full() && state.has_free_slot() {
+                match state.send_waiters.pop_front() {
+                    Some(waiter) => {
+                        state.reserved += 1;
+                        batch.push(waiter.waker);
+                    }
+                    None => break,
+                }
+            }
+        });
+    }
+
+    // 唤醒所有等待的发送端，让它们看到通道已经关闭。
+    // 移出队列的发送端按分到空位记账，由它们在发现通道关闭时归还
+    fn wake_senders(&self) {
+        WakeBatch::drain(&self.state, |state, batch| {
+            while !batch.is_full() {
+                match state.send_waiters.pop_front() {
+                    Some(waiter) => {
+                        state.reserved += 1;
+                        batch.push(waiter.waker);
+                    }
+                    None => break,
+                }
+            }
+        });
+    }
+
+    fn add_sender(&self) {
+        self.state.lock(|state| state.senders += 1);
+    }
+
+    fn drop_sender(&self) {
+        let waker = self.state.lock(|state| {
+            state.senders -= 1;
+            if state.senders == 0 {
+                state.receiver.take()
+            } else {
+                None
+            }
+        });
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+
+    fn is_closed(&self) -> bool {
+        self.state.lock(|state| state.closed)
+    }
+}
+
+impl<T> State<T> {
+    fn has_free_slot(&self) -> bool {
+        self.queue.len() + self.reserved < self.capacity
+    }
+}
+
+/// 有界通道的发送端
+///
+/// `send().await` 先为消息预留一个空位再放入；预留的空位属于这个发送端，
+/// 即使等待中的 `send` 被丢弃，空位也会留给它的下一次发送，发送端丢弃时归还。
+pub struct Sender<T> {
+    chan: Arc<Chan<T>>,
+    reserved: bool,
+    wait_id: Option<u64>,
+}
+
+impl<T> Sender<T> {
+    /// 等待空位并发送；接收端已经关闭时退回消息
+    pub async fn send(&mut self, value: T) -> Result<(), SendError<T>> {
+        if core::future::poll_fn(|context| self.poll_reserve(context))
+            .await
+            .is_err()
+        {
+            return Err(SendError(value));
+        }
+        self.reserved = false;
+        self.chan
+            .push(value, true)
+            .map_err(|error| SendError(error.into_inner()))
+    }
+
+    /// 不等待地发送，不分配内存，可以在中断处理程序中调用
+    pub fn try_send(&self, value: T) -> Result<(), TrySendError<T>> {
+        self.chan.push(value, false)
+    }
+
+    /// 接收端是否已经关闭
+    pub fn is_closed(&self) -> bool {
+        self.chan.is_closed()
+    }
+
+    // 预留一个空位；通道关闭时返回 `Err`
+    fn poll_reserve(&mut self, context: &mut Context<'_>) -> Poll<Result<(), ()>> {
+        if self.reserved {
+            return Poll::Ready(if self.chan.is_closed() {
+                Err(())
+            } else {
+                Ok(())
+            });
+        }
+        ready!(coop::poll_proceed(context));
+        let wait_id = &mut self.wait_id;
+        let reserved = self.chan.state.lock(|state| {
+            if state.closed {
+                if let Some(id) = wait_id.take() {
+                    if state.send_waiters.remove(id).is_none() {
+                        state.reserved -= 1;
+                    }
+                }
+                return Some(Err(()));
+            }
+            match *wait_id {
+                Some(id) if state.send_waiters.update(id, context.waker()) => None,
+                Some(_) => {
+                    *wait_id = None;
+                    Some(Ok(()))
+                }
+                None if state.send_waiters.is_empty() && state.has_free_slot() => {
+                    state.reserved += 1;
+                    Some(Ok(()))
+                }
+                None => {
+                    *wait_id = Some(state.send_waiters.push(context.waker(), ()));
+                    None
+                }
+            }
+        });
+        match reserved {
+            Some(Ok(())) => {
+                self.reserved = true;
+                Poll::Ready(Ok(()))
+            }
+            Some(Err(())) => Poll::Ready(Err(())),
+            None => Poll::Pending,
+        }
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.chan.add_sender();
+        Sender {
+            chan: self.chan.clone(),
+            reserved: false,
+            wait_id: None,
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        // 还在排队时离开队列；已经分到的空位归还给后面的发送端
+        let released = match self.wait_id.take() {
+            Some(id) => self
+                .chan
+                .state
+                .lock(|state| state.send_waiters.remove(id).is_none()),
+            None => false,
+        };
+        if released || self.reserved {
+            self.chan.release_slots(1);
+        }
+        self.chan.drop_sender();
+    }
+}
+
+impl<T> Sink<T> for Sender<T> {
+    type Error = SendError<()>;
+
+    fn poll_ready(
+        self: Pin<&mut Self>,
+        context: &mut Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        self.get_mut()
+            .poll_reserve(context)
+            .map_err(|()| SendError(()))
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: T) -> Result<(), Self::Error> {
+        let this = self.get_mut();
+        assert!(this.reserved, "start_send called without poll_ready");
+        this.reserved = false;
+        this.chan.push(item, true).map_err(|_| SendError(()))
+    }
+
+    fn poll_flush(
+        self: Pin<&mut Self>,
+        _context: &mut Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(
+        self: Pin<&mut Self>,
+        _context: &mut Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// 无界通道的发送端
+pub struct UnboundedSender<T> {
+    chan: Arc<Chan<T>>,
+}
+
+impl<T> UnboundedSender<T> {
+    /// 立即发送；接收端已经关闭时退回消息
+    pub fn send(&self, value: T) -> Result<(), SendError<T>> {
+        self.chan
+            .push(value, false)
+            .map_err(|error| SendError(error.into_inner()))
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.chan.is_closed()
+    }
+}
+
+impl<T> Clone for UnboundedSender<T> {
+    fn clone(&self) -> Self {
+        self.chan.add_sender();
+        UnboundedSender {
+            chan: self.chan.clone(),
+        }
+    }
+}
+
+impl<T> Drop for UnboundedSender<T> {
+    fn drop(&mut self) {
+        self.chan.drop_sender();
+    }
+}
+
+impl<T> Sink<T> for UnboundedSender<T> {
+    type Error = SendError<()>;
+
+    fn poll_ready(
+        self: Pin<&mut Self>,
+        _context: &mut Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(if self.is_closed() {
+            Err(SendError(()))
+        } else {
+            Ok(())
+        })
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: T) -> Result<(), Self::Error> {
+        self.send(item).map_err(|_| SendError(()))
+    }
+
+    fn poll_flush(
+        self: Pin<&mut Self>,
+        _context: &mut Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(
+        self: Pin<&mut Self>,
+        _context: &mut Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// 接收端，有界和无界通道共用
+///
+/// 所有发送端都丢弃、队列也取空后，`recv` 返回 `None`，作为 `Stream` 时流结束。
+pub struct Receiver<T> {
+    chan: Arc<Chan<T>>,
+}
+
+impl<T> Receiver<T> {
+    /// 等待下一条消息
+    pub async fn recv(&mut self) -> Option<T> {
+        core::future::poll_fn(|context| self.poll_recv(context)).await
+    }
+
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        let (result, bounded) = self.chan.state.lock(|state| {
+            let result = match state.queue.pop_front() {
+                Some(value) => Ok(value),
+                None if state.senders == 0 => Err(TryRecvError::Disconnected),
+                None => Err(TryRecvError::Empty),
+            };
+            (result, state.capacity != usize::MAX)
+        });
+        if result.is_ok() && bounded {
+            self.chan.release_slots(0);
+        }
+        result
+    }
+
+    pub fn poll_recv(&mut self, context: &mut Context<'_>) -> Poll<Option<T>> {
+        ready!(coop::poll_proceed(context));
+        let polled = self.chan.state.lock(|state| match state.queue.pop_front() {
+            Some(value) => Poll::Ready(Some((value, state.capacity != usize::MAX))),
+            None if state.senders == 0 => Poll::Ready(None),
+            None => {
+                match &state.receiver {
+                    Some(waker) if waker.will_wake(context.waker()) => {}
+                    _ => state.receiver = Some(context.waker().clone()),
+                }
+                Poll::Pending
+            }
+        });
+        polled.map(|received| {
+            received.map(|(value, bounded)| {
+                if bounded {
+                    self.chan.release_slots(0);
+                }
+                value
+            })
+        })
+    }
+
+    /// 关闭通道：之后的发送都会失败，已经在队列中的消息仍然可以取出
+    pub fn close(&mut self) {
+        self.chan.state.lock(|state| state.closed = true);
+        self.chan.wake_senders();
+    }
+
+    /// 队列中的消息数
+    pub fn len(&self) -> usize {
+        self.chan.state.lock(|state| state.queue.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+impl<T> Stream for Receiver<T> {
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<Option<T>> {
+        self.get_mut().poll_recv(context)
+    }
+}
+
+impl<T> FusedStream for Receiver<T> {
+    fn is_terminated(&self) -> bool {
+        self.chan
+            .state
+            .lock(|state| state.senders == 0 && state.queue.is_empty())
+    }
+}
@@ -0,0 +1,152 @@
+//! 任务间的一次性通知。
+
+use super::{IrqMutex, WaiterList, WakeBatch};
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+/// 唤醒等待通知的任务
+///
+/// `notify_one` 唤醒最早的一个等待者；没有等待者时保存一次通知，下一次等待立即完成。
+/// `notify_waiters` 唤醒调用时已经在等待的所有任务，不保存通知。两者都可以在中断处理程序中调用。
+pub struct Notify {
+    state: IrqMutex<State>,
+}
+
+struct State {
+    // 没有等待者时保存的一次 `notify_one`
+    stored: bool,
+    waiters: WaiterList<Notification>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Notification {
+    Waiting,
+    One,
+    All,
+}
+
+impl Notify {
+    pub const fn new() -> Self {
+        Notify {
+            state: IrqMutex::new(State {
+                stored: false,
+                waiters: WaiterList::new(),
+            }),
+        }
+    }
+
+    /// 等待下一次通知；返回的 future 第一次被轮询时才开始排队
+    pub fn notified(&self) -> Notified<'_> {
+        Notified {
+            notify: self,
+            id: None,
+        }
+    }
+
+    pub fn notify_one(&self) {
+        let waker = self.state.lock(|state| {
+            let waiter = state
+                .waiters
+                .iter_mut()
+                .find(|waiter| waiter.data == Notification::Waiting);
+            match waiter {
+                Some(waiter) => {
+                    waiter.data = Notification::One;
+                    Some(waiter.waker.clone())
+                }
+                None => {
+                    state.stored = true;
+                    None
+                }
+            }
+        });
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+
+    pub fn notify_waiters(&self) {
+        // 之后才开始等待的任务不受这次通知影响
+        let target = self.state.lock(|state| state.waiters.next_id());
+        WakeBatch::drain(&self.state, |state, batch| {
+            let waiters = state
+                .waiters
+                .iter_mut()
+                .filter(|waiter| waiter.id < target && waiter.data == Notification::Waiting);
+            for waiter in waiters {
+                if batch.is_full() {
+                    break;
+                }
+                waiter.data = Notification::All;
+                batch.push(waiter.waker.clone());
+            }
+        });
+    }
+
+    /// 正在等待的任务数
+    pub fn waiters(&self) -> usize {
+        self.state.lock(|state| state.waiters.len())
+    }
+}
+
+impl Default for Notify {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// [`Notify::notified`] 返回的 future
+pub struct Notified<'a> {
+    notify: &'a Notify,
+    id: Option<u64>,
+}
+
+impl Future for Notified<'_> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<()> {
+        let this = self.get_mut();
+        let notified = this.notify.state.lock(|state| match this.id {
+            None => {
+                if core::mem::take(&mut state.stored) {
+                    true
+                } else {
+                    this.id = Some(state.waiters.push(context.waker(), Notification::Waiting));
+                    false
+                }
+            }
+            Some(id) => {
+                let waiter = state.waiters.find_mut(id).expect("waiter missing");
+                if waiter.data == Notification::Waiting {
+                    if !waiter.waker.will_wake(context.waker()) {
+                        waiter.waker = context.waker().clone();
+                    }
+                    false
+                } else {
+                    state.waiters.remove(id);
+                    true
+                }
+            }
+        });
+
+        if notified {
+            this.id = None;
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+impl Drop for Notified<'_> {
+    // 收到 `notify_one` 却没有被轮询就被丢弃时，把通知转交给下一个等待者
+    fn drop(&mut self) {
+        if let Some(id) = self.id.take() {
+            let removed = self.notify.state.lock(|state| state.waiters.remove(id));
+            if matches!(removed, Some(waiter) if waiter.data == Notification::One) {
+                self.notify.notify_one();
+            }
+        }
+    }
+}
@@ -0,0 +1,129 @@
+//! 只传递一个值的通道。
+//!
+//! 值的存储空间在创建时分配，`Sender::send` 不分配内存也不等待，可以在中断处理程序中调用。
+
+use super::IrqMutex;
+use crate::task::coop;
+use alloc::sync::Arc;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{ready, Context, Poll, Waker};
+
+/// 发送端在发送前被丢弃
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecvError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TryRecvError {
+    /// 还没有发送
+    Empty,
+    /// 发送端没有发送就被丢弃，或者值已经被取走
+    Closed,
+}
+
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let shared = Arc::new(IrqMutex::new(State {
+        value: None,
+        sender_alive: true,
+        receiver_alive: true,
+        receiver: None,
+    }));
+    (
+        Sender {
+            shared: shared.clone(),
+        },
+        Receiver { shared },
+    )
+}
+
+struct State<T> {
+    value: Option<T>,
+    sender_alive: bool,
+    receiver_alive: bool,
+    receiver: Option<Waker>,
+}
+
+pub struct Sender<T> {
+    shared: Arc<IrqMutex<State<T>>>,
+}
+
+impl<T> Sender<T> {
+    /// 发送值；接收端已经丢弃或关闭时退回
+    pub fn send(self, value: T) -> Result<(), T> {
+        let waker = self.shared.lock(|state| {
+            if !state.receiver_alive {
+                return Err(value);
+            }
+            state.value = Some(value);
+            Ok(state.receiver.take())
+        })?;
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+        Ok(())
+    }
+
+    /// 接收端是否已经丢弃或关闭
+    pub fn is_closed(&self) -> bool {
+        self.shared.lock(|state| !state.receiver_alive)
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let waker = self.shared.lock(|state| {
+            state.sender_alive = false;
+            state.receiver.take()
+        });
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+/// 接收端，本身是等待值的 future
+pub struct Receiver<T> {
+    shared: Arc<IrqMutex<State<T>>>,
+}
+
+impl<T> Receiver<T> {
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        self.shared.lock(|state| match state.value.take() {
+            Some(value) => Ok(value),
+            None if state.sender_alive => Err(TryRecvError::Empty),
+            None => Err(TryRecvError::Closed),
+        })
+    }
+
+    /// 拒绝之后的发送；已经发送的值仍然可以取出
+    pub fn close(&mut self) {
+        self.shared.lock(|state| state.receiver_alive = false);
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+impl<T> Future for Receiver<T> {
+    type Output = Result<T, RecvError>;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<Self::Output> {
+        ready!(coop::poll_proceed(context));
+        self.shared.lock(|state| {
+            if let Some(value) = state.value.take() {
+                return Poll::Ready(Ok(value));
+            }
+            if !state.sender_alive {
+                return Poll::Ready(Err(RecvError));
+            }
+            match &state.receiver {
+                Some(waker) if waker.will_wake(context.waker()) => {}
+                _ => state.receiver = Some(context.waker().clone()),
+            }
+            Poll::Pending
+        })
+    }
+}
@@ -0,0 +1,163 @@
+//! 公平的异步信号量。
+
+use super::{IrqMutex, WaiterList, WakeBatch};
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+/// 计数信号量
+///
+/// 等待者按到达顺序得到许可：队首的等待者许可不足时，后来者即使只需要更少的许可也要排队，
+/// 因此一次申请很多许可的等待者不会被饿死。申请的许可数超过信号量可能拥有的总数时永远等待。
+pub struct Semaphore {
+    state: IrqMutex<State>,
+}
+
+struct State {
+    permits: usize,
+    // 每个等待者需要的许可数
+    waiters: WaiterList<usize>,
+}
+
+impl Semaphore {
+    pub const fn new(permits: usize) -> Self {
+        Semaphore {
+            state: IrqMutex::new(State {
+                permits,
+                waiters: WaiterList::new(),
+            }),
+        }
+    }
+
+    /// 当前可用的许可数
+    pub fn available_permits(&self) -> usize {
+        self.state.lock(|state| state.permits)
+    }
+
+    /// 等待一个许可
+    pub fn acquire(&self) -> Acquire<'_> {
+        self.acquire_many(1)
+    }
+
+    /// 等待 `permits` 个许可，一次全部取得
+    pub fn acquire_many(&self, permits: usize) -> Acquire<'_> {
+        Acquire {
+            semaphore: self,
+            permits,
+            id: None,
+        }
+    }
+
+    /// 不等待地取得一个许可；许可不足或已有等待者时返回 `None`
+    pub fn try_acquire(&self) -> Option<SemaphorePermit<'_>> {
+        self.try_acquire_many(1)
+    }
+
+    pub fn try_acquire_many(&self, permits: usize) -> Option<SemaphorePermit<'_>> {
+        let acquired = self.state.lock(|state| {
+            let available = state.waiters.is_empty() && state.permits >= permits;
+            if available {
+                state.permits -= permits;
+            }
+            available
+        });
+        acquired.then_some(SemaphorePermit {
+            semaphore: self,
+            permits,
+        })
+    }
+
+    /// 增加 `permits` 个许可，并按顺序分给许可已经足够的等待者；可以在中断处理程序中调用
+    pub fn add_permits(&self, permits: usize) {
+        let mut added = permits;
+        WakeBatch::drain(&self.state, |state, batch| {
+            state.permits += core::mem::take(&mut added);
+            while !batch.is_full() {
+                match state.waiters.front() {
+                    Some(waiter) if waiter.data <= state.permits => {}
+                    _ => break,
+                }
+                if let Some(waiter) = state.waiters.pop_front() {
+                    state.permits -= waiter.data;
+                    batch.push(waiter.waker);
+                }
+            }
+        });
+    }
+}
+
+/// [`Semaphore::acquire`] 返回的 future
+pub struct Acquire<'a> {
+    semaphore: &'a Semaphore,
+    permits: usize,
+    // 排队后的等待者ID；被移出队列说明许可已经分给了它
+    id: Option<u64>,
+}
+
+impl<'a> Future for Acquire<'a> {
+    type Output = SemaphorePermit<'a>;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let permits = this.permits;
+        let acquired = this.semaphore.state.lock(|state| match this.id {
+            None => {
+                if state.waiters.is_empty() && state.permits >= permits {
+                    state.permits -= permits;
+                    true
+                } else {
+                    this.id = Some(state.waiters.push(context.waker(), permits));
+                    false
+                }
+            }
+            Some(id) => !state.waiters.update(id, context.waker()),
+        });
+
+        if acquired {
+            this.id = None;
+            Poll::Ready(SemaphorePermit {
+                semaphore: this.semaphore,
+                permits,
+            })
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+impl Drop for Acquire<'_> {
+    fn drop(&mut self) {
+        if let Some(id) = self.id.take() {
+            let queued = self
+                .semaphore
+                .state
+                .lock(|state| state.waiters.remove(id).is_some());
+            // 仍在排队：队首可能变了，重新分配；已经分到许可：归还
+            let returned = if queued { 0 } else { self.permits };
+            self.semaphore.add_permits(returned);
+        }
+    }
+}
+
+/// 持有的许可，丢弃时归还
+pub struct SemaphorePermit<'a> {
+    semaphore: &'a Semaphore,
+    permits: usize,
+}
+
+impl SemaphorePermit<'_> {
+    pub fn permits(&self) -> usize {
+        self.permits
+    }
+
+    /// 不归还许可，由调用者负责之后调用 [`Semaphore::add_permits`]
+    pub fn forget(self) {
+        core::mem::forget(self);
+    }
+}
+
+impl Drop for SemaphorePermit<'_> {
+    fn drop(&mut self) {
+        self.semaphore.add_permits(self.permits);
+    }
+}
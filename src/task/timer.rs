@@ -1,79 +1,237 @@
-use alloc::vec::Vec;
-use core::future::Future;
+//! # tick 驱动的定时器
+//!
+//! 时钟中断调用 [`tick`] 推进全局 tick，[`sleep_ticks`] 返回的 future 把唤醒器登记到
+//! 分层时间轮中，到期时由时钟中断唤醒。每个 [`Sleep`] 持有自己的登记，丢弃时删除，
+//! 同一个任务中的多个 `Sleep` 互不影响。[`timeout`] 与 [`Interval`] 都建立在 `Sleep` 之上。
+//! 启用无滴答空闲（[`enable_tickless`]）后，执行器空闲时关闭周期时钟，按最近的期限单次定时。
+
+mod tickless;
+mod wheel;
+
+pub(crate) use tickless::idle;
+pub use tickless::{enable_tickless, idle_stats, is_tickless, IdleStats};
+
+use core::future::{self, Future};
 use core::pin::Pin;
 use core::sync::atomic::{AtomicU64, Ordering};
 use core::task::{Context, Poll, Waker};
+use futures_util::stream::Stream;
 use spin::Mutex;
+use wheel::TimerWheel;
+use x86_64::instructions::interrupts;
 
-static CURRENT_TICK: AtomicU64 = AtomicU64::new(0);
-static SLEEPERS: Mutex<Vec<SleeperEntry>> = Mutex::new(Vec::new());
+// 每次持锁最多取出的到期唤醒器数，放在时钟中断的栈上
+const WAKE_BATCH: usize = 32;
 
-#[derive(Clone)]
-struct SleeperEntry {
-    wake_tick: u64,
-    waker: Waker,
-}
+static CURRENT_TICK: AtomicU64 = AtomicU64::new(0);
+static WHEEL: Mutex<TimerWheel> = Mutex::new(TimerWheel::new());
 
 pub fn current_tick() -> u64 {
     CURRENT_TICK.load(Ordering::Relaxed)
 }
 
+/// 时间轮中尚未到期的登记数
+pub fn pending_sleepers() -> usize {
+    interrupts::without_interrupts(|| WHEEL.lock().pending())
+}
+
 pub fn tick() {
-    let tick_value = CURRENT_TICK.fetch_add(1, Ordering::Relaxed) + 1;
-    let mut wakers_to_wake: Vec<Waker> = Vec::new();
-
-    {
-        let mut sleepers = SLEEPERS.lock();
-        let mut index = 0;
-        while index < sleepers.len() {
-            if sleepers[index].wake_tick <= tick_value {
-                let entry = sleepers.swap_remove(index);
-                wakers_to_wake.push(entry.waker);
-            } else {
-                index += 1;
+    advance_ticks(1);
+}
+
+// 推进 `ticks` 个 tick 并唤醒到期的睡眠者
+fn advance_ticks(ticks: u64) {
+    let tick_value = CURRENT_TICK.fetch_add(ticks, Ordering::Relaxed) + ticks;
+
+    // 分批取出到期的唤醒器，在锁外唤醒
+    loop {
+        let mut batch: [Option<Waker>; WAKE_BATCH] = [const { None }; WAKE_BATCH];
+        let count = interrupts::without_interrupts(|| {
+            let mut wheel = WHEEL.lock();
+            wheel.advance(tick_value);
+            let mut count = 0;
+            while count < WAKE_BATCH {
+                match wheel.pop_expired() {
+                    Some(waker) => batch[count] = Some(waker),
+                    None => break,
+                }
+                count += 1;
             }
+            count
+        });
+
+        for waker in batch.iter_mut().filter_map(Option::take) {
+            waker.wake();
+        }
+        if count < WAKE_BATCH {
+            break;
         }
     }
+}
 
-    for waker in wakers_to_wake {
-        waker.wake();
-    }
+// 时间轮下一次需要处理的 tick
+fn next_event() -> Option<u64> {
+    interrupts::without_interrupts(|| WHEEL.lock().next_event())
 }
 
 pub fn sleep_ticks(ticks: u64) -> Sleep {
     let current = current_tick();
     let delta = if ticks == 0 { 1 } else { ticks };
+    sleep_until(current.saturating_add(delta))
+}
+
+/// 睡眠到全局 tick 达到 `wake_tick`
+pub fn sleep_until(wake_tick: u64) -> Sleep {
     Sleep {
-        wake_tick: current.saturating_add(delta),
+        wake_tick,
+        key: None,
     }
 }
 
 pub struct Sleep {
     wake_tick: u64,
+    // 在时间轮中的登记，到期或提前丢弃时据此回收
+    key: Option<u32>,
+}
+
+impl Sleep {
+    /// 到期的 tick
+    pub fn deadline(&self) -> u64 {
+        self.wake_tick
+    }
+
+    /// 改为在 `wake_tick` 到期，原有的登记被删除
+    pub fn reset(&mut self, wake_tick: u64) {
+        self.release();
+        self.wake_tick = wake_tick;
+    }
+
+    fn release(&mut self) {
+        if let Some(key) = self.key.take() {
+            interrupts::without_interrupts(|| WHEEL.lock().remove(key));
+        }
+    }
 }
 
 impl Future for Sleep {
     type Output = ();
 
     fn poll(self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<Self::Output> {
-        if current_tick() >= self.wake_tick {
+        let this = self.get_mut();
+        if current_tick() >= this.wake_tick {
+            this.release();
             return Poll::Ready(());
         }
 
-        let mut sleepers = SLEEPERS.lock();
-        if let Some(existing_entry) = sleepers
-            .iter_mut()
-            .find(|entry| entry.waker.will_wake(context.waker()))
-        {
-            existing_entry.wake_tick = self.wake_tick;
-            existing_entry.waker = context.waker().clone();
+        // 时钟中断同样会获取时间轮的锁
+        let registered = interrupts::without_interrupts(|| {
+            let mut wheel = WHEEL.lock();
+            match this.key {
+                Some(key) => wheel.update(key, context.waker()),
+                None => {
+                    this.key = wheel.insert(this.wake_tick, context.waker().clone());
+                    this.key.is_some()
+                }
+            }
+        });
+
+        if registered {
+            Poll::Pending
         } else {
-            sleepers.push(SleeperEntry {
-                wake_tick: self.wake_tick,
-                waker: context.waker().clone(),
-            });
+            // 登记前时间轮已经越过了到期 tick
+            this.release();
+            Poll::Ready(())
         }
+    }
+}
+
+impl Drop for Sleep {
+    // 未到期就被丢弃（例如所在任务被取消）时，删除时间轮中的登记
+    fn drop(&mut self) {
+        self.release();
+    }
+}
+
+/// [`timeout`] 在内部 future 完成前到期
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Elapsed;
+
+/// 为 `future` 设置 `ticks` 个 tick 的期限
+///
+/// 期限内完成时得到 `Ok(输出)`，否则得到 `Err(Elapsed)` 并丢弃内部 future。
+pub fn timeout<F: Future>(ticks: u64, future: F) -> Timeout<F> {
+    Timeout {
+        future,
+        sleep: sleep_ticks(ticks),
+    }
+}
+
+pub struct Timeout<F> {
+    future: F,
+    sleep: Sleep,
+}
+
+impl<F: Future> Future for Timeout<F> {
+    type Output = Result<F::Output, Elapsed>;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<Self::Output> {
+        // SAFETY: `future` 不会被移出 `Timeout`，`sleep` 是 `Unpin` 的
+        let this = unsafe { self.get_unchecked_mut() };
+        let future = unsafe { Pin::new_unchecked(&mut this.future) };
+        if let Poll::Ready(output) = future.poll(context) {
+            // 不必等到 `Timeout` 被丢弃才删除登记
+            this.sleep.release();
+            return Poll::Ready(Ok(output));
+        }
+        Pin::new(&mut this.sleep)
+            .poll(context)
+            .map(|()| Err(Elapsed))
+    }
+}
+
+/// 每 `period` 个 tick 触发一次的定时器，第一次在 `period` 个 tick 之后触发
+pub fn interval(period: u64) -> Interval {
+    assert!(period > 0, "interval period must be positive");
+    Interval {
+        period,
+        sleep: sleep_ticks(period),
+    }
+}
+
+/// 周期定时器，也可以作为产生触发 tick 的 `Stream` 使用
+///
+/// 下一次触发的期限按上一次的期限加周期计算，不随任务被调度的延迟漂移；
+/// 错过的周期会被跳过，而不是连续触发。
+pub struct Interval {
+    period: u64,
+    sleep: Sleep,
+}
+
+impl Interval {
+    pub fn period(&self) -> u64 {
+        self.period
+    }
+
+    /// 等待下一次触发，返回本次触发的期限 tick
+    pub async fn tick(&mut self) -> u64 {
+        future::poll_fn(|context| self.poll_tick(context)).await
+    }
+
+    pub fn poll_tick(&mut self, context: &mut Context<'_>) -> Poll<u64> {
+        if Pin::new(&mut self.sleep).poll(context).is_pending() {
+            return Poll::Pending;
+        }
+        let fired = self.sleep.deadline();
+        let missed = current_tick().saturating_sub(fired) / self.period;
+        self.sleep.reset(fired + (missed + 1) * self.period);
+        Poll::Ready(fired)
+    }
+}
+
+impl Stream for Interval {
+    type Item = u64;
 
-        Poll::Pending
+    fn poll_next(self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<Option<u64>> {
+        self.get_mut().poll_tick(context).map(Some)
     }
 }
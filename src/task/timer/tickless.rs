@@ -0,0 +1,137 @@
+//! 无滴答空闲。
+//!
+//! 执行器空闲时，如果最近的定时器事件还在一个 tick 之后，就屏蔽 PIT 周期中断，用本地 APIC
+//! 定时器按该事件单次定时，然后停机。被任何中断唤醒后，根据 APIC 定时器已经走过的计数补上
+//! 错过的 tick，再恢复 PIT。APIC 定时器的频率在启用时以 PIT tick 为基准校准。
+
+use super::{advance_ticks, current_tick, next_event};
+use crate::apic;
+use crate::interrupts::{set_timer_masked, timer_interrupt_pending};
+use crate::task::accounting::read_tsc;
+use core::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use x86_64::instructions::{hlt, interrupts};
+
+// 校准时让 APIC 定时器计数的 PIT tick 数
+const CALIBRATION_TICKS: u32 = 2;
+
+// 每个 PIT tick 对应的 APIC 定时器计数，为 0 时未启用
+static COUNTS_PER_TICK: AtomicU32 = AtomicU32::new(0);
+// 上次无滴答停机中不足一个 tick 的计数，计入下一次停机
+static LEFTOVER_COUNTS: AtomicU32 = AtomicU32::new(0);
+
+static HALTS: AtomicU64 = AtomicU64::new(0);
+static TICKLESS_HALTS: AtomicU64 = AtomicU64::new(0);
+static IDLE_CYCLES: AtomicU64 = AtomicU64::new(0);
+static SKIPPED_TICKS: AtomicU64 = AtomicU64::new(0);
+
+/// 空闲统计
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IdleStats {
+    /// 执行器空闲停机的次数
+    pub halts: u64,
+    /// 其中屏蔽周期时钟、按最近期限单次定时的次数
+    pub tickless_halts: u64,
+    /// 停机期间经过的 TSC 周期数
+    pub idle_cycles: u64,
+    /// 没有 PIT 中断、在唤醒后补上的 tick 数
+    pub skipped_ticks: u64,
+}
+
+pub fn idle_stats() -> IdleStats {
+    IdleStats {
+        halts: HALTS.load(Ordering::Relaxed),
+        tickless_halts: TICKLESS_HALTS.load(Ordering::Relaxed),
+        idle_cycles: IDLE_CYCLES.load(Ordering::Relaxed),
+        skipped_ticks: SKIPPED_TICKS.load(Ordering::Relaxed),
+    }
+}
+
+pub fn is_tickless() -> bool {
+    COUNTS_PER_TICK.load(Ordering::Relaxed) != 0
+}
+
+/// 以 PIT tick 为基准校准本地 APIC 定时器并启用无滴答空闲，返回每个 tick 的计数
+///
+/// 需要先映射本地 APIC（`smp::init`），并在中断开启时调用；校准会等待约三个 tick。
+pub fn enable_tickless() -> Result<u32, &'static str> {
+    if !apic::is_mapped() {
+        return Err("local APIC is not mapped");
+    }
+    if !interrupts::are_enabled() {
+        return Err("calibration needs timer interrupts");
+    }
+
+    wait_for_next_tick();
+    apic::start_one_shot_timer(u32::MAX, true);
+    for _ in 0..CALIBRATION_TICKS {
+        wait_for_next_tick();
+    }
+    let counts = u32::MAX - apic::stop_timer();
+    let counts_per_tick = counts / CALIBRATION_TICKS;
+    if counts_per_tick == 0 {
+        return Err("local APIC timer is not counting");
+    }
+    LEFTOVER_COUNTS.store(0, Ordering::Relaxed);
+    COUNTS_PER_TICK.store(counts_per_tick, Ordering::Relaxed);
+    Ok(counts_per_tick)
+}
+
+fn wait_for_next_tick() {
+    let start = current_tick();
+    while current_tick() == start {
+        hlt();
+    }
+}
+
+/// 执行器无事可做时停机，直到下一个中断
+///
+/// 调用时中断必须已经关闭，以免在检查就绪队列之后、停机之前错过唤醒；返回时中断已开启。
+pub(crate) fn idle() {
+    let start = read_tsc();
+    HALTS.fetch_add(1, Ordering::Relaxed);
+
+    let counts_per_tick = COUNTS_PER_TICK.load(Ordering::Relaxed);
+    let ticks_ahead = match next_event() {
+        Some(event) => event.saturating_sub(current_tick()),
+        None => u64::MAX,
+    };
+    if counts_per_tick == 0 || ticks_ahead <= 1 {
+        // 下一个 PIT tick 就有事情要做
+        interrupts::enable_and_hlt();
+    } else {
+        halt_tickless(counts_per_tick, ticks_ahead);
+        interrupts::enable();
+    }
+
+    IDLE_CYCLES.fetch_add(read_tsc().wrapping_sub(start), Ordering::Relaxed);
+}
+
+fn halt_tickless(counts_per_tick: u32, ticks_ahead: u64) {
+    TICKLESS_HALTS.fetch_add(1, Ordering::Relaxed);
+    let ticks = ticks_ahead.min(u64::from(u32::MAX / counts_per_tick));
+    let count = ticks as u32 * counts_per_tick;
+
+    set_timer_masked(true);
+    apic::start_one_shot_timer(count, false);
+    interrupts::enable_and_hlt();
+    interrupts::disable();
+    let counts =
+        u64::from(count - apic::stop_timer()) + u64::from(LEFTOVER_COUNTS.load(Ordering::Relaxed));
+    let elapsed = counts / u64::from(counts_per_tick);
+    LEFTOVER_COUNTS.store(
+        (counts % u64::from(counts_per_tick)) as u32,
+        Ordering::Relaxed,
+    );
+
+    // 屏蔽期间 PIT 请求过的中断在恢复后仍会送达，由它补上最后一个 tick
+    let skipped = if timer_interrupt_pending() {
+        elapsed.saturating_sub(1)
+    } else {
+        elapsed
+    };
+    set_timer_masked(false);
+    if skipped > 0 {
+        SKIPPED_TICKS.fetch_add(skipped, Ordering::Relaxed);
+        advance_ticks(skipped);
+    }
+}
@@ -0,0 +1,244 @@
+//! 分层时间轮。
+//!
+//! 共 [`LEVELS`] 层、每层 [`SLOTS`] 个槽，第 `l` 层的一个槽覆盖 `SLOTS^l` 个 tick。
+//! 登记时按到期 tick 与当前 tick 最高的不同位选择层级，插入和删除都是 O(1) 的链表操作；
+//! 当前 tick 跨过某层槽的边界时，把该槽中的登记重新分配到更低的层级，第 0 层的槽到达时
+//! 登记移入已到期链表。超出最高层范围的登记放在溢出链表中，最高层转完一圈时重新分配。
+//!
+//! 登记项存放在按块增长的槽位表中，链表用下标串联。只有任务上下文中的登记会分配新块，
+//! 时钟中断推进时间轮时不分配内存。
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::task::Waker;
+
+const LEVELS: usize = 4;
+const SLOT_BITS: u32 = 6;
+const SLOTS: usize = 1 << SLOT_BITS;
+// 槽位表每次增长的登记项数
+const CHUNK: usize = 64;
+
+// 链表编号：先是各层的槽，然后是溢出链表和已到期链表
+const OVERFLOW: u32 = (LEVELS * SLOTS) as u32;
+const EXPIRED: u32 = OVERFLOW + 1;
+const LISTS: usize = LEVELS * SLOTS + 2;
+
+const NIL: u32 = u32::MAX;
+// `prev` 带有此标志时表示该项是链表头，低位是链表编号
+const HEAD: u32 = 1 << 31;
+// 不在任何链表中：空闲，或已经到期并取走了唤醒器
+const DETACHED: u32 = u32::MAX;
+
+struct Entry {
+    deadline: u64,
+    waker: Option<Waker>,
+    prev: u32,
+    next: u32,
+}
+
+pub(super) struct TimerWheel {
+    // 时间轮已经处理到的 tick
+    now: u64,
+    // 按块分配，增长时不需要搬移已有登记，也不会申请大块连续内存
+    #[allow(clippy::vec_box)]
+    chunks: Vec<Box<[Entry; CHUNK]>>,
+    // 空闲登记项链表，用 `next` 串联
+    free: u32,
+    heads: [u32; LISTS],
+    // 尚未取走唤醒器的登记数
+    pending: usize,
+}
+
+impl TimerWheel {
+    pub(super) const fn new() -> Self {
+        TimerWheel {
+            now: 0,
+            chunks: Vec::new(),
+            free: NIL,
+            heads: [NIL; LISTS],
+            pending: 0,
+        }
+    }
+
+    pub(super) fn pending(&self) -> usize {
+        self.pending
+    }
+
+    /// 登记在 `deadline` 唤醒 `waker`，返回登记的键；`deadline` 已经处理过时返回 `None`
+    pub(super) fn insert(&mut self, deadline: u64, waker: Waker) -> Option<u32> {
+        if deadline <= self.now {
+            return None;
+        }
+        let key = self.allocate();
+        let entry = self.entry_mut(key);
+        entry.deadline = deadline;
+        entry.waker = Some(waker);
+        self.link(key);
+        self.pending += 1;
+        Some(key)
+    }
+
+    /// 更新登记的唤醒器；登记已经到期时返回 `false`
+    pub(super) fn update(&mut self, key: u32, waker: &Waker) -> bool {
+        let entry = self.entry_mut(key);
+        if entry.prev == DETACHED {
+            return false;
+        }
+        match &entry.waker {
+            Some(existing) if existing.will_wake(waker) => {}
+            _ => entry.waker = Some(waker.clone()),
+        }
+        true
+    }
+
+    /// 删除登记并回收登记项
+    pub(super) fn remove(&mut self, key: u32) {
+        if self.entry(key).prev != DETACHED {
+            self.unlink(key);
+            self.pending -= 1;
+        }
+        let free = self.free;
+        let entry = self.entry_mut(key);
+        entry.waker = None;
+        entry.prev = DETACHED;
+        entry.next = free;
+        self.free = key;
+    }
+
+    /// 逐个 tick 推进到 `tick`，到期的登记移入已到期链表
+    pub(super) fn advance(&mut self, tick: u64) {
+        while self.now < tick {
+            self.now += 1;
+            if self.now.trailing_zeros() >= SLOT_BITS * LEVELS as u32 {
+                self.cascade(OVERFLOW);
+            }
+            // 从高层到低层：高层分配下来的登记可能落在本 tick 要处理的低层槽中
+            for level in (0..LEVELS).rev() {
+                let shift = SLOT_BITS * level as u32;
+                if self.now.trailing_zeros() >= shift {
+                    let slot = (self.now >> shift) as usize & (SLOTS - 1);
+                    self.cascade(list_index(level, slot));
+                }
+            }
+        }
+    }
+
+    /// 时间轮下一次需要处理的 tick：最早的到期 tick，或者某个高层槽需要向下分配的 tick。
+    /// 没有任何登记时返回 `None`
+    pub(super) fn next_event(&self) -> Option<u64> {
+        if self.heads[EXPIRED as usize] != NIL {
+            return Some(self.now);
+        }
+        // 同一层的登记都在当前槽之后；低层的事件总是早于高层的事件
+        for level in 0..LEVELS {
+            let shift = SLOT_BITS * level as u32;
+            let current = (self.now >> shift) as usize & (SLOTS - 1);
+            let rotation_start = self.now >> (shift + SLOT_BITS) << (shift + SLOT_BITS);
+            for slot in current + 1..SLOTS {
+                if self.heads[list_index(level, slot) as usize] != NIL {
+                    return Some(rotation_start + ((slot as u64) << shift));
+                }
+            }
+        }
+        if self.heads[OVERFLOW as usize] != NIL {
+            let span = SLOT_BITS * LEVELS as u32;
+            return Some(((self.now >> span) + 1) << span);
+        }
+        None
+    }
+
+    /// 取出一个已到期登记的唤醒器，登记项留给 `Sleep` 回收
+    pub(super) fn pop_expired(&mut self) -> Option<Waker> {
+        let key = self.heads[EXPIRED as usize];
+        if key == NIL {
+            return None;
+        }
+        self.unlink(key);
+        self.pending -= 1;
+        let entry = self.entry_mut(key);
+        entry.prev = DETACHED;
+        entry.waker.take()
+    }
+
+    fn allocate(&mut self) -> u32 {
+        if self.free == NIL {
+            let base = (self.chunks.len() * CHUNK) as u32;
+            assert!(base + (CHUNK as u32) < HEAD, "timer wheel is full");
+            self.chunks
+                .push(Box::new(core::array::from_fn(|offset| Entry {
+                    deadline: 0,
+                    waker: None,
+                    prev: DETACHED,
+                    next: if offset + 1 < CHUNK {
+                        base + offset as u32 + 1
+                    } else {
+                        NIL
+                    },
+                })));
+            self.free = base;
+        }
+        let key = self.free;
+        self.free = self.entry(key).next;
+        key
+    }
+
+    // 重新分配某个链表中的全部登记
+    fn cascade(&mut self, list: u32) {
+        let mut key = core::mem::replace(&mut self.heads[list as usize], NIL);
+        while key != NIL {
+            let next = self.entry(key).next;
+            self.link(key);
+            key = next;
+        }
+    }
+
+    // 按到期 tick 与当前 tick 选择链表：最高的不同位决定层级，该层对应的位决定槽
+    fn list_for(&self, deadline: u64) -> u32 {
+        if deadline <= self.now {
+            return EXPIRED;
+        }
+        let highest_bit = u64::BITS - 1 - (deadline ^ self.now).leading_zeros();
+        let level = (highest_bit / SLOT_BITS) as usize;
+        if level >= LEVELS {
+            return OVERFLOW;
+        }
+        let slot = (deadline >> (SLOT_BITS * level as u32)) as usize & (SLOTS - 1);
+        list_index(level, slot)
+    }
+
+    fn link(&mut self, key: u32) {
+        let list = self.list_for(self.entry(key).deadline);
+        let head = self.heads[list as usize];
+        if head != NIL {
+            self.entry_mut(head).prev = key;
+        }
+        let entry = self.entry_mut(key);
+        entry.prev = HEAD | list;
+        entry.next = head;
+        self.heads[list as usize] = key;
+    }
+
+    fn unlink(&mut self, key: u32) {
+        let Entry { prev, next, .. } = *self.entry(key);
+        if prev & HEAD != 0 {
+            self.heads[(prev & !HEAD) as usize] = next;
+        } else {
+            self.entry_mut(prev).next = next;
+        }
+        if next != NIL {
+            self.entry_mut(next).prev = prev;
+        }
+    }
+
+    fn entry(&self, key: u32) -> &Entry {
+        &self.chunks[key as usize / CHUNK][key as usize % CHUNK]
+    }
+
+    fn entry_mut(&mut self, key: u32) -> &mut Entry {
+        &mut self.chunks[key as usize / CHUNK][key as usize % CHUNK]
+    }
+}
+
+fn list_index(level: usize, slot: usize) -> u32 {
+    (level * SLOTS + slot) as u32
+}
@@ -0,0 +1,354 @@
+//! # 卡死任务与丢失中断的看门狗
+//!
+//! 每个执行器在创建时占用一个观察槽，轮询任务前后和每轮调度时更新其中的状态与起始 tick。
+//! 时钟中断调用 [`on_timer_tick`] 检查所有槽：某个任务的单次 `poll` 超过阈值仍未返回，
+//! 或者执行器在任务之外停留过久，就在串口报告任务ID、名称与被中断的指令地址。
+//! 时钟中断本身也被计时：两次中断之间的 TSC 周期远超按 tick 推算的时长，说明中断被屏蔽过久。
+//!
+//! 检查只使用原子操作，不加锁也不分配内存；同一次停顿只报告一次。
+//! 卡住的执行器可能正是运行工作队列的那个，停顿报告因此在时钟中断中直接打印；
+//! 丢失中断的报告产生时中断已经恢复，打印推迟到 [`workqueue::SYSTEM`](super::workqueue::SYSTEM)。
+
+use super::accounting::read_tsc;
+use super::workqueue::{self, Work};
+use super::{timer, TaskId};
+use crate::thread;
+use core::ptr;
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicU64, AtomicU8, AtomicUsize, Ordering};
+use spin::Mutex;
+
+/// 同时被观察的执行器数上限，超出的执行器不被观察
+pub const MAX_WATCHED_EXECUTORS: usize = 8;
+/// 默认的停顿阈值，约 2 秒
+pub const DEFAULT_STALL_TICKS: u64 = 36;
+// 两次时钟中断的间隔超过应有时长这么多个 tick，才算丢失了中断
+const MISSED_TICK_SLACK: u64 = 3;
+// 估计每 tick 周期数之前至少观察的 tick 数
+const CALIBRATION_TICKS: u64 = 16;
+
+const IDLE: u8 = 0;
+const SCHEDULING: u8 = 1;
+const POLLING: u8 = 2;
+const BLOCK_ON: u8 = 3;
+
+static ENABLED: AtomicBool = AtomicBool::new(true);
+static STALL_TICKS: AtomicU64 = AtomicU64::new(DEFAULT_STALL_TICKS);
+static SLOTS: [Slot; MAX_WATCHED_EXECUTORS] = [const { Slot::new() }; MAX_WATCHED_EXECUTORS];
+
+static STUCK_POLLS: AtomicU64 = AtomicU64::new(0);
+static EXECUTOR_STALLS: AtomicU64 = AtomicU64::new(0);
+static MISSED_TICKS: AtomicU64 = AtomicU64::new(0);
+static LAST_REPORT: Mutex<Option<WatchdogReport>> = Mutex::new(None);
+// 等待工作队列打印的丢失中断报告，未打印前再次丢失时只保留最新一次
+static DEFERRED_REPORT: Mutex<Option<WatchdogReport>> = Mutex::new(None);
+
+// 时钟中断的计时，只在时钟中断中读写
+static FIRST_TSC: AtomicU64 = AtomicU64::new(0);
+static FIRST_TICK: AtomicU64 = AtomicU64::new(0);
+static LAST_TSC: AtomicU64 = AtomicU64::new(0);
+static LAST_TICK: AtomicU64 = AtomicU64::new(0);
+
+/// 看门狗发现的问题
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StallKind {
+    /// 任务的单次 `poll` 没有返回
+    TaskPoll,
+    /// `Executor::block_on` 的 future 的单次 `poll` 没有返回
+    BlockOn,
+    /// 执行器在任务之外停留过久，没有进入下一轮调度
+    Executor,
+    /// 时钟中断被屏蔽，期间丢失了若干 tick
+    TimerInterrupts,
+}
+
+/// 一次报告的内容
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchdogReport {
+    pub kind: StallKind,
+    /// 卡住的任务，只有 [`StallKind::TaskPoll`] 时存在
+    pub task_id: Option<TaskId>,
+    pub name: Option<&'static str>,
+    /// 已经停顿的 tick 数；丢失中断时为丢失的 tick 数
+    pub ticks: u64,
+    /// 被时钟中断打断的指令地址；卡住的执行器所在线程已被切换出去时为 `None`
+    pub instruction_pointer: Option<u64>,
+}
+
+/// 看门狗的累计计数
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchdogStats {
+    pub stuck_polls: u64,
+    pub executor_stalls: u64,
+    /// 估计因中断被屏蔽而丢失的 tick 数
+    pub missed_ticks: u64,
+}
+
+struct Slot {
+    claimed: AtomicBool,
+    state: AtomicU8,
+    // 进入当前状态时的 tick
+    since: AtomicU64,
+    task_id: AtomicU64,
+    name_ptr: AtomicPtr<u8>,
+    name_len: AtomicUsize,
+    // 执行器所在的线程
+    thread: AtomicU64,
+    // 当前这次停顿已经报告过
+    reported: AtomicBool,
+}
+
+impl Slot {
+    const fn new() -> Self {
+        Slot {
+            claimed: AtomicBool::new(false),
+            state: AtomicU8::new(IDLE),
+            since: AtomicU64::new(0),
+            task_id: AtomicU64::new(0),
+            name_ptr: AtomicPtr::new(ptr::null_mut()),
+            name_len: AtomicUsize::new(0),
+            thread: AtomicU64::new(0),
+            reported: AtomicBool::new(false),
+        }
+    }
+
+    fn enter(&self, state: u8) {
+        self.since.store(timer::current_tick(), Ordering::Relaxed);
+        self.thread
+            .store(thread::running_id().as_u64(), Ordering::Relaxed);
+        self.reported.store(false, Ordering::Relaxed);
+        self.state.store(state, Ordering::Release);
+    }
+
+    fn name(&self) -> Option<&'static str> {
+        let name_ptr = self.name_ptr.load(Ordering::Relaxed);
+        if name_ptr.is_null() {
+            return None;
+        }
+        let len = self.name_len.load(Ordering::Relaxed);
+        // 指针与长度来自同一个 `&'static str`，在切换任务前写入
+        let bytes = unsafe { core::slice::from_raw_parts(name_ptr, len) };
+        Some(unsafe { core::str::from_utf8_unchecked(bytes) })
+    }
+}
+
+// 执行器持有的观察槽，丢弃时归还
+pub(crate) struct ExecutorWatch(Option<&'static Slot>);
+
+impl ExecutorWatch {
+    pub(crate) fn claim() -> Self {
+        let slot = SLOTS.iter().find(|slot| {
+            slot.claimed
+                .compare_exchange(false, true, Ordering::AcqRel, Ordering::Relaxed)
+                .is_ok()
+        });
+        if let Some(slot) = slot {
+            slot.state.store(IDLE, Ordering::Release);
+        }
+        ExecutorWatch(slot)
+    }
+
+    // 新一轮调度开始，或者一次轮询刚结束
+    pub(crate) fn scheduling(&self) {
+        if let Some(slot) = self.0 {
+            slot.enter(SCHEDULING);
+        }
+    }
+
+    pub(crate) fn begin_poll(&self, task_id: TaskId, name: Option<&'static str>) {
+        if let Some(slot) = self.0 {
+            slot.task_id.store(task_id.as_u64(), Ordering::Relaxed);
+            let (name_ptr, name_len) = name.map_or((ptr::null_mut(), 0), |name| {
+                (name.as_ptr().cast_mut(), name.len())
+            });
+            slot.name_len.store(name_len, Ordering::Relaxed);
+            slot.name_ptr.store(name_ptr, Ordering::Relaxed);
+            slot.enter(POLLING);
+        }
+    }
+
+    pub(crate) fn begin_block_on(&self) {
+        if let Some(slot) = self.0 {
+            slot.enter(BLOCK_ON);
+        }
+    }
+
+    // 执行器停机等待中断，不算停顿
+    pub(crate) fn idle(&self) {
+        if let Some(slot) = self.0 {
+            slot.state.store(IDLE, Ordering::Release);
+        }
+    }
+}
+
+impl Drop for ExecutorWatch {
+    fn drop(&mut self) {
+        if let Some(slot) = self.0 {
+            slot.state.store(IDLE, Ordering::Release);
+            slot.claimed.store(false, Ordering::Release);
+        }
+    }
+}
+
+/// 开启或关闭检查，返回之前的状态
+pub fn set_enabled(enabled: bool) -> bool {
+    ENABLED.swap(enabled, Ordering::Relaxed)
+}
+
+/// 设置停顿阈值（tick 数）
+pub fn set_stall_threshold(ticks: u64) {
+    assert!(ticks > 0, "stall threshold must be positive");
+    STALL_TICKS.store(ticks, Ordering::Relaxed);
+}
+
+pub fn stall_threshold() -> u64 {
+    STALL_TICKS.load(Ordering::Relaxed)
+}
+
+pub fn stats() -> WatchdogStats {
+    WatchdogStats {
+        stuck_polls: STUCK_POLLS.load(Ordering::Relaxed),
+        executor_stalls: EXECUTOR_STALLS.load(Ordering::Relaxed),
+        missed_ticks: MISSED_TICKS.load(Ordering::Relaxed),
+    }
+}
+
+/// 最近一次报告
+pub fn last_report() -> Option<WatchdogReport> {
+    x86_64::instructions::interrupts::without_interrupts(|| *LAST_REPORT.lock())
+}
+
+/// 根据时钟中断的间隔估计的每 tick TSC 周期数，观察的 tick 不够时为 `None`
+pub fn cycles_per_tick() -> Option<u64> {
+    let first_tsc = FIRST_TSC.load(Ordering::Relaxed);
+    let ticks = LAST_TICK
+        .load(Ordering::Relaxed)
+        .saturating_sub(FIRST_TICK.load(Ordering::Relaxed));
+    if first_tsc == 0 || ticks < CALIBRATION_TICKS {
+        return None;
+    }
+    Some(LAST_TSC.load(Ordering::Relaxed).wrapping_sub(first_tsc) / ticks)
+}
+
+/// 时钟中断在推进 tick 之后调用，`instruction_pointer` 是被打断的指令地址
+pub(crate) fn on_timer_tick(instruction_pointer: u64) {
+    let now_tsc = read_tsc();
+    let now = timer::current_tick();
+    if ENABLED.load(Ordering::Relaxed) {
+        check_missed_ticks(now_tsc, now, instruction_pointer);
+        check_executors(now, instruction_pointer);
+    }
+    if FIRST_TSC.load(Ordering::Relaxed) == 0 {
+        FIRST_TSC.store(now_tsc, Ordering::Relaxed);
+        FIRST_TICK.store(now, Ordering::Relaxed);
+    }
+    LAST_TSC.store(now_tsc, Ordering::Relaxed);
+    LAST_TICK.store(now, Ordering::Relaxed);
+}
+
+// 与上一次时钟中断比较：间隔比推进的 tick 数长出 `MISSED_TICK_SLACK` 个 tick 以上即为丢失中断。
+// 无滴答空闲补上的 tick 计入推进数，因此不会误报
+fn check_missed_ticks(now_tsc: u64, now: u64, instruction_pointer: u64) {
+    let Some(cycles_per_tick) = cycles_per_tick().filter(|&cycles| cycles > 0) else {
+        return;
+    };
+    let elapsed = now_tsc.wrapping_sub(LAST_TSC.load(Ordering::Relaxed)) / cycles_per_tick;
+    let advanced = now.saturating_sub(LAST_TICK.load(Ordering::Relaxed));
+    if elapsed <= advanced + MISSED_TICK_SLACK {
+        return;
+    }
+    let missed = elapsed - advanced;
+    MISSED_TICKS.fetch_add(missed, Ordering::Relaxed);
+    // 中断重新打开后立即送达，被打断的正是屏蔽中断的代码之后
+    let report = WatchdogReport {
+        kind: StallKind::TimerInterrupts,
+        task_id: None,
+        name: None,
+        ticks: missed,
+        instruction_pointer: Some(instruction_pointer),
+    };
+    record(report);
+    if let Some(mut deferred) = DEFERRED_REPORT.try_lock() {
+        *deferred = Some(report);
+    }
+    workqueue::SYSTEM.queue(Work::new(print_deferred_report, 0));
+}
+
+fn print_deferred_report(_: usize) {
+    let report =
+        x86_64::instructions::interrupts::without_interrupts(|| DEFERRED_REPORT.lock().take());
+    if let Some(report) = report {
+        print(report);
+    }
+}
+
+fn check_executors(now: u64, instruction_pointer: u64) {
+    let threshold = STALL_TICKS.load(Ordering::Relaxed);
+    let running = thread::running_id().as_u64();
+    for slot in &SLOTS {
+        if !slot.claimed.load(Ordering::Acquire) {
+            continue;
+        }
+        let state = slot.state.load(Ordering::Acquire);
+        let stalled = now.saturating_sub(slot.since.load(Ordering::Relaxed));
+        if state == IDLE || stalled < threshold || slot.reported.load(Ordering::Relaxed) {
+            continue;
+        }
+        let (kind, task_id, name) = match state {
+            POLLING => (
+                StallKind::TaskPoll,
+                Some(TaskId(slot.task_id.load(Ordering::Relaxed))),
+                slot.name(),
+            ),
+            BLOCK_ON => (StallKind::BlockOn, None, None),
+            _ => (StallKind::Executor, None, None),
+        };
+        match kind {
+            StallKind::Executor => EXECUTOR_STALLS.fetch_add(1, Ordering::Relaxed),
+            _ => STUCK_POLLS.fetch_add(1, Ordering::Relaxed),
+        };
+        slot.reported.store(true, Ordering::Relaxed);
+        let on_this_thread = slot.thread.load(Ordering::Relaxed) == running;
+        let report = WatchdogReport {
+            kind,
+            task_id,
+            name,
+            ticks: stalled,
+            instruction_pointer: on_this_thread.then_some(instruction_pointer),
+        };
+        record(report);
+        print(report);
+    }
+}
+
+fn record(report: WatchdogReport) {
+    if let Some(mut last) = LAST_REPORT.try_lock() {
+        *last = Some(report);
+    }
+}
+
+// 串口输出在关中断时持锁，时钟中断里打印不会与被打断的代码死锁
+fn print(report: WatchdogReport) {
+    let task_id = report.task_id.map(TaskId::as_u64);
+    let name = report.name.unwrap_or("-");
+    match report.instruction_pointer {
+        Some(rip) => {
+            crate::serial_println!(
+                "[watchdog] {:?} stalled for {} ticks: task={:?} name={} rip={:#x}",
+                report.kind,
+                report.ticks,
+                task_id,
+                name,
+                rip,
+            );
+        }
+        None => {
+            crate::serial_println!(
+                "[watchdog] {:?} stalled for {} ticks: task={:?} name={} rip=- (thread switched out)",
+                report.kind,
+                report.ticks,
+                task_id,
+                name,
+            );
+        }
+    }
+}
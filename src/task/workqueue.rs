@@ -0,0 +1,227 @@
+//! # 延迟工作队列（中断下半部）
+//!
+//! 中断处理程序只做必须立即完成的部分，其余工作打包成 [`Work`] 放入 [`WorkQueue`]，
+//! 之后由运行 [`WorkQueue::worker`] 的高优先级任务在开中断的上下文中执行。
+//! 入队不分配内存、不等待：队列是定长环形缓冲区，满时丢弃并计数。
+//! 全局的 [`SYSTEM`] 队列供中断处理程序使用；创建工作者时队列被登记到
+//! [`queue_stats`] 的列表中。
+
+use super::accounting::read_tsc;
+use alloc::vec::Vec;
+use core::future::{poll_fn, Future};
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use core::task::Poll;
+use futures_util::task::AtomicWaker;
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+/// 每个队列最多容纳的工作数
+pub const WORK_QUEUE_CAPACITY: usize = 64;
+// 工作者每次被轮询最多执行的工作数，超过后让出执行权
+const WORKER_BUDGET: usize = 16;
+
+/// 中断处理程序使用的全局队列
+pub static SYSTEM: WorkQueue = WorkQueue::new("system");
+
+static REGISTERED: Mutex<Vec<&'static WorkQueue>> = Mutex::new(Vec::new());
+
+/// 一项延迟执行的工作：函数指针加一个参数，可以在中断中复制而不分配内存
+#[derive(Debug, Clone, Copy)]
+pub struct Work {
+    function: fn(usize),
+    argument: usize,
+}
+
+impl Work {
+    pub const fn new(function: fn(usize), argument: usize) -> Self {
+        Work { function, argument }
+    }
+
+    fn run(self) {
+        (self.function)(self.argument)
+    }
+}
+
+/// 某个队列的统计快照
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WorkQueueStats {
+    pub name: &'static str,
+    /// 成功入队的工作数
+    pub queued: u64,
+    /// 已经执行完的工作数
+    pub completed: u64,
+    /// 队列满而被丢弃的工作数
+    pub dropped: u64,
+    /// 当前排队的工作数
+    pub pending: usize,
+    /// 排队数的历史最大值
+    pub high_watermark: usize,
+    /// 从入队到开始执行的最长等待（TSC 周期）
+    pub max_delay_cycles: u64,
+    /// 累计执行耗时（TSC 周期）
+    pub run_cycles: u64,
+}
+
+#[derive(Clone, Copy)]
+struct Queued {
+    work: Work,
+    queued_at: u64,
+}
+
+struct Ring {
+    items: [Option<Queued>; WORK_QUEUE_CAPACITY],
+    head: usize,
+    len: usize,
+}
+
+/// 定长的延迟工作队列
+pub struct WorkQueue {
+    name: &'static str,
+    ring: Mutex<Ring>,
+    waker: AtomicWaker,
+    registered: AtomicBool,
+    queued: AtomicU64,
+    completed: AtomicU64,
+    dropped: AtomicU64,
+    high_watermark: AtomicUsize,
+    max_delay_cycles: AtomicU64,
+    run_cycles: AtomicU64,
+}
+
+impl WorkQueue {
+    pub const fn new(name: &'static str) -> Self {
+        WorkQueue {
+            name,
+            ring: Mutex::new(Ring {
+                items: [None; WORK_QUEUE_CAPACITY],
+                head: 0,
+                len: 0,
+            }),
+            waker: AtomicWaker::new(),
+            registered: AtomicBool::new(false),
+            queued: AtomicU64::new(0),
+            completed: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+            high_watermark: AtomicUsize::new(0),
+            max_delay_cycles: AtomicU64::new(0),
+            run_cycles: AtomicU64::new(0),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// 放入一项工作并唤醒工作者；队列满时丢弃并返回 `false`。可以在中断处理程序中调用
+    pub fn queue(&self, work: Work) -> bool {
+        let queued_at = read_tsc();
+        let depth = interrupts::without_interrupts(|| {
+            let mut ring = self.ring.lock();
+            if ring.len == WORK_QUEUE_CAPACITY {
+                return None;
+            }
+            let index = (ring.head + ring.len) % WORK_QUEUE_CAPACITY;
+            ring.items[index] = Some(Queued { work, queued_at });
+            ring.len += 1;
+            Some(ring.len)
+        });
+        match depth {
+            Some(depth) => {
+                self.queued.fetch_add(1, Ordering::Relaxed);
+                self.high_watermark.fetch_max(depth, Ordering::Relaxed);
+                self.waker.wake();
+                true
+            }
+            None => {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+                false
+            }
+        }
+    }
+
+    /// 当前排队的工作数
+    pub fn len(&self) -> usize {
+        interrupts::without_interrupts(|| self.ring.lock().len)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// 按入队顺序执行最多 `budget` 项工作，返回执行的数量
+    ///
+    /// 只有出队时短暂关中断，工作本身在调用者的中断状态下执行。
+    pub fn run_pending(&self, budget: usize) -> usize {
+        let mut ran = 0;
+        while ran < budget {
+            let Some(queued) = self.pop() else {
+                break;
+            };
+            let start = read_tsc();
+            self.max_delay_cycles
+                .fetch_max(start.saturating_sub(queued.queued_at), Ordering::Relaxed);
+            queued.work.run();
+            self.run_cycles
+                .fetch_add(read_tsc().saturating_sub(start), Ordering::Relaxed);
+            self.completed.fetch_add(1, Ordering::Relaxed);
+            ran += 1;
+        }
+        ran
+    }
+
+    /// 永不结束的工作者任务：等待工作并执行，每执行一批就让出一次执行权。
+    /// 通常以 `TaskPriority::High` 或更高的优先级派生，每个队列只应有一个工作者
+    pub fn worker(&'static self) -> impl Future<Output = ()> + Send + 'static {
+        self.register();
+        poll_fn(move |context| {
+            self.waker.register(context.waker());
+            if self.run_pending(WORKER_BUDGET) == WORKER_BUDGET && !self.is_empty() {
+                // 还有工作，重新排队，让同优先级的任务也有机会运行
+                context.waker().wake_by_ref();
+            }
+            Poll::Pending
+        })
+    }
+
+    pub fn stats(&self) -> WorkQueueStats {
+        WorkQueueStats {
+            name: self.name,
+            queued: self.queued.load(Ordering::Relaxed),
+            completed: self.completed.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+            pending: self.len(),
+            high_watermark: self.high_watermark.load(Ordering::Relaxed),
+            max_delay_cycles: self.max_delay_cycles.load(Ordering::Relaxed),
+            run_cycles: self.run_cycles.load(Ordering::Relaxed),
+        }
+    }
+
+    fn pop(&self) -> Option<Queued> {
+        interrupts::without_interrupts(|| {
+            let mut ring = self.ring.lock();
+            if ring.len == 0 {
+                return None;
+            }
+            let head = ring.head;
+            let queued = ring.items[head].take();
+            ring.head = (head + 1) % WORK_QUEUE_CAPACITY;
+            ring.len -= 1;
+            queued
+        })
+    }
+
+    fn register(&'static self) {
+        if !self.registered.swap(true, Ordering::Relaxed) {
+            REGISTERED.lock().push(self);
+        }
+    }
+}
+
+/// 所有创建过工作者的队列的统计
+pub fn queue_stats() -> Vec<WorkQueueStats> {
+    REGISTERED
+        .lock()
+        .iter()
+        .map(|queue| queue.stats())
+        .collect()
+}
@@ -0,0 +1,327 @@
+//! 可抢占的内核线程。
+//!
+//! 每个线程有自己的内核栈和保存的寄存器上下文，由时钟中断按时间片轮转抢占，
+//! 因此一个永不让出的线程也不会饿死其他线程。异步任务仍由 [`Executor`] 协作调度，
+//! [`spawn_executor`] 可以让一个执行器运行在独立的线程上。
+//!
+//! 线程切换可能发生在中断处理中，所以切换路径上只使用定长的无锁队列，不分配也不释放内存；
+//! 已结束线程的栈在之后的 [`spawn`] 或 [`join`] 中回收。
+
+use crate::process::{self, Pid};
+use crate::task::coop;
+use crate::task::executor::Executor;
+use crate::task::local::SavedLocals;
+use crate::usermode::ExecutionState;
+use alloc::boxed::Box;
+use alloc::collections::BTreeSet;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::arch::global_asm;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use crossbeam_queue::ArrayQueue;
+use lazy_static::lazy_static;
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+use x86_64::structures::paging::PhysFrame;
+
+/// 同时存在的线程数上限（包括启动线程）
+pub const MAX_THREADS: usize = 16;
+
+const THREAD_STACK_SIZE: usize = 4096 * 4;
+// 每个线程连续运行的时钟 tick 数
+const TIME_SLICE_TICKS: u64 = 1;
+
+static PREEMPTION_ENABLED: AtomicBool = AtomicBool::new(false);
+static SLICE_REMAINING: AtomicU64 = AtomicU64::new(TIME_SLICE_TICKS);
+static CURRENT: Mutex<Option<Box<Thread>>> = Mutex::new(None);
+// 正在运行的线程，切换时更新，供中断处理程序无锁读取
+static RUNNING: AtomicU64 = AtomicU64::new(0);
+// 仍在运行或等待运行的线程，只在线程上下文中访问
+static LIVE_THREADS: Mutex<BTreeSet<ThreadId>> = Mutex::new(BTreeSet::new());
+
+lazy_static! {
+    static ref READY: ArrayQueue<Box<Thread>> = ArrayQueue::new(MAX_THREADS);
+    static ref FINISHED: ArrayQueue<Box<Thread>> = ArrayQueue::new(MAX_THREADS);
+}
+
+/// 线程标识符，启动线程为0
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ThreadId(u64);
+
+impl ThreadId {
+    fn new() -> ThreadId {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+        ThreadId(NEXT_ID.fetch_add(1, Ordering::Relaxed))
+    }
+
+    pub fn as_u64(self) -> u64 {
+        self.0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpawnError {
+    /// 线程数达到 [`MAX_THREADS`]
+    TooManyThreads,
+}
+
+type ThreadEntry = Box<dyn FnOnce() + Send>;
+
+struct Thread {
+    id: ThreadId,
+    // 切换出去时保存的栈指针，其余寄存器保存在栈上
+    saved_rsp: u64,
+    // 启动线程使用引导程序提供的栈，这里为 None
+    _stack: Option<Vec<u8>>,
+    // 线程被切换出去时所在的地址空间和用户态状态
+    level_4_frame: PhysFrame,
+    execution_state: ExecutionState,
+    current_pid: Option<Pid>,
+    // 线程被切换出去时正在轮询的任务剩余的协作预算与局部变量表
+    coop_budget: u32,
+    task_locals: SavedLocals,
+}
+
+global_asm!(
+    // fn thread_switch(old_rsp: *mut u64, new_rsp: u64)
+    ".global thread_switch",
+    "thread_switch:",
+    "push rbp",
+    "push rbx",
+    "push r12",
+    "push r13",
+    "push r14",
+    "push r15",
+    "mov [rdi], rsp",
+    "mov rsp, rsi",
+    "pop r15",
+    "pop r14",
+    "pop r13",
+    "pop r12",
+    "pop rbx",
+    "pop rbp",
+    "ret",
+    // 新线程第一次被切换到时从这里开始，rbx 中是线程入口闭包
+    ".global thread_trampoline",
+    "thread_trampoline:",
+    "mov rdi, rbx",
+    "call {start}",
+    "ud2",
+    start = sym thread_start,
+);
+
+extern "C" {
+    fn thread_switch(old_rsp: *mut u64, new_rsp: u64);
+    fn thread_trampoline();
+}
+
+/// 把当前执行流登记为启动线程并开启时钟抢占
+///
+/// 必须在堆初始化之后调用，且只能调用一次。
+pub fn init() {
+    use x86_64::registers::control::Cr3;
+
+    let boot_thread = Box::new(Thread {
+        id: ThreadId(0),
+        saved_rsp: 0,
+        _stack: None,
+        level_4_frame: Cr3::read().0,
+        execution_state: ExecutionState::initial(),
+        current_pid: None,
+        coop_budget: coop::UNCONSTRAINED,
+        task_locals: SavedLocals::NONE,
+    });
+    LIVE_THREADS.lock().insert(boot_thread.id);
+    interrupts::without_interrupts(|| {
+        let mut current = CURRENT.lock();
+        assert!(current.is_none(), "thread::init called twice");
+        *current = Some(boot_thread);
+    });
+    PREEMPTION_ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// 创建一个新线程执行 `entry`，线程在下一次调度时开始运行
+pub fn spawn<F>(entry: F) -> Result<ThreadId, SpawnError>
+where
+    F: FnOnce() + Send + 'static,
+{
+    use x86_64::registers::control::Cr3;
+
+    reap_finished();
+    let id = ThreadId::new();
+    {
+        let mut live_threads = LIVE_THREADS.lock();
+        if live_threads.len() >= MAX_THREADS {
+            return Err(SpawnError::TooManyThreads);
+        }
+        live_threads.insert(id);
+    }
+
+    let entry: *mut ThreadEntry = Box::into_raw(Box::new(Box::new(entry)));
+    let mut stack = vec![0u8; THREAD_STACK_SIZE];
+    // 初始栈与 `thread_switch` 压栈的布局一致：r15..rbp，最后是返回地址
+    let stack_top = (stack.as_mut_ptr() as u64 + THREAD_STACK_SIZE as u64) & !0xf;
+    let initial_frame = [
+        0,
+        0,
+        0,
+        0,
+        entry as u64,
+        0,
+        thread_trampoline as *const () as u64,
+    ];
+    let saved_rsp = stack_top - core::mem::size_of_val(&initial_frame) as u64;
+    unsafe {
+        core::ptr::copy_nonoverlapping(
+            initial_frame.as_ptr(),
+            saved_rsp as *mut u64,
+            initial_frame.len(),
+        );
+    }
+
+    let thread = Box::new(Thread {
+        id,
+        saved_rsp,
+        _stack: Some(stack),
+        level_4_frame: Cr3::read().0,
+        execution_state: ExecutionState::initial(),
+        current_pid: None,
+        coop_budget: coop::UNCONSTRAINED,
+        task_locals: SavedLocals::NONE,
+    });
+    if READY.push(thread).is_err() {
+        unreachable!("ready queue holds at most MAX_THREADS threads");
+    }
+    Ok(id)
+}
+
+/// 在新线程上创建执行器，`setup` 负责向其中添加任务，随后线程一直运行该执行器
+pub fn spawn_executor<F>(setup: F) -> Result<ThreadId, SpawnError>
+where
+    F: FnOnce(&mut Executor) + Send + 'static,
+{
+    spawn(move || {
+        let mut executor = Executor::new();
+        setup(&mut executor);
+        executor.run();
+    })
+}
+
+/// 当前线程的标识符；`init` 之前返回启动线程的标识符
+pub fn current_id() -> ThreadId {
+    interrupts::without_interrupts(|| {
+        CURRENT
+            .lock()
+            .as_ref()
+            .map_or(ThreadId(0), |thread| thread.id)
+    })
+}
+
+// 同 `current_id`，但不加锁，可以在中断处理程序中调用
+pub(crate) fn running_id() -> ThreadId {
+    ThreadId(RUNNING.load(Ordering::Relaxed))
+}
+
+/// 线程是否已经结束
+pub fn is_finished(id: ThreadId) -> bool {
+    !LIVE_THREADS.lock().contains(&id)
+}
+
+/// 是否有其他线程在等待运行
+pub fn has_ready_threads() -> bool {
+    !READY.is_empty()
+}
+
+/// 主动让出 CPU，轮到其他就绪线程运行
+pub fn yield_now() {
+    interrupts::without_interrupts(|| switch(false));
+}
+
+/// 让出 CPU 直到线程 `id` 结束
+pub fn join(id: ThreadId) {
+    while !is_finished(id) {
+        yield_now();
+    }
+    reap_finished();
+}
+
+/// 结束当前线程
+pub fn exit() -> ! {
+    let id = current_id();
+    assert_ne!(id, ThreadId(0), "the boot thread cannot exit");
+    LIVE_THREADS.lock().remove(&id);
+    interrupts::disable();
+    switch(true);
+    unreachable!("finished thread was scheduled again");
+}
+
+/// 时钟中断处理程序在发送 EOI 之后调用，时间片用完时切换到下一个线程
+pub(crate) fn on_timer_tick() {
+    if !PREEMPTION_ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    if SLICE_REMAINING.fetch_sub(1, Ordering::Relaxed) <= 1 {
+        switch(false);
+    }
+}
+
+// 切换到下一个就绪线程；必须在屏蔽中断的情况下调用
+fn switch(finished: bool) {
+    use x86_64::registers::control::{Cr3, Cr3Flags};
+
+    SLICE_REMAINING.store(TIME_SLICE_TICKS, Ordering::Relaxed);
+    let mut current = CURRENT.lock();
+    // `init` 之前当前执行流还不是线程，不能切换
+    if current.is_none() {
+        return;
+    }
+    let next = match READY.pop() {
+        Some(next) => next,
+        // 没有其他线程可运行，结束的线程一定不是最后一个（启动线程不会结束）
+        None => return,
+    };
+    let mut previous = current.replace(next).unwrap();
+
+    previous.level_4_frame = Cr3::read().0;
+    previous.execution_state = ExecutionState::save();
+    previous.current_pid = process::current_pid();
+    previous.coop_budget = coop::current_budget();
+    previous.task_locals = SavedLocals::save();
+
+    let next = current.as_ref().unwrap();
+    RUNNING.store(next.id.0, Ordering::Relaxed);
+    unsafe {
+        if next.level_4_frame != previous.level_4_frame {
+            Cr3::write(next.level_4_frame, Cr3Flags::empty());
+        }
+        next.execution_state.restore();
+    }
+    process::set_current_pid(next.current_pid);
+    coop::set_budget(next.coop_budget);
+    next.task_locals.restore();
+
+    // 线程对象在堆上，移入队列后地址不变
+    let previous_rsp: *mut u64 = &mut previous.saved_rsp;
+    let next_rsp = next.saved_rsp;
+    let queue = if finished { &*FINISHED } else { &*READY };
+    if queue.push(previous).is_err() {
+        unreachable!("thread queues hold at most MAX_THREADS threads");
+    }
+    drop(current);
+    unsafe { thread_switch(previous_rsp, next_rsp) };
+}
+
+// 释放已结束线程的栈；只在开中断的线程上下文中调用
+fn reap_finished() {
+    while let Some(thread) = FINISHED.pop() {
+        drop(thread);
+    }
+}
+
+extern "C" fn thread_start(entry: *mut ThreadEntry) -> ! {
+    // 新线程是在屏蔽中断的切换路径中第一次运行的
+    interrupts::enable();
+    let entry = unsafe { Box::from_raw(entry) };
+    entry();
+    exit()
+}
@@ -0,0 +1,417 @@
+//! 用户态（ring 3）支持。
+//!
+//! `resume` 把保存的用户上下文装入寄存器后通过 `iretq` 进入 ring 3，并记下当前内核上下文；
+//! 用户程序执行 `exit`、`yield` 或 `sleep` 系统调用时，`suspend` 把用户寄存器写回上下文，
+//! 再恢复内核上下文，使 `resume` 像普通函数一样带着暂停原因返回。
+
+use crate::syscall::SyscallFrame;
+use alloc::vec::Vec;
+use core::arch::global_asm;
+use core::mem::offset_of;
+use core::ptr::{addr_of_mut, null_mut};
+use spin::Mutex;
+use x86_64::structures::paging::{
+    FrameAllocator, Mapper, OffsetPageTable, Page, PageTableFlags, Size4KiB,
+};
+use x86_64::VirtAddr;
+
+/// 用户地址空间起始地址（4级页表第64项）
+pub const USER_SPACE_START: u64 = 0x0000_2000_0000_0000;
+/// 用户地址空间结束地址（不含，4级页表第128项）
+pub const USER_SPACE_END: u64 = 0x0000_4000_0000_0000;
+
+const CAPTURED_OUTPUT_LIMIT: usize = 4096;
+
+// 用户态 RFLAGS 只保留 IF，IOPL 等特权位一律清除
+const USER_RFLAGS: u64 = 0x202;
+const RFLAGS_USER_MASK: u64 = 0xcd5;
+
+static CAPTURED_OUTPUT: Mutex<Vec<u8>> = Mutex::new(Vec::new());
+
+// `resume` 保存的内核栈指针，`suspend` 从这里恢复
+#[no_mangle]
+static mut USER_RETURN_RSP: u64 = 0;
+
+// 正在运行的用户程序的上下文，`suspend` 把用户寄存器写到这里
+static mut ACTIVE_CONTEXT: *mut UserContext = null_mut();
+
+/// 用户程序暂停时的寄存器状态
+#[derive(Debug, Clone, Default)]
+#[repr(C)]
+pub struct UserContext {
+    pub rax: u64,
+    pub rbx: u64,
+    pub rcx: u64,
+    pub rdx: u64,
+    pub rsi: u64,
+    pub rdi: u64,
+    pub rbp: u64,
+    pub r8: u64,
+    pub r9: u64,
+    pub r10: u64,
+    pub r11: u64,
+    pub r12: u64,
+    pub r13: u64,
+    pub r14: u64,
+    pub r15: u64,
+    pub rip: u64,
+    pub rflags: u64,
+    pub rsp: u64,
+}
+
+impl UserContext {
+    /// 从 `entry` 开始执行、使用 `stack_top` 作为栈顶的初始上下文，其余寄存器清零
+    pub fn new(entry: VirtAddr, stack_top: VirtAddr) -> Self {
+        UserContext {
+            rip: entry.as_u64(),
+            rsp: stack_top.as_u64(),
+            rflags: USER_RFLAGS,
+            ..UserContext::default()
+        }
+    }
+
+    // 系统调用返回后用户程序应看到的寄存器：`rax` 为返回值，`rcx`/`r11` 被 `syscall` 破坏
+    fn after_syscall(frame: &SyscallFrame, return_value: u64) -> Self {
+        let rflags = (frame.user_rflags & RFLAGS_USER_MASK) | USER_RFLAGS;
+        UserContext {
+            rax: return_value,
+            rbx: frame.rbx,
+            rcx: frame.user_rip,
+            rdx: frame.arg2,
+            rsi: frame.arg1,
+            rdi: frame.arg0,
+            rbp: frame.rbp,
+            r8: frame.arg4,
+            r9: frame.arg5,
+            r10: frame.arg3,
+            r11: rflags,
+            r12: frame.r12,
+            r13: frame.r13,
+            r14: frame.r14,
+            r15: frame.r15,
+            rip: frame.user_rip,
+            rflags,
+            rsp: frame.user_rsp,
+        }
+    }
+}
+
+/// 进入和返回用户态时使用的全局状态
+///
+/// 这些状态属于当前的内核执行流，内核线程切换时需要随线程一起保存和恢复。
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ExecutionState {
+    privilege_stack: VirtAddr,
+    syscall_stack: VirtAddr,
+    user_return_rsp: u64,
+    active_context: usize,
+}
+
+impl ExecutionState {
+    /// 还没有进入过用户态的执行流使用的初始状态
+    pub(crate) fn initial() -> Self {
+        use crate::gdt;
+
+        ExecutionState {
+            privilege_stack: gdt::default_privilege_stack(),
+            syscall_stack: gdt::default_privilege_stack(),
+            user_return_rsp: 0,
+            active_context: 0,
+        }
+    }
+
+    pub(crate) fn save() -> Self {
+        use crate::{gdt, syscall};
+
+        ExecutionState {
+            privilege_stack: gdt::privilege_stack(),
+            syscall_stack: syscall::kernel_stack(),
+            user_return_rsp: unsafe { *addr_of_mut!(USER_RETURN_RSP) },
+            active_context: unsafe { *addr_of_mut!(ACTIVE_CONTEXT) } as usize,
+        }
+    }
+
+    /// # Safety
+    /// 只能在切换到保存该状态的执行流时调用。
+    pub(crate) unsafe fn restore(&self) {
+        use crate::{gdt, syscall};
+
+        gdt::set_privilege_stack(self.privilege_stack);
+        syscall::set_kernel_stack(self.syscall_stack);
+        *addr_of_mut!(USER_RETURN_RSP) = self.user_return_rsp;
+        *addr_of_mut!(ACTIVE_CONTEXT) = self.active_context as *mut UserContext;
+    }
+}
+
+/// 用户程序把控制权交还内核的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UserExit {
+    /// 调用了 `exit`，附带退出码
+    Exited(u64),
+    /// 调用了 `yield`
+    Yielded,
+    /// 调用了 `sleep`，附带要等待的 tick 数
+    Sleep(u64),
+}
+
+// 汇编返回的暂停原因，放在 rax:rdx 中
+#[repr(C)]
+struct RawUserExit {
+    kind: u64,
+    value: u64,
+}
+
+const EXIT_KIND_EXITED: u64 = 0;
+const EXIT_KIND_YIELDED: u64 = 1;
+const EXIT_KIND_SLEEP: u64 = 2;
+
+global_asm!(
+    // fn user_resume(context: *const UserContext, user_cs: u64, user_ss: u64) -> RawUserExit
+    ".global user_resume",
+    "user_resume:",
+    "push rbp",
+    "push rbx",
+    "push r12",
+    "push r13",
+    "push r14",
+    "push r15",
+    "mov [rip + USER_RETURN_RSP], rsp",
+    // 构造 iretq 栈帧：SS、RSP、RFLAGS、CS、RIP
+    "push rdx",
+    "push [rdi + {rsp}]",
+    "push [rdi + {rflags}]",
+    "push rsi",
+    "push [rdi + {rip}]",
+    // 装入用户寄存器，rdi 保存着上下文地址，最后恢复
+    "mov rax, [rdi + {rax}]",
+    "mov rbx, [rdi + {rbx}]",
+    "mov rcx, [rdi + {rcx}]",
+    "mov rdx, [rdi + {rdx}]",
+    "mov rsi, [rdi + {rsi}]",
+    "mov rbp, [rdi + {rbp}]",
+    "mov r8, [rdi + {r8}]",
+    "mov r9, [rdi + {r9}]",
+    "mov r10, [rdi + {r10}]",
+    "mov r11, [rdi + {r11}]",
+    "mov r12, [rdi + {r12}]",
+    "mov r13, [rdi + {r13}]",
+    "mov r14, [rdi + {r14}]",
+    "mov r15, [rdi + {r15}]",
+    "mov rdi, [rdi + {rdi}]",
+    "iretq",
+    // fn user_return(kind: u64, value: u64) -> !
+    ".global user_return",
+    "user_return:",
+    "mov rax, rdi",
+    "mov rdx, rsi",
+    "mov rsp, [rip + USER_RETURN_RSP]",
+    "pop r15",
+    "pop r14",
+    "pop r13",
+    "pop r12",
+    "pop rbx",
+    "pop rbp",
+    "ret",
+    rax = const offset_of!(UserContext, rax),
+    rbx = const offset_of!(UserContext, rbx),
+    rcx = const offset_of!(UserContext, rcx),
+    rdx = const offset_of!(UserContext, rdx),
+    rsi = const offset_of!(UserContext, rsi),
+    rdi = const offset_of!(UserContext, rdi),
+    rbp = const offset_of!(UserContext, rbp),
+    r8 = const offset_of!(UserContext, r8),
+    r9 = const offset_of!(UserContext, r9),
+    r10 = const offset_of!(UserContext, r10),
+    r11 = const offset_of!(UserContext, r11),
+    r12 = const offset_of!(UserContext, r12),
+    r13 = const offset_of!(UserContext, r13),
+    r14 = const offset_of!(UserContext, r14),
+    r15 = const offset_of!(UserContext, r15),
+    rip = const offset_of!(UserContext, rip),
+    rflags = const offset_of!(UserContext, rflags),
+    rsp = const offset_of!(UserContext, rsp),
+);
+
+extern "C" {
+    fn user_resume(context: *const UserContext, user_cs: u64, user_ss: u64) -> RawUserExit;
+    fn user_return(kind: u64, value: u64) -> !;
+}
+
+/// 在 ring 3 中从 `context` 继续执行，直到用户程序通过系统调用交还控制权
+///
+/// 返回 `Yielded` 或 `Sleep` 时，`context` 已更新为系统调用之后的状态，可以再次传入以继续执行。
+/// `kernel_stack_top` 会被设置为 TSS 的 RSP0 和 `syscall` 入口使用的内核栈。
+///
+/// # Safety
+/// `context` 中的指令和栈地址必须在当前地址空间中映射为用户可访问；
+/// `kernel_stack_top` 必须指向一块在用户程序运行期间专用的内核栈。
+pub unsafe fn resume(context: &mut UserContext, kernel_stack_top: VirtAddr) -> UserExit {
+    use crate::{gdt, syscall};
+    use x86_64::instructions::interrupts;
+    use x86_64::registers::model_specific::GsBase;
+
+    let interrupts_enabled = interrupts::are_enabled();
+    gdt::set_privilege_stack(kernel_stack_top);
+    syscall::set_kernel_stack(kernel_stack_top);
+
+    // 用户程序加载 GS 选择子会改写 GS 基址，返回后恢复每 CPU 数据的地址
+    let gs_base = GsBase::read();
+    *addr_of_mut!(ACTIVE_CONTEXT) = context;
+    let raw = user_resume(
+        context,
+        u64::from(gdt::user_code_selector().0),
+        u64::from(gdt::user_data_selector().0),
+    );
+    *addr_of_mut!(ACTIVE_CONTEXT) = null_mut();
+    GsBase::write(gs_base);
+
+    // 系统调用在屏蔽中断的上下文中返回，这里恢复调用前的中断状态
+    if interrupts_enabled {
+        interrupts::enable();
+    }
+    match raw.kind {
+        EXIT_KIND_YIELDED => UserExit::Yielded,
+        EXIT_KIND_SLEEP => UserExit::Sleep(raw.value),
+        _ => UserExit::Exited(raw.value),
+    }
+}
+
+/// 切换到 ring 3 执行 `entry`，直到用户程序调用 `exit` 后返回其退出码
+///
+/// 不属于任何进程的程序独占 CPU：`yield` 立即继续执行，`sleep` 在内核中忙等。
+///
+/// # Safety
+/// `entry` 和 `user_stack_top` 必须位于已映射且带有 `USER_ACCESSIBLE` 标志的页面中。
+pub unsafe fn enter_user_mode(entry: VirtAddr, user_stack_top: VirtAddr) -> u64 {
+    use crate::gdt;
+    use crate::task::timer;
+
+    let mut context = UserContext::new(entry, user_stack_top);
+    loop {
+        match resume(&mut context, gdt::default_privilege_stack()) {
+            UserExit::Exited(exit_code) => return exit_code,
+            UserExit::Yielded => {}
+            UserExit::Sleep(ticks) => {
+                let wake_tick = timer::current_tick().saturating_add(ticks);
+                while timer::current_tick() < wake_tick {
+                    x86_64::instructions::hlt();
+                }
+            }
+        }
+    }
+}
+
+/// 暂停当前用户程序，回到 `resume` 的调用者
+///
+/// `return_value` 是用户程序被重新调度后看到的系统调用返回值。
+///
+/// # Safety
+/// 只能在由 `resume` 进入的用户程序所触发的系统调用中调用。
+pub(crate) unsafe fn suspend(frame: &SyscallFrame, return_value: u64, exit: UserExit) -> ! {
+    let context = *addr_of_mut!(ACTIVE_CONTEXT);
+    if !context.is_null() {
+        *context = UserContext::after_syscall(frame, return_value);
+    }
+    match exit {
+        UserExit::Exited(code) => user_return(EXIT_KIND_EXITED, code),
+        UserExit::Yielded => user_return(EXIT_KIND_YIELDED, 0),
+        UserExit::Sleep(ticks) => user_return(EXIT_KIND_SLEEP, ticks),
+    }
+}
+
+/// 检查用户态传入的缓冲区是否完全位于用户地址空间内，且每一页都已映射并允许用户访问
+pub(crate) fn user_slice(addr: u64, len: u64) -> Option<&'static [u8]> {
+    let end = addr.checked_add(len)?;
+    if addr < USER_SPACE_START || end > USER_SPACE_END {
+        return None;
+    }
+    if len > 0 {
+        let first = Page::<Size4KiB>::containing_address(VirtAddr::new(addr));
+        let last = Page::<Size4KiB>::containing_address(VirtAddr::new(end - 1));
+        if !Page::range_inclusive(first, last).all(user_page_accessible) {
+            return None;
+        }
+    }
+    Some(unsafe { core::slice::from_raw_parts(addr as *const u8, len as usize) })
+}
+
+// 在当前页表中逐级检查页面：每一级表项都必须存在并允许用户访问，否则访问会触发页错误
+fn user_page_accessible(page: Page) -> bool {
+    use x86_64::registers::control::Cr3;
+    use x86_64::structures::paging::PageTable;
+
+    let required = PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE;
+    let address = page.start_address();
+    let indexes = [
+        address.p4_index(),
+        address.p3_index(),
+        address.p2_index(),
+        address.p1_index(),
+    ];
+    let mut frame = Cr3::read().0;
+    for index in indexes {
+        let table: &PageTable = unsafe { &*crate::memory::frame_to_virt(frame).as_ptr() };
+        let entry = &table[index];
+        if !entry.flags().contains(required) {
+            return false;
+        }
+        // 大页表项直接映射了整个区域
+        if entry.flags().contains(PageTableFlags::HUGE_PAGE) {
+            return true;
+        }
+        frame = match entry.frame() {
+            Ok(frame) => frame,
+            Err(_) => return false,
+        };
+    }
+    true
+}
+
+/// 为用户程序映射一个页面并写入初始内容
+///
+/// 内容通过物理内存映射写入，因此即使页面对用户只读也能正常初始化。
+pub fn map_user_page(
+    mapper: &mut OffsetPageTable,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+    page: Page,
+    flags: PageTableFlags,
+    contents: &[u8],
+) -> Result<(), &'static str> {
+    if !(USER_SPACE_START..USER_SPACE_END).contains(&page.start_address().as_u64()) {
+        return Err("page is outside of the user address space");
+    }
+    if contents.len() > page.size() as usize {
+        return Err("contents do not fit into a single page");
+    }
+
+    let frame = frame_allocator
+        .allocate_frame()
+        .ok_or("no free frame for user page")?;
+    let frame_ptr: *mut u8 = (mapper.phys_offset() + frame.start_address().as_u64()).as_mut_ptr();
+    unsafe {
+        core::ptr::write_bytes(frame_ptr, 0, page.size() as usize);
+        core::ptr::copy_nonoverlapping(contents.as_ptr(), frame_ptr, contents.len());
+        mapper
+            .map_to(
+                page,
+                frame,
+                flags | PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE,
+                frame_allocator,
+            )
+            .map_err(|_| "failed to map user page")?
+            .flush();
+    }
+    Ok(())
+}
+
+/// 记录用户程序写到标准输出的数据，便于测试和诊断读取
+pub(crate) fn capture_output(bytes: &[u8]) {
+    let mut output = CAPTURED_OUTPUT.lock();
+    let room = CAPTURED_OUTPUT_LIMIT.saturating_sub(output.len());
+    output.extend_from_slice(&bytes[..bytes.len().min(room)]);
+}
+
+/// 取出并清空已记录的用户输出
+pub fn take_captured_output() -> Vec<u8> {
+    core::mem::take(&mut *CAPTURED_OUTPUT.lock())
+}
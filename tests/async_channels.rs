@@ -0,0 +1,268 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(os_by_rust::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::vec;
+use alloc::vec::Vec;
+use bootloader::{entry_point, BootInfo};
+use core::future::Future;
+use core::panic::PanicInfo;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+use futures_util::sink::SinkExt;
+use futures_util::stream::StreamExt;
+use os_by_rust::task::executor::{Executor, Spawner};
+use os_by_rust::task::sync::{broadcast, mpsc, oneshot};
+use os_by_rust::task::Task;
+use x86_64::VirtAddr;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use os_by_rust::allocator;
+    use os_by_rust::memory::{self, BootInfoFrameAllocator};
+
+    os_by_rust::init();
+    let physical_memory_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(physical_memory_offset) };
+    let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map) };
+    allocator::init_heap(&mut mapper, &mut frame_allocator).expect("heap initialization failed");
+
+    let mut executor = Executor::new();
+    let spawner = executor.spawner();
+    executor
+        .try_spawn(Task::new(async_channels_task(spawner)))
+        .expect("failed to spawn async channels task");
+    executor.run();
+}
+
+async fn async_channels_task(spawner: Spawner) {
+    bounded_mpsc_applies_backpressure(&spawner).await;
+    mpsc_reports_closed_ends(&spawner).await;
+    unbounded_mpsc_works_as_stream_and_sink(&spawner).await;
+    oneshot_delivers_or_reports_drop(&spawner).await;
+    broadcast_fans_out_and_reports_lag(&spawner).await;
+    os_by_rust::exit_qemu(os_by_rust::QemuExitCode::Success);
+}
+
+// 队列满时发送端等待，空位按发送端排队的顺序分配
+async fn bounded_mpsc_applies_backpressure(spawner: &Spawner) {
+    let (sender, mut receiver) = mpsc::channel(2);
+    sender.try_send(0).expect("queue has room");
+    sender.try_send(1).expect("queue has room");
+    assert_eq!(sender.try_send(99), Err(mpsc::TrySendError::Full(99)));
+
+    let handles: Vec<_> = (2..5)
+        .map(|value| {
+            let mut sender = sender.clone();
+            spawner.spawn(async move {
+                sender.send(value).await.expect("receiver closed");
+            })
+        })
+        .collect();
+    yield_now(2).await;
+    assert_eq!(receiver.len(), 2);
+    // 有发送端在等待空位时，`try_send` 不插队
+    assert_eq!(receiver.recv().await, Some(0));
+    assert_eq!(sender.try_send(99), Err(mpsc::TrySendError::Full(99)));
+
+    let mut received = Vec::new();
+    for _ in 1..5 {
+        received.push(receiver.recv().await.expect("senders alive"));
+    }
+    for handle in handles {
+        handle.await.expect("sender task cancelled");
+    }
+    assert_eq!(received, vec![1, 2, 3, 4]);
+    assert_eq!(receiver.try_recv(), Err(mpsc::TryRecvError::Empty));
+}
+
+async fn mpsc_reports_closed_ends(spawner: &Spawner) {
+    // 接收端关闭时，正在等待空位的发送端得到原来的值
+    let (mut sender, mut receiver) = mpsc::channel(1);
+    sender.send(1).await.expect("queue has room");
+    let waiting = {
+        let mut sender = sender.clone();
+        spawner.spawn(async move { sender.send(2).await })
+    };
+    yield_now(2).await;
+    receiver.close();
+    assert_eq!(
+        waiting.await.expect("sender task cancelled"),
+        Err(mpsc::SendError(2))
+    );
+    assert!(sender.is_closed());
+    assert_eq!(sender.try_send(3), Err(mpsc::TrySendError::Closed(3)));
+    // 关闭前已经入队的消息仍然可以取出
+    assert_eq!(receiver.recv().await, Some(1));
+
+    // 所有发送端丢弃后接收端取空队列并结束
+    let (sender, mut receiver) = mpsc::channel(4);
+    let producer = spawner.spawn(async move {
+        let mut sender = sender;
+        for value in 0..3 {
+            sender.send(value).await.expect("receiver closed");
+        }
+    });
+    let mut received = Vec::new();
+    while let Some(value) = receiver.recv().await {
+        received.push(value);
+    }
+    producer.await.expect("producer task cancelled");
+    assert_eq!(received, vec![0, 1, 2]);
+    assert_eq!(receiver.try_recv(), Err(mpsc::TryRecvError::Disconnected));
+}
+
+async fn unbounded_mpsc_works_as_stream_and_sink(spawner: &Spawner) {
+    let (sender, receiver) = mpsc::unbounded();
+    let producers: Vec<_> = (0..2u32)
+        .map(|producer| {
+            let sender = sender.clone();
+            spawner.spawn(async move {
+                for value in 0..50 {
+                    sender
+                        .send(producer * 100 + value)
+                        .expect("receiver closed");
+                    yield_now(1).await;
+                }
+            })
+        })
+        .collect();
+    drop(sender);
+
+    let mut received: Vec<u32> = receiver.collect().await;
+    for handle in producers {
+        handle.await.expect("producer task cancelled");
+    }
+    assert_eq!(received.len(), 100);
+    // 同一个发送端的消息保持顺序
+    let first: Vec<_> = received
+        .iter()
+        .copied()
+        .filter(|value| *value < 100)
+        .collect();
+    assert_eq!(first, (0..50).collect::<Vec<_>>());
+    received.sort_unstable();
+    received.dedup();
+    assert_eq!(received.len(), 100);
+
+    // 有界发送端作为 `Sink` 时同样受容量约束
+    let (mut sender, mut receiver) = mpsc::channel(1);
+    let consumer = spawner.spawn(async move {
+        let mut total = 0;
+        while let Some(value) = receiver.next().await {
+            total += value;
+        }
+        total
+    });
+    for value in 1..=10 {
+        SinkExt::send(&mut sender, value)
+            .await
+            .expect("receiver closed");
+    }
+    drop(sender);
+    assert_eq!(consumer.await.expect("consumer task cancelled"), 55);
+}
+
+async fn oneshot_delivers_or_reports_drop(spawner: &Spawner) {
+    let (sender, receiver) = oneshot::channel();
+    let waiter = spawner.spawn(receiver);
+    yield_now(1).await;
+    assert!(!sender.is_closed());
+    sender.send(7).expect("receiver alive");
+    assert_eq!(waiter.await.expect("waiter cancelled"), Ok(7));
+
+    let (sender, receiver) = oneshot::channel::<u32>();
+    let waiter = spawner.spawn(receiver);
+    yield_now(1).await;
+    drop(sender);
+    assert_eq!(
+        waiter.await.expect("waiter cancelled"),
+        Err(oneshot::RecvError)
+    );
+
+    let (sender, mut receiver) = oneshot::channel();
+    assert_eq!(receiver.try_recv(), Err(oneshot::TryRecvError::Empty));
+    receiver.close();
+    assert!(sender.is_closed());
+    assert_eq!(sender.send(1), Err(1));
+}
+
+async fn broadcast_fans_out_and_reports_lag(spawner: &Spawner) {
+    let (sender, first) = broadcast::channel(4);
+    let second = sender.subscribe();
+    assert_eq!(sender.receiver_count(), 2);
+
+    let listeners: Vec<_> = [first, second]
+        .into_iter()
+        .map(|receiver| {
+            spawner.spawn(async move {
+                receiver
+                    .map(|message| message.expect("listener lagged"))
+                    .collect::<Vec<u32>>()
+                    .await
+            })
+        })
+        .collect();
+    for value in 0..3 {
+        assert_eq!(sender.send(value), Ok(2));
+        yield_now(1).await;
+    }
+
+    // 不接收的订阅者被覆盖后得到错过的条数，再从最旧的消息继续
+    let mut slow = sender.subscribe();
+    for value in 3..9 {
+        sender.send(value).expect("receivers alive");
+        yield_now(1).await;
+    }
+    assert_eq!(slow.try_recv(), Err(broadcast::TryRecvError::Lagged(2)));
+    assert_eq!(slow.recv().await, Ok(5));
+    drop(slow);
+
+    drop(sender);
+    for listener in listeners {
+        assert_eq!(
+            listener.await.expect("listener cancelled"),
+            (0..9).collect::<Vec<_>>()
+        );
+    }
+
+    let (sender, receiver) = broadcast::channel(1);
+    drop(receiver);
+    assert_eq!(sender.send(1), Err(broadcast::SendError(1)));
+}
+
+// 让出执行权 `times` 次
+async fn yield_now(times: usize) {
+    YieldMultiple {
+        remaining_yields: times,
+    }
+    .await
+}
+
+struct YieldMultiple {
+    remaining_yields: usize,
+}
+
+impl Future for YieldMultiple {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<Self::Output> {
+        if self.remaining_yields == 0 {
+            Poll::Ready(())
+        } else {
+            self.remaining_yields -= 1;
+            context.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    os_by_rust::test_panic_handler(info);
+}
@@ -0,0 +1,272 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(os_by_rust::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use bootloader::{entry_point, BootInfo};
+use core::future::Future;
+use core::panic::PanicInfo;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use core::task::{Context, Poll};
+use os_by_rust::task::executor::{Executor, Spawner};
+use os_by_rust::task::sync::{Barrier, Mutex, Notify, RwLock, Semaphore};
+use os_by_rust::task::Task;
+use x86_64::VirtAddr;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use os_by_rust::allocator;
+    use os_by_rust::memory::{self, BootInfoFrameAllocator};
+
+    os_by_rust::init();
+    let physical_memory_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(physical_memory_offset) };
+    let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map) };
+    allocator::init_heap(&mut mapper, &mut frame_allocator).expect("heap initialization failed");
+
+    let mut executor = Executor::new();
+    let spawner = executor.spawner();
+    executor
+        .try_spawn(Task::new(async_sync_task(spawner)))
+        .expect("failed to spawn async sync task");
+    executor.run();
+}
+
+async fn async_sync_task(spawner: Spawner) {
+    mutex_is_held_across_await(&spawner).await;
+    rwlock_shares_readers_and_queues_writer(&spawner).await;
+    semaphore_limits_concurrency(&spawner).await;
+    notify_wakes_waiters(&spawner).await;
+    barrier_releases_full_rounds(&spawner).await;
+    os_by_rust::exit_qemu(os_by_rust::QemuExitCode::Success);
+}
+
+// 持锁期间让出执行权，其他任务按到达顺序排队
+async fn mutex_is_held_across_await(spawner: &Spawner) {
+    let mutex = Arc::new(Mutex::new(Vec::new()));
+    let handles: Vec<_> = (0..3)
+        .map(|index| {
+            let mutex = mutex.clone();
+            spawner.spawn(async move {
+                let mut order = mutex.lock().await;
+                order.push(index);
+                yield_now(4).await;
+                assert_eq!(order.last(), Some(&index));
+            })
+        })
+        .collect();
+    yield_now(1).await;
+    assert!(mutex.is_locked());
+    assert!(mutex.try_lock().is_none());
+
+    for handle in handles {
+        handle.await.expect("mutex task cancelled");
+    }
+    assert!(!mutex.is_locked());
+    assert_eq!(*mutex.lock().await, vec![0, 1, 2]);
+}
+
+// 读者可以同时持锁；写者等待期间，后来的读者排在写者之后
+async fn rwlock_shares_readers_and_queues_writer(spawner: &Spawner) {
+    let lock = Arc::new(RwLock::new(0));
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let readers = Arc::new(AtomicUsize::new(0));
+    let max_readers = Arc::new(AtomicUsize::new(0));
+
+    let reader = |name: &'static str| {
+        let (lock, log, readers, max_readers) = (
+            lock.clone(),
+            log.clone(),
+            readers.clone(),
+            max_readers.clone(),
+        );
+        spawner.spawn(async move {
+            let value = lock.read().await;
+            let current = readers.fetch_add(1, Ordering::Relaxed) + 1;
+            max_readers.fetch_max(current, Ordering::Relaxed);
+            log.lock().await.push((name, *value));
+            yield_now(3).await;
+            readers.fetch_sub(1, Ordering::Relaxed);
+        })
+    };
+    let first = reader("first");
+    let second = reader("second");
+    yield_now(1).await;
+
+    let writer = {
+        let (lock, log) = (lock.clone(), log.clone());
+        spawner.spawn(async move {
+            let mut value = lock.write().await;
+            *value += 1;
+            log.lock().await.push(("writer", *value));
+        })
+    };
+    yield_now(1).await;
+    let late = reader("late");
+
+    for handle in [first, second, writer, late] {
+        handle.await.expect("rwlock task cancelled");
+    }
+    assert_eq!(max_readers.load(Ordering::Relaxed), 2);
+    assert_eq!(
+        *log.lock().await,
+        vec![("first", 0), ("second", 0), ("writer", 1), ("late", 1)]
+    );
+}
+
+async fn semaphore_limits_concurrency(spawner: &Spawner) {
+    let semaphore = Arc::new(Semaphore::new(2));
+    let inside = Arc::new(AtomicUsize::new(0));
+    let max_inside = Arc::new(AtomicUsize::new(0));
+    let handles: Vec<_> = (0..5)
+        .map(|_| {
+            let (semaphore, inside, max_inside) =
+                (semaphore.clone(), inside.clone(), max_inside.clone());
+            spawner.spawn(async move {
+                let _permit = semaphore.acquire().await;
+                let current = inside.fetch_add(1, Ordering::Relaxed) + 1;
+                max_inside.fetch_max(current, Ordering::Relaxed);
+                yield_now(2).await;
+                inside.fetch_sub(1, Ordering::Relaxed);
+            })
+        })
+        .collect();
+    yield_now(1).await;
+    // 有人排队时不允许插队
+    assert!(semaphore.try_acquire().is_none());
+
+    for handle in handles {
+        handle.await.expect("semaphore task cancelled");
+    }
+    assert_eq!(max_inside.load(Ordering::Relaxed), 2);
+    assert_eq!(semaphore.available_permits(), 2);
+
+    // 被丢弃的排队者不占用许可，也不挡住后面的等待者
+    let held = semaphore.acquire_many(2).await;
+    let mut dropped = Box::pin(semaphore.acquire_many(2));
+    assert!(poll_once(dropped.as_mut()).await.is_none());
+    drop(dropped);
+    drop(held);
+    assert_eq!(semaphore.available_permits(), 2);
+    let _one = semaphore
+        .try_acquire()
+        .expect("permit leaked by dropped waiter");
+}
+
+async fn notify_wakes_waiters(spawner: &Spawner) {
+    let notify = Arc::new(Notify::new());
+
+    // 没有等待者时保存一次通知
+    notify.notify_one();
+    notify.notified().await;
+
+    let woken = Arc::new(AtomicUsize::new(0));
+    let handles: Vec<_> = (0..3)
+        .map(|_| {
+            let (notify, woken) = (notify.clone(), woken.clone());
+            spawner.spawn(async move {
+                notify.notified().await;
+                woken.fetch_add(1, Ordering::Relaxed);
+            })
+        })
+        .collect();
+    yield_now(1).await;
+    assert_eq!(notify.waiters(), 3);
+    notify.notify_one();
+    yield_now(1).await;
+    assert_eq!(woken.load(Ordering::Relaxed), 1);
+    notify.notify_waiters();
+    for handle in handles {
+        handle.await.expect("notify task cancelled");
+    }
+    assert_eq!(woken.load(Ordering::Relaxed), 3);
+
+    // `notify_waiters` 不保存通知
+    let mut late = Box::pin(notify.notified());
+    assert!(poll_once(late.as_mut()).await.is_none());
+
+    // 收到 `notify_one` 但被丢弃的等待者把通知转交给下一个
+    let mut next = core::pin::pin!(notify.notified());
+    assert!(poll_once(next.as_mut()).await.is_none());
+    notify.notify_one();
+    drop(late);
+    assert!(poll_once(next.as_mut()).await.is_some());
+}
+
+async fn barrier_releases_full_rounds(spawner: &Spawner) {
+    const PARTIES: usize = 3;
+    let barrier = Arc::new(Barrier::new(PARTIES));
+    let leaders = Arc::new(AtomicUsize::new(0));
+    let passed = Arc::new(AtomicUsize::new(0));
+    let handles: Vec<_> = (0..PARTIES)
+        .map(|_| {
+            let (barrier, leaders, passed) = (barrier.clone(), leaders.clone(), passed.clone());
+            spawner.spawn(async move {
+                for round in 0..2 {
+                    if barrier.wait().await.is_leader() {
+                        leaders.fetch_add(1, Ordering::Relaxed);
+                    }
+                    assert!(passed.fetch_add(1, Ordering::Relaxed) >= round * PARTIES);
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.await.expect("barrier task cancelled");
+    }
+    assert_eq!(leaders.load(Ordering::Relaxed), 2);
+    assert_eq!(passed.load(Ordering::Relaxed), 2 * PARTIES);
+}
+
+// 只轮询一次，完成时返回输出
+async fn poll_once<F: Future>(future: Pin<&mut F>) -> Option<F::Output> {
+    let mut future = Some(future);
+    core::future::poll_fn(|context| {
+        let output = match future.take().expect("polled twice").poll(context) {
+            Poll::Ready(output) => Some(output),
+            Poll::Pending => None,
+        };
+        Poll::Ready(output)
+    })
+    .await
+}
+
+// 让出执行权 `times` 次
+async fn yield_now(times: usize) {
+    YieldMultiple {
+        remaining_yields: times,
+    }
+    .await
+}
+
+struct YieldMultiple {
+    remaining_yields: usize,
+}
+
+impl Future for YieldMultiple {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<Self::Output> {
+        if self.remaining_yields == 0 {
+            Poll::Ready(())
+        } else {
+            self.remaining_yields -= 1;
+            context.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    os_by_rust::test_panic_handler(info);
+}
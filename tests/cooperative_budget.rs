@@ -0,0 +1,214 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(os_by_rust::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use os_by_rust::task::accounting::read_tsc;
+use os_by_rust::task::coop::{self, POLL_BUDGET};
+use os_by_rust::task::executor::{self, trace_snapshot, Executor, ExecutorConfig, TraceKind};
+use os_by_rust::task::sync::mpsc;
+use os_by_rust::task::{yield_now, Task, TaskPriority};
+use x86_64::VirtAddr;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use os_by_rust::allocator;
+    use os_by_rust::memory::{self, BootInfoFrameAllocator};
+
+    os_by_rust::init();
+    let physical_memory_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(physical_memory_offset) };
+    let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map) };
+    allocator::init_heap(&mut mapper, &mut frame_allocator).expect("heap initialization failed");
+
+    yield_now_interleaves_tasks();
+    budget_is_per_poll();
+    exhausted_budget_yields_to_high_priority();
+    exhausted_budget_returns_to_block_on();
+    slow_poll_is_detected();
+
+    os_by_rust::exit_qemu(os_by_rust::QemuExitCode::Success);
+    os_by_rust::hlt_loop();
+}
+
+// 两个同优先级的任务每步都让出，执行顺序交替
+fn yield_now_interleaves_tasks() {
+    let mut executor = Executor::new();
+    let order = Arc::new(spin::Mutex::new(Vec::new()));
+    for name in ['a', 'b'] {
+        let order = order.clone();
+        executor.spawn(async move {
+            for _ in 0..3 {
+                order.lock().push(name);
+                yield_now().await;
+            }
+        });
+    }
+    executor.run_until_idle();
+    assert_eq!(*order.lock(), ['a', 'b', 'a', 'b', 'a', 'b']);
+}
+
+// 执行器之外不受限制；每次轮询重新得到完整的预算
+fn budget_is_per_poll() {
+    assert_eq!(coop::remaining_budget(), None);
+
+    let mut executor = Executor::new();
+    let budgets = executor.block_on(async {
+        let (sender, mut receiver) = mpsc::unbounded();
+        for value in 0..3 {
+            sender.send(value).unwrap();
+        }
+        let before = coop::remaining_budget();
+        receiver.recv().await;
+        receiver.recv().await;
+        let after = coop::remaining_budget();
+        yield_now().await;
+        (before, after, coop::remaining_budget())
+    });
+    assert_eq!(budgets.0, Some(POLL_BUDGET));
+    assert_eq!(budgets.1, Some(POLL_BUDGET - 2));
+    assert_eq!(budgets.2, Some(POLL_BUDGET));
+    assert_eq!(coop::remaining_budget(), None);
+}
+
+// 消息一直就绪的接收循环在预算用完时回到执行器，高优先级任务可以在中途运行
+fn exhausted_budget_yields_to_high_priority() {
+    let mut executor = Executor::new();
+    let total = POLL_BUDGET as usize * 4;
+    let (sender, mut receiver) = mpsc::unbounded();
+    for value in 0..total {
+        sender.send(value).unwrap();
+    }
+    drop(sender);
+
+    let received = Arc::new(AtomicUsize::new(0));
+    let consumer = {
+        let received = received.clone();
+        executor.spawn(async move {
+            while receiver.recv().await.is_some() {
+                received.fetch_add(1, Ordering::Relaxed);
+            }
+        })
+    };
+    let samples = Arc::new(spin::Mutex::new(Vec::new()));
+    {
+        let (received, samples) = (received.clone(), samples.clone());
+        executor.spawn_with_priority(
+            async move {
+                loop {
+                    let seen = received.load(Ordering::Relaxed);
+                    samples.lock().push(seen);
+                    if seen == total {
+                        break;
+                    }
+                    yield_now().await;
+                }
+            },
+            TaskPriority::High,
+        );
+    }
+
+    let forced_before = coop::forced_yields();
+    executor.run_until_idle();
+    assert_eq!(executor.block_on(consumer), Ok(()));
+
+    assert_eq!(received.load(Ordering::Relaxed), total);
+    assert!(coop::forced_yields() - forced_before >= 3);
+    assert!(executor.stats().forced_yields >= 3);
+    let samples = samples.lock();
+    assert!(samples
+        .iter()
+        .any(|&seen| seen > 0 && seen < total && seen % POLL_BUDGET as usize == 0));
+}
+
+// 永不结束、消息一直就绪的任务每次轮询用完预算后让出，`block_on` 与 `run_until` 照常返回
+fn exhausted_budget_returns_to_block_on() {
+    let mut executor = Executor::new();
+    let received = Arc::new(AtomicUsize::new(0));
+    {
+        let received = received.clone();
+        executor.spawn(async move {
+            let (sender, mut receiver) = mpsc::unbounded();
+            loop {
+                sender.send(()).unwrap();
+                receiver.recv().await;
+                received.fetch_add(1, Ordering::Relaxed);
+            }
+        });
+    }
+
+    let forced_before = coop::forced_yields();
+    let value = executor.block_on(async {
+        for _ in 0..3 {
+            yield_now().await;
+        }
+        7
+    });
+    assert_eq!(value, 7);
+    assert!(coop::forced_yields() - forced_before >= 3);
+
+    let target = coop::forced_yields() + 3;
+    executor.run_until(|| coop::forced_yields() >= target);
+    // 每次轮询恰好消耗完整的预算后回到执行器
+    assert!(received.load(Ordering::Relaxed) > 0);
+    assert_eq!(received.load(Ordering::Relaxed) % POLL_BUDGET as usize, 0);
+    assert_eq!(executor.stats().active_tasks, 1);
+}
+
+// 单次轮询超过阈值的任务计入执行器统计和任务记账
+fn slow_poll_is_detected() {
+    const SPIN_CYCLES: u64 = 1_000_000;
+
+    let mut executor = ExecutorConfig::new()
+        .slow_poll_threshold(SPIN_CYCLES / 2)
+        .build();
+    let before = executor.stats().slow_polls;
+    let spun = Arc::new(AtomicBool::new(false));
+    let task = {
+        let spun = spun.clone();
+        Task::new(async move {
+            let start = read_tsc();
+            while read_tsc() - start < SPIN_CYCLES {
+                core::hint::spin_loop();
+            }
+            spun.store(true, Ordering::Release);
+            core::future::pending::<()>().await;
+        })
+        .named("spinner")
+    };
+    executor.try_spawn(task).unwrap();
+    executor.run_until(|| spun.load(Ordering::Acquire));
+
+    assert_eq!(executor.stats().slow_polls, before + 1);
+    let stats = executor.task_stats();
+    let spinner = stats
+        .iter()
+        .find(|task| task.name == Some("spinner"))
+        .unwrap();
+    assert_eq!(spinner.slow_polls, 1);
+    assert!(spinner.max_poll_cycles >= SPIN_CYCLES);
+
+    // 最近一次慢轮询带有任务名与周期数，同时写入跟踪缓冲区
+    let slow_poll = executor::last_slow_poll().unwrap();
+    assert_eq!(slow_poll.task_id, spinner.id);
+    assert_eq!(slow_poll.name, Some("spinner"));
+    assert!(slow_poll.cycles >= SPIN_CYCLES);
+    let spinner_id = spinner.id.as_u64();
+    assert!(trace_snapshot()
+        .iter()
+        .any(|event| event.kind == TraceKind::SlowPoll && event.task_id == spinner_id));
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    os_by_rust::test_panic_handler(info);
+}
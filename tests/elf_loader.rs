@@ -0,0 +1,109 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(os_by_rust::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use os_by_rust::elf::{self, ElfError};
+use os_by_rust::memory::BootInfoFrameAllocator;
+use os_by_rust::usermode;
+use spin::Mutex;
+use x86_64::VirtAddr;
+
+// 由 tests/programs/hello.S 生成：逐行打印 argv[1] 与 envp[0]，以 argc 作为退出码
+static HELLO_ELF: &[u8] = include_bytes!("programs/hello.elf");
+
+static FRAME_ALLOCATOR: Mutex<Option<BootInfoFrameAllocator>> = Mutex::new(None);
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use os_by_rust::allocator;
+    use os_by_rust::memory;
+
+    os_by_rust::init();
+    let physical_memory_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(physical_memory_offset) };
+    let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map) };
+    allocator::init_heap(&mut mapper, &mut frame_allocator).expect("heap initialization failed");
+    *FRAME_ALLOCATOR.lock() = Some(frame_allocator);
+
+    test_main();
+    os_by_rust::exit_qemu(os_by_rust::QemuExitCode::Success);
+    os_by_rust::hlt_loop();
+}
+
+#[test_case]
+fn rejects_non_elf_images() {
+    assert_eq!(elf::parse_header(&[0; 16]).err(), Some(ElfError::Truncated));
+    assert_eq!(elf::parse_header(&[0; 64]).err(), Some(ElfError::BadMagic));
+}
+
+#[test_case]
+fn parses_embedded_program_headers() {
+    let header = elf::parse_header(HELLO_ELF).expect("embedded program should parse");
+    let load_segments = (0..header.program_header_count)
+        .map(|index| elf::program_header(HELLO_ELF, &header, index).unwrap())
+        .filter(|program_header| program_header.segment_type == 1)
+        .count();
+    assert!(load_segments >= 2);
+}
+
+#[test_case]
+fn runs_embedded_program_with_arguments() {
+    let mut frame_allocator = FRAME_ALLOCATOR.lock();
+    let frame_allocator = frame_allocator.as_mut().unwrap();
+
+    usermode::take_captured_output();
+    let exit_code = elf::run(
+        HELLO_ELF,
+        &["hello", "from-argv"],
+        &["PATH=/bin"],
+        frame_allocator,
+    )
+    .expect("failed to run embedded program");
+
+    assert_eq!(exit_code, 2);
+    assert_eq!(usermode::take_captured_output(), b"from-argv\nPATH=/bin\n");
+}
+
+#[test_case]
+fn failed_load_returns_all_frames() {
+    let mut frame_allocator = FRAME_ALLOCATOR.lock();
+    let frame_allocator = frame_allocator.as_mut().unwrap();
+
+    // 第一个 `PT_LOAD` 段映射之后，第二个段的文件偏移越界（程序头 56 字节，`p_offset` 位于第 8 字节）
+    let mut image = HELLO_ELF.to_vec();
+    let header = elf::parse_header(&image).unwrap();
+    let second_load = (0..header.program_header_count)
+        .filter(|&index| {
+            elf::program_header(&image, &header, index)
+                .unwrap()
+                .segment_type
+                == 1
+        })
+        .nth(1)
+        .expect("embedded program should have two load segments");
+    let offset_field = header.program_header_offset as usize + usize::from(second_load) * 56 + 8;
+    image[offset_field..offset_field + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+
+    let before = frame_allocator.allocated_frames();
+    let result = elf::load(&image, &["broken"], &[], frame_allocator);
+    assert_eq!(result.err(), Some(ElfError::Truncated));
+    assert_eq!(frame_allocator.allocated_frames(), before);
+
+    // 所有段都已映射，参数放不进用户栈
+    let huge_argument = "x".repeat(elf::USER_STACK_PAGES as usize * 4096);
+    let result = elf::load(HELLO_ELF, &[&huge_argument], &[], frame_allocator);
+    assert_eq!(result.err(), Some(ElfError::ArgumentsTooLarge));
+    assert_eq!(frame_allocator.allocated_frames(), before);
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    os_by_rust::test_panic_handler(info);
+}
@@ -0,0 +1,136 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(os_by_rust::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicU64, Ordering};
+use os_by_rust::task::executor::{ExecutorConfig, QueueMode, SpawnError, Spawner, MAX_TASKS};
+use os_by_rust::task::{sleep_ticks, Task, TaskPriority};
+use x86_64::VirtAddr;
+
+// 超过默认上限的任务数，只有可增长队列能容纳
+const GROWABLE_TASKS: u64 = MAX_TASKS as u64 + 20;
+const LOW_LIMIT: usize = 2;
+const LOW_CHILDREN: u64 = 5;
+
+static GROWABLE_DONE: AtomicU64 = AtomicU64::new(0);
+static LOW_RUNNING: AtomicU64 = AtomicU64::new(0);
+static LOW_MAX_RUNNING: AtomicU64 = AtomicU64::new(0);
+static LOW_DONE: AtomicU64 = AtomicU64::new(0);
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use os_by_rust::allocator;
+    use os_by_rust::memory::{self, BootInfoFrameAllocator};
+
+    os_by_rust::init();
+    let physical_memory_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(physical_memory_offset) };
+    let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map) };
+    allocator::init_heap(&mut mapper, &mut frame_allocator).expect("heap initialization failed");
+
+    // 定长队列的容量即任务上限
+    let mut bounded = ExecutorConfig::new().queue_capacity(4).build();
+    for _ in 0..4 {
+        bounded
+            .try_spawn(Task::new(async {}))
+            .expect("bounded executor rejected a task below its capacity");
+    }
+    assert_eq!(
+        bounded.try_spawn(Task::new(async {})),
+        Err(SpawnError::QueueFull)
+    );
+    drop(bounded);
+
+    // 设置顺序不影响结果
+    let limited = ExecutorConfig::new().max_tasks(3).growable();
+    assert_eq!(limited, ExecutorConfig::new().growable().max_tasks(3));
+    assert_eq!(limited.mode(), QueueMode::Growable);
+    assert_eq!(limited.task_limit(), 3);
+    assert_eq!(ExecutorConfig::new().growable().task_limit(), usize::MAX);
+    assert_eq!(ExecutorConfig::new().task_limit(), MAX_TASKS);
+    let mut limited = limited.build();
+    for _ in 0..3 {
+        limited.try_spawn(Task::new(async {})).unwrap();
+    }
+    assert_eq!(
+        limited.try_spawn(Task::new(async {})),
+        Err(SpawnError::QueueFull)
+    );
+    drop(limited);
+
+    let mut executor = ExecutorConfig::new()
+        .growable()
+        .priority_limit(TaskPriority::Low, LOW_LIMIT)
+        .build();
+    for _ in 0..GROWABLE_TASKS {
+        executor
+            .try_spawn(Task::new(async {
+                GROWABLE_DONE.fetch_add(1, Ordering::Relaxed);
+            }))
+            .expect("growable executor rejected a task");
+    }
+    let spawner = executor.spawner();
+    executor.spawn(executor_config_task(spawner));
+    executor.run();
+}
+
+async fn executor_config_task(spawner: Spawner) {
+    sleep_ticks(1).await;
+    assert_eq!(GROWABLE_DONE.load(Ordering::Relaxed), GROWABLE_TASKS);
+
+    // 低优先级名额用完后 `try_spawn` 失败，而派生者可以异步等待名额
+    let mut children = Vec::new();
+    for _ in 0..LOW_LIMIT {
+        children.push(
+            spawner
+                .try_spawn_with_priority(low_priority_child(), TaskPriority::Low)
+                .expect("low priority slot should be free"),
+        );
+    }
+    assert_eq!(
+        spawner
+            .try_spawn_with_priority(async {}, TaskPriority::Low)
+            .err(),
+        Some(SpawnError::QueueFull)
+    );
+    // 其他优先级不受影响
+    spawner
+        .try_spawn(async {})
+        .expect("normal priority spawn should not be limited")
+        .detach();
+    for _ in LOW_LIMIT as u64..LOW_CHILDREN {
+        children.push(
+            spawner
+                .spawn_when_available_with_priority(low_priority_child(), TaskPriority::Low)
+                .await,
+        );
+    }
+    for child in children {
+        child.await.expect("low priority child cancelled");
+    }
+    assert_eq!(LOW_DONE.load(Ordering::Relaxed), LOW_CHILDREN);
+    assert_eq!(LOW_MAX_RUNNING.load(Ordering::Relaxed), LOW_LIMIT as u64);
+
+    os_by_rust::exit_qemu(os_by_rust::QemuExitCode::Success);
+}
+
+async fn low_priority_child() {
+    let running = LOW_RUNNING.fetch_add(1, Ordering::Relaxed) + 1;
+    LOW_MAX_RUNNING.fetch_max(running, Ordering::Relaxed);
+    sleep_ticks(1).await;
+    LOW_RUNNING.fetch_sub(1, Ordering::Relaxed);
+    LOW_DONE.fetch_add(1, Ordering::Relaxed);
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    os_by_rust::test_panic_handler(info);
+}
@@ -0,0 +1,196 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(os_by_rust::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use bootloader::{entry_point, BootInfo};
+use core::future::Future;
+use core::panic::PanicInfo;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use core::task::{Context, Poll};
+use os_by_rust::task::executor::Executor;
+use os_by_rust::task::{sleep_ticks, timer, TaskPriority};
+use x86_64::VirtAddr;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use os_by_rust::allocator;
+    use os_by_rust::memory::{self, BootInfoFrameAllocator};
+
+    os_by_rust::init();
+    let physical_memory_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(physical_memory_offset) };
+    let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map) };
+    allocator::init_heap(&mut mapper, &mut frame_allocator).expect("heap initialization failed");
+
+    let mut executor = Executor::new();
+    block_on_borrows_locals(&mut executor);
+    block_on_waits_for_timer(&mut executor);
+    run_until_stops_on_predicate(&mut executor);
+    run_until_idle_drains_tasks(&mut executor);
+    runs_beside_busy_task();
+
+    // 执行器已经交还控制权，直接在 `main` 中退出
+    os_by_rust::exit_qemu(os_by_rust::QemuExitCode::Success);
+    os_by_rust::hlt_loop();
+}
+
+// `block_on` 的 future 可以借用栈上的数据，同时其他任务照常调度
+fn block_on_borrows_locals(executor: &mut Executor) {
+    let progress = Arc::new(AtomicUsize::new(0));
+    let background = {
+        let progress = progress.clone();
+        executor.spawn(async move {
+            for _ in 0..8 {
+                progress.fetch_add(1, Ordering::Relaxed);
+                YieldMultiple::new(1).await;
+            }
+            progress.load(Ordering::Relaxed)
+        })
+    };
+
+    let mut values = Vec::new();
+    let sum = executor.block_on(async {
+        for value in 1..=4 {
+            values.push(value);
+            YieldMultiple::new(2).await;
+        }
+        values.iter().sum::<u32>()
+    });
+    assert_eq!(sum, 10);
+    assert_eq!(values.len(), 4);
+    assert!(progress.load(Ordering::Relaxed) > 0);
+
+    // 等待任务的 `JoinHandle` 也可以交给 `block_on`
+    assert_eq!(executor.block_on(background), Ok(8));
+}
+
+// 没有就绪任务时执行器停机，定时器中断唤醒 `block_on` 的 future
+fn block_on_waits_for_timer(executor: &mut Executor) {
+    let start = timer::current_tick();
+    let woke_at = executor.block_on(async {
+        sleep_ticks(3).await;
+        timer::current_tick()
+    });
+    assert!(woke_at >= start + 3);
+}
+
+// 条件满足后返回，未结束的任务留在执行器中
+fn run_until_stops_on_predicate(executor: &mut Executor) {
+    let counter = Arc::new(AtomicUsize::new(0));
+    for _ in 0..3 {
+        let counter = counter.clone();
+        executor.spawn_with_priority(
+            async move {
+                loop {
+                    counter.fetch_add(1, Ordering::Relaxed);
+                    sleep_ticks(1).await;
+                }
+            },
+            TaskPriority::Low,
+        );
+    }
+    executor.run_until(|| counter.load(Ordering::Relaxed) >= 9);
+    assert!(counter.load(Ordering::Relaxed) >= 9);
+    assert_eq!(executor.stats().active_tasks, 3);
+}
+
+fn run_until_idle_drains_tasks(executor: &mut Executor) {
+    let mut fresh = Executor::new();
+    let finished = Arc::new(AtomicUsize::new(0));
+    let spawner = fresh.spawner();
+    for index in 0..4 {
+        let (finished, spawner) = (finished.clone(), spawner.clone());
+        fresh.spawn(async move {
+            sleep_ticks(index).await;
+            // 运行中派生的任务也要等它结束
+            let finished_inner = finished.clone();
+            spawner.spawn(async move {
+                finished_inner.fetch_add(1, Ordering::Relaxed);
+            });
+            finished.fetch_add(1, Ordering::Relaxed);
+        });
+    }
+    fresh.run_until_idle();
+    assert_eq!(finished.load(Ordering::Relaxed), 8);
+    assert_eq!(fresh.stats().active_tasks, 0);
+
+    // 之前留下的永不结束的任务仍在原执行器中，可以继续运行
+    let before = timer::current_tick();
+    executor.run_until(|| timer::current_tick() >= before + 2);
+    assert_eq!(executor.stats().active_tasks, 3);
+}
+
+// 一直让出的任务每轮只被轮询一次，`block_on` 的 future、`run_until` 的条件与注入的任务照常运行
+fn runs_beside_busy_task() {
+    let mut executor = Executor::new();
+    let spins = Arc::new(AtomicUsize::new(0));
+    {
+        let spins = spins.clone();
+        executor.spawn(async move {
+            loop {
+                spins.fetch_add(1, Ordering::Relaxed);
+                YieldMultiple::new(1).await;
+            }
+        });
+    }
+
+    let value = executor.block_on(async {
+        YieldMultiple::new(3).await;
+        7
+    });
+    assert_eq!(value, 7);
+
+    // 每轮调度之后检查一次条件，期间忙任务恰好被轮询一次
+    let target = spins.load(Ordering::Relaxed) + 5;
+    executor.run_until(|| spins.load(Ordering::Relaxed) >= target);
+    assert_eq!(spins.load(Ordering::Relaxed), target);
+
+    let injected = Arc::new(AtomicBool::new(false));
+    {
+        let injected = injected.clone();
+        executor.spawner().spawn(async move {
+            injected.store(true, Ordering::Relaxed);
+        });
+    }
+    executor.run_until(|| injected.load(Ordering::Relaxed));
+    assert_eq!(executor.stats().active_tasks, 1);
+}
+
+struct YieldMultiple {
+    remaining_yields: usize,
+}
+
+impl YieldMultiple {
+    fn new(yield_count: usize) -> Self {
+        Self {
+            remaining_yields: yield_count,
+        }
+    }
+}
+
+impl Future for YieldMultiple {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<Self::Output> {
+        if self.remaining_yields == 0 {
+            Poll::Ready(())
+        } else {
+            self.remaining_yields -= 1;
+            context.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    os_by_rust::test_panic_handler(info);
+}
@@ -0,0 +1,60 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(os_by_rust::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::string::String;
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use os_by_rust::task::executor::Executor;
+use os_by_rust::task::{sleep_ticks, JoinError, Task, TaskPriority};
+use x86_64::VirtAddr;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use os_by_rust::allocator;
+    use os_by_rust::memory::{self, BootInfoFrameAllocator};
+
+    os_by_rust::init();
+    let physical_memory_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(physical_memory_offset) };
+    let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map) };
+    allocator::init_heap(&mut mapper, &mut frame_allocator).expect("heap initialization failed");
+
+    let mut executor = Executor::new();
+    let value = executor.spawn(async {
+        sleep_ticks(1).await;
+        6 * 7
+    });
+    let text = executor.spawn_with_priority(async { String::from("joined") }, TaskPriority::High);
+    // 分离的任务照常运行，输出被丢弃
+    executor.spawn(async { String::from("detached") }).detach();
+
+    // 任务被丢弃而没有完成时，句柄得到取消错误
+    let mut abandoned = Executor::new();
+    let cancelled = abandoned.spawn(async { 1u8 });
+    drop(abandoned);
+    assert!(cancelled.is_finished());
+
+    let (task, never_spawned) = Task::joinable(async {});
+    drop(task);
+
+    executor.spawn(async move {
+        assert!(!value.is_finished());
+        assert_eq!(value.await, Ok(42));
+        assert_eq!(text.await.as_deref(), Ok("joined"));
+        assert_eq!(cancelled.await, Err(JoinError::Cancelled));
+        assert_eq!(never_spawned.await, Err(JoinError::Cancelled));
+        os_by_rust::exit_qemu(os_by_rust::QemuExitCode::Success);
+    });
+    executor.run();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    os_by_rust::test_panic_handler(info);
+}
@@ -0,0 +1,108 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(os_by_rust::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use os_by_rust::task::executor::Executor;
+use os_by_rust::task::{yield_now, JoinHandle, TaskPriority};
+use x86_64::VirtAddr;
+
+static HOG_POLLS: AtomicU64 = AtomicU64::new(0);
+static HOG_POLLS_AT_NORMAL_DONE: AtomicU64 = AtomicU64::new(0);
+static NORMAL_DONE: AtomicBool = AtomicBool::new(false);
+static IDLE_DONE: AtomicBool = AtomicBool::new(false);
+
+const WORKER_YIELDS: usize = 10;
+const SUPERVISOR_YIELDS: usize = 20;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use os_by_rust::allocator;
+    use os_by_rust::memory::{self, BootInfoFrameAllocator};
+
+    os_by_rust::init();
+    let physical_memory_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(physical_memory_offset) };
+    let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map) };
+    allocator::init_heap(&mut mapper, &mut frame_allocator).expect("heap initialization failed");
+
+    let mut executor = Executor::new();
+    // 永不结束、每次轮询后立即重新唤醒自己的高优先级任务，高优先级队列始终非空
+    let hog = executor.spawn_with_priority(
+        async {
+            loop {
+                HOG_POLLS.fetch_add(1, Ordering::Relaxed);
+                yield_now().await;
+            }
+        },
+        TaskPriority::High,
+    );
+    executor.spawn_with_priority(
+        async {
+            for _ in 0..WORKER_YIELDS {
+                yield_now().await;
+            }
+            HOG_POLLS_AT_NORMAL_DONE.store(HOG_POLLS.load(Ordering::Relaxed), Ordering::Relaxed);
+            NORMAL_DONE.store(true, Ordering::Relaxed);
+        },
+        TaskPriority::Normal,
+    );
+    executor.spawn_with_priority(
+        async {
+            for _ in 0..WORKER_YIELDS {
+                yield_now().await;
+            }
+            IDLE_DONE.store(true, Ordering::Relaxed);
+        },
+        TaskPriority::Idle,
+    );
+    executor.spawn_with_priority(supervisor(hog), TaskPriority::Low);
+    executor.run();
+}
+
+async fn supervisor(hog: JoinHandle<()>) {
+    // 老化保证低优先级任务在持续的高优先级负载下仍能推进
+    while !(NORMAL_DONE.load(Ordering::Relaxed) && IDLE_DONE.load(Ordering::Relaxed)) {
+        yield_now().await;
+    }
+    assert!(HOG_POLLS_AT_NORMAL_DONE.load(Ordering::Relaxed) > 2 * WORKER_YIELDS as u64);
+
+    let before = hog_polls_during_yields().await;
+    assert!(
+        before > 4 * SUPERVISOR_YIELDS as u64,
+        "high priority hog should dominate a low priority task, got {}",
+        before
+    );
+
+    // 运行时把占用者降到最低级别，此后主要由监督任务运行
+    hog.set_priority(TaskPriority::Idle);
+    assert_eq!(hog.priority(), TaskPriority::Idle);
+    let after = hog_polls_during_yields().await;
+    assert!(
+        after < SUPERVISOR_YIELDS as u64,
+        "demoted hog should yield to a low priority task, got {}",
+        after
+    );
+
+    os_by_rust::exit_qemu(os_by_rust::QemuExitCode::Success);
+}
+
+async fn hog_polls_during_yields() -> u64 {
+    let start = HOG_POLLS.load(Ordering::Relaxed);
+    for _ in 0..SUPERVISOR_YIELDS {
+        yield_now().await;
+    }
+    HOG_POLLS.load(Ordering::Relaxed) - start
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    os_by_rust::test_panic_handler(info);
+}
@@ -0,0 +1,78 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(os_by_rust::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use futures_util::future::join;
+use os_by_rust::memory;
+use os_by_rust::process::{self, Handle};
+use os_by_rust::task::executor::Executor;
+use os_by_rust::task::Task;
+use os_by_rust::usermode;
+use x86_64::VirtAddr;
+
+// 由 tests/programs/yielder.S 生成：打印三次 PID 并在每次打印后让出，以 PID 作为退出码
+static YIELDER_ELF: &[u8] = include_bytes!("programs/yielder.elf");
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use os_by_rust::allocator;
+    use os_by_rust::memory::BootInfoFrameAllocator;
+
+    os_by_rust::init();
+    let physical_memory_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(physical_memory_offset) };
+    let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map) };
+    allocator::init_heap(&mut mapper, &mut frame_allocator).expect("heap initialization failed");
+    memory::init_frame_allocator(frame_allocator);
+
+    let mut executor = Executor::new();
+    executor
+        .try_spawn(Task::new(process_smoke_task()))
+        .expect("failed to spawn process smoke task");
+    executor.run();
+}
+
+async fn process_smoke_task() {
+    let allocated_before = memory::with_frame_allocator(|allocator| allocator.allocated_frames());
+
+    let parent = process::spawn(YIELDER_ELF, &["yielder"], &[], None).expect("spawn parent");
+    let child = process::spawn(YIELDER_ELF, &["yielder"], &[], Some(parent)).expect("spawn child");
+    assert_eq!((parent.as_u64(), child.as_u64()), (1, 2));
+
+    let parent_process = process::get(parent).unwrap();
+    let child_process = process::get(child).unwrap();
+    assert_eq!(parent_process.children(), [child]);
+    assert_eq!(child_process.parent(), Some(parent));
+    assert_eq!(child_process.handles().get(1), Some(Handle::Console));
+    assert_eq!(child_process.handles().get(3), None);
+    drop((parent_process, child_process));
+
+    usermode::take_captured_output();
+    let (parent_exit, child_exit) = join(process::run(parent), process::run(child)).await;
+    assert_eq!(parent_exit, Some(1));
+    assert_eq!(child_exit, Some(2));
+    // 两个进程轮流运行，每次 yield 都会切换到另一个进程
+    assert_eq!(usermode::take_captured_output(), b"121212");
+
+    // 两个进程都没有父进程等待回收，退出后立即从进程表中移除，帧全部归还
+    assert!(process::get(parent).is_none());
+    assert!(process::get(child).is_none());
+    assert_eq!(
+        memory::with_frame_allocator(|allocator| allocator.allocated_frames()),
+        allocated_before
+    );
+
+    os_by_rust::exit_qemu(os_by_rust::QemuExitCode::Success);
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    os_by_rust::test_panic_handler(info);
+}
@@ -0,0 +1,129 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(os_by_rust::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use bootloader::{entry_point, BootInfo};
+use core::future::Future;
+use core::panic::PanicInfo;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+use os_by_rust::task::executor::{
+    dump_trace, set_tracing, trace_snapshot, Executor, TraceFormat, TraceKind, TRACE_CAPACITY,
+};
+use x86_64::VirtAddr;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use os_by_rust::allocator;
+    use os_by_rust::memory::{self, BootInfoFrameAllocator};
+
+    os_by_rust::init();
+    let physical_memory_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(physical_memory_offset) };
+    let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map) };
+    allocator::init_heap(&mut mapper, &mut frame_allocator).expect("heap initialization failed");
+
+    let mut executor = Executor::new();
+    records_task_lifecycle(&mut executor);
+    disabled_tracing_records_nothing(&mut executor);
+    ring_keeps_latest_events(&mut executor);
+
+    // 两种格式都能输出，文本格式的行供人工查看
+    dump_trace(TraceFormat::Text);
+    dump_trace(TraceFormat::Binary);
+
+    os_by_rust::exit_qemu(os_by_rust::QemuExitCode::Success);
+    os_by_rust::hlt_loop();
+}
+
+fn events_of(task_id: u64) -> Vec<TraceKind> {
+    trace_snapshot()
+        .into_iter()
+        .filter(|event| event.task_id == task_id)
+        .map(|event| event.kind)
+        .collect()
+}
+
+// 让出一次的任务依次产生：派生、轮询、唤醒、挂起、轮询、完成
+fn records_task_lifecycle(executor: &mut Executor) {
+    let handle = executor.spawn(YieldMultiple::new(1));
+    let task_id = handle.id().as_u64();
+    executor.run_until_idle();
+
+    assert_eq!(
+        events_of(task_id),
+        [
+            TraceKind::Spawn,
+            TraceKind::PollStart,
+            TraceKind::Wake,
+            TraceKind::PollEnd,
+            TraceKind::PollStart,
+            TraceKind::Complete,
+        ]
+    );
+}
+
+fn disabled_tracing_records_nothing(executor: &mut Executor) {
+    assert!(set_tracing(false));
+    let handle = executor.spawn(YieldMultiple::new(2));
+    let task_id = handle.id().as_u64();
+    executor.run_until_idle();
+    assert!(!set_tracing(true));
+
+    assert!(events_of(task_id).is_empty());
+}
+
+// 超过容量后只保留最近的事件，序号严格递增
+fn ring_keeps_latest_events(executor: &mut Executor) {
+    let handle = executor.spawn(YieldMultiple::new(TRACE_CAPACITY));
+    let task_id = handle.id().as_u64();
+    executor.run_until_idle();
+
+    let events = trace_snapshot();
+    assert!(events.len() <= TRACE_CAPACITY);
+    assert!(events.len() > TRACE_CAPACITY / 2);
+    assert!(events
+        .windows(2)
+        .all(|pair| pair[0].sequence < pair[1].sequence));
+    // 最早的派生事件已经被覆盖，最后一条是完成事件
+    let own = events_of(task_id);
+    assert!(!own.contains(&TraceKind::Spawn));
+    assert_eq!(own.last(), Some(&TraceKind::Complete));
+}
+
+struct YieldMultiple {
+    remaining_yields: usize,
+}
+
+impl YieldMultiple {
+    fn new(yield_count: usize) -> Self {
+        Self {
+            remaining_yields: yield_count,
+        }
+    }
+}
+
+impl Future for YieldMultiple {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<Self::Output> {
+        if self.remaining_yields == 0 {
+            Poll::Ready(())
+        } else {
+            self.remaining_yields -= 1;
+            context.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    os_by_rust::test_panic_handler(info);
+}
@@ -0,0 +1,55 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(os_by_rust::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use os_by_rust::{acpi, smp};
+use x86_64::VirtAddr;
+
+// 与 Cargo.toml 中 test-args 的 `-smp 4` 保持一致；其他测试不发送 SIPI，应用处理器一直停在固件中
+const EXPECTED_CPUS: usize = 4;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use os_by_rust::allocator;
+    use os_by_rust::memory::{self, BootInfoFrameAllocator};
+
+    os_by_rust::init();
+    let physical_memory_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(physical_memory_offset) };
+    let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map) };
+    allocator::init_heap(&mut mapper, &mut frame_allocator).expect("heap initialization failed");
+
+    let madt = acpi::parse_madt().expect("failed to parse MADT");
+    assert_eq!(madt.processor_apic_ids.len(), EXPECTED_CPUS);
+
+    assert!(smp::current_cpu().is_none());
+    let online = smp::init(&mut mapper, &mut frame_allocator).expect("SMP bring-up failed");
+    assert_eq!(online, EXPECTED_CPUS);
+    assert_eq!(smp::online_cpus(), EXPECTED_CPUS);
+    for cpu_index in 0..EXPECTED_CPUS {
+        assert!(
+            smp::is_online(cpu_index),
+            "CPU {} did not check in",
+            cpu_index
+        );
+    }
+
+    let bsp = smp::current_cpu().expect("BSP per-CPU data missing");
+    assert_eq!(bsp.cpu_index(), 0);
+    assert_eq!(bsp.apic_id(), os_by_rust::apic::id());
+
+    os_by_rust::exit_qemu(os_by_rust::QemuExitCode::Success);
+    os_by_rust::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    os_by_rust::test_panic_handler(info);
+}
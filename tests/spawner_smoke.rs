@@ -0,0 +1,93 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(os_by_rust::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicU64, Ordering};
+use os_by_rust::task::executor::{Executor, SpawnError, Spawner};
+use os_by_rust::task::{sleep_ticks, TaskPriority};
+use os_by_rust::thread;
+use x86_64::VirtAddr;
+
+static CHILDREN_RUN: AtomicU64 = AtomicU64::new(0);
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use os_by_rust::allocator;
+    use os_by_rust::memory::{self, BootInfoFrameAllocator};
+
+    os_by_rust::init();
+    let physical_memory_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(physical_memory_offset) };
+    let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map) };
+    allocator::init_heap(&mut mapper, &mut frame_allocator).expect("heap initialization failed");
+    thread::init();
+
+    // 执行器不运行时注入队列会被填满，错误与 `try_spawn` 一致
+    let idle = Executor::new();
+    let idle_spawner = idle.spawner();
+    let mut pending = Vec::new();
+    let overflow = loop {
+        match idle_spawner.try_spawn(async {}) {
+            Ok(handle) => pending.push(handle),
+            Err(error) => break error,
+        }
+    };
+    assert_eq!(overflow, SpawnError::QueueFull);
+    drop(pending);
+    drop(idle);
+
+    let mut executor = Executor::new();
+    let spawner = executor.spawner();
+    executor.spawn(spawner_smoke_task(spawner.clone()));
+
+    // 另一个内核线程通过克隆的 Spawner 向启动线程上的执行器添加任务
+    thread::spawn(move || {
+        spawner
+            .try_spawn_with_priority(
+                async {
+                    CHILDREN_RUN.fetch_add(100, Ordering::Relaxed);
+                },
+                TaskPriority::High,
+            )
+            .expect("failed to spawn from thread")
+            .detach();
+    })
+    .expect("failed to spawn thread");
+
+    executor.run();
+}
+
+async fn spawner_smoke_task(spawner: Spawner) {
+    // 任务内部派生子任务，子任务再派生孙任务
+    let mut handles = Vec::new();
+    for index in 0..4u64 {
+        let nested = spawner.clone();
+        handles.push(spawner.spawn(async move {
+            CHILDREN_RUN.fetch_add(1, Ordering::Relaxed);
+            nested.spawn(async move { index * 10 }).await.unwrap() + index
+        }));
+    }
+    let mut total = 0;
+    for handle in handles {
+        total += handle.await.expect("child task cancelled");
+    }
+    assert_eq!(total, 66);
+
+    while CHILDREN_RUN.load(Ordering::Relaxed) != 104 {
+        sleep_ticks(1).await;
+    }
+    os_by_rust::exit_qemu(os_by_rust::QemuExitCode::Success);
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    os_by_rust::test_panic_handler(info);
+}
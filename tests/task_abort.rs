@@ -0,0 +1,82 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(os_by_rust::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use os_by_rust::task::executor::Executor;
+use os_by_rust::task::{sleep_ticks, timer, AbortHandle, JoinError, JoinHandle, Task};
+use x86_64::VirtAddr;
+
+static LOOP_ITERATIONS: AtomicU64 = AtomicU64::new(0);
+static NEVER_RAN: AtomicBool = AtomicBool::new(true);
+static PLAIN_TASK_DONE: AtomicBool = AtomicBool::new(false);
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use os_by_rust::allocator;
+    use os_by_rust::memory::{self, BootInfoFrameAllocator};
+
+    os_by_rust::init();
+    let physical_memory_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(physical_memory_offset) };
+    let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map) };
+    allocator::init_heap(&mut mapper, &mut frame_allocator).expect("heap initialization failed");
+
+    let mut executor = Executor::new();
+    let looping = executor.spawn(async {
+        loop {
+            LOOP_ITERATIONS.fetch_add(1, Ordering::Relaxed);
+            sleep_ticks(1000).await;
+        }
+    });
+
+    // 第一次调度前就被取消的任务不会运行
+    let never = executor.spawn(async { NEVER_RAN.store(false, Ordering::Relaxed) });
+    never.abort();
+
+    // 没有 JoinHandle 的任务也可以通过 AbortHandle 取消
+    let plain = Task::new(async {
+        sleep_ticks(1000).await;
+        PLAIN_TASK_DONE.store(true, Ordering::Relaxed);
+    });
+    let plain_abort = plain.abort_handle();
+    executor
+        .try_spawn(plain)
+        .expect("failed to spawn plain task");
+
+    executor.spawn(task_abort_smoke(looping, never, plain_abort));
+    executor.run();
+}
+
+async fn task_abort_smoke(looping: JoinHandle<()>, never: JoinHandle<()>, plain: AbortHandle) {
+    sleep_ticks(1).await;
+    assert_eq!(LOOP_ITERATIONS.load(Ordering::Relaxed), 1);
+    assert_eq!(timer::pending_sleepers(), 2);
+
+    looping.abort();
+    assert_eq!(looping.await, Err(JoinError::Cancelled));
+    assert_eq!(never.await, Err(JoinError::Cancelled));
+    assert!(NEVER_RAN.load(Ordering::Relaxed));
+
+    plain.abort();
+    assert!(plain.is_aborted());
+    sleep_ticks(1).await;
+    // 被取消任务的定时器登记随 future 一起删除
+    assert_eq!(timer::pending_sleepers(), 0);
+    assert!(!PLAIN_TASK_DONE.load(Ordering::Relaxed));
+    assert_eq!(LOOP_ITERATIONS.load(Ordering::Relaxed), 1);
+
+    os_by_rust::exit_qemu(os_by_rust::QemuExitCode::Success);
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    os_by_rust::test_panic_handler(info);
+}
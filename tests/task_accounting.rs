@@ -0,0 +1,100 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(os_by_rust::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use os_by_rust::task::accounting::{self, TaskStats};
+use os_by_rust::task::executor::{Executor, Spawner};
+use os_by_rust::task::{sleep_ticks, yield_now, Task, TaskId};
+use x86_64::VirtAddr;
+
+// 让出执行权的次数，任务共被轮询 YIELDS + 1 次
+const YIELDS: u64 = 5;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use os_by_rust::allocator;
+    use os_by_rust::memory::{self, BootInfoFrameAllocator};
+
+    os_by_rust::init();
+    let physical_memory_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(physical_memory_offset) };
+    let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map) };
+    allocator::init_heap(&mut mapper, &mut frame_allocator).expect("heap initialization failed");
+
+    let mut executor = Executor::new();
+    let spawner = executor.spawner();
+    let idle = Task::new(core::future::pending::<()>()).named("idle");
+    let idle_id = idle.id;
+    let yielder = Task::new(yielder()).named("yielder");
+    let yielder_id = yielder.id;
+    executor.try_spawn(idle).expect("failed to spawn idle task");
+    executor
+        .try_spawn(yielder)
+        .expect("failed to spawn yielder");
+    executor
+        .try_spawn(Task::new(checker(spawner, idle_id, yielder_id)).named("checker"))
+        .expect("failed to spawn checker");
+
+    let stats = executor.task_stats();
+    assert_eq!(stats.len(), 3);
+    let idle_stats = find(&stats, idle_id);
+    assert_eq!(idle_stats.name, Some("idle"));
+    assert_eq!(idle_stats.polls, 0);
+    assert_eq!(idle_stats.last_run_tick, None);
+
+    executor.run();
+}
+
+async fn checker(spawner: Spawner, idle_id: TaskId, yielder_id: TaskId) {
+    sleep_ticks(1).await;
+
+    let stats = accounting::task_list();
+    let yielder_stats = find(&stats, yielder_id);
+    assert_eq!(yielder_stats.name, Some("yielder"));
+    assert_eq!(yielder_stats.polls, YIELDS + 1);
+    assert_eq!(yielder_stats.wakes, YIELDS);
+    assert!(yielder_stats.poll_cycles > 0);
+    assert!(yielder_stats.last_run_tick.is_some());
+
+    let idle_stats = find(&stats, idle_id);
+    assert_eq!(idle_stats.polls, 1);
+    assert_eq!(idle_stats.wakes, 0);
+
+    // 结束的任务从列表中移除
+    let finished = spawner.spawn(async {});
+    let finished_id = finished.id();
+    finished.await.expect("short task failed");
+    assert!(accounting::task_list()
+        .iter()
+        .all(|task| task.id != finished_id));
+
+    accounting::print_top();
+    os_by_rust::exit_qemu(os_by_rust::QemuExitCode::Success);
+}
+
+// 让出几次后永远挂起，便于检查它的计数
+async fn yielder() {
+    for _ in 0..YIELDS {
+        yield_now().await;
+    }
+    core::future::pending::<()>().await;
+}
+
+fn find(stats: &[TaskStats], id: TaskId) -> TaskStats {
+    *stats
+        .iter()
+        .find(|task| task.id == id)
+        .expect("task missing from accounting")
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    os_by_rust::test_panic_handler(info);
+}
@@ -0,0 +1,151 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(os_by_rust::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use os_by_rust::task::executor::{Executor, Spawner};
+use os_by_rust::task::{sleep_ticks, GroupError, JoinError, TaskGroup};
+use x86_64::VirtAddr;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use os_by_rust::allocator;
+    use os_by_rust::memory::{self, BootInfoFrameAllocator};
+
+    os_by_rust::init();
+    let physical_memory_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(physical_memory_offset) };
+    let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map) };
+    allocator::init_heap(&mut mapper, &mut frame_allocator).expect("heap initialization failed");
+
+    let mut executor = Executor::new();
+    let spawner = executor.spawner();
+    executor.block_on(join_all_keeps_spawn_order(spawner.clone()));
+    executor.block_on(join_next_follows_completion(spawner.clone()));
+    executor.block_on(first_failure_cancels_siblings(spawner.clone()));
+    executor.block_on(dropping_group_cancels_children(spawner.clone()));
+
+    // 被取消的子任务都已经离开执行器
+    executor.run_until_idle();
+    assert_eq!(spawner.live_tasks(), 0);
+    os_by_rust::exit_qemu(os_by_rust::QemuExitCode::Success);
+    os_by_rust::hlt_loop();
+}
+
+async fn join_all_keeps_spawn_order(spawner: Spawner) {
+    let mut group = TaskGroup::new(spawner.clone());
+    for index in 0..4u64 {
+        // 先派生的睡得更久，完成顺序与派生顺序相反
+        group.spawn(async move {
+            sleep_ticks(4 - index).await;
+            index
+        });
+    }
+    assert_eq!(group.len(), 4);
+    assert_eq!(group.join_all().await, vec![Ok(0), Ok(1), Ok(2), Ok(3)]);
+
+    let mut group = TaskGroup::new(spawner);
+    group.spawn(async { Ok::<u32, ()>(1) });
+    group.spawn(async { Ok(2) });
+    assert_eq!(group.try_join_all().await, Ok(vec![1, 2]));
+}
+
+async fn join_next_follows_completion(spawner: Spawner) {
+    let mut group = TaskGroup::new(spawner);
+    for ticks in [3u64, 1, 2] {
+        group.spawn(async move {
+            sleep_ticks(ticks).await;
+            ticks
+        });
+    }
+    let mut finished = Vec::new();
+    while let Some(result) = group.join_next().await {
+        finished.push(result.expect("child cancelled"));
+    }
+    assert_eq!(finished, vec![1, 2, 3]);
+    assert!(group.is_empty());
+}
+
+async fn first_failure_cancels_siblings(spawner: Spawner) {
+    let completed = Arc::new(AtomicUsize::new(0));
+    let dropped = Arc::new(AtomicUsize::new(0));
+    let mut group = TaskGroup::new(spawner);
+    for _ in 0..3 {
+        let guard = DropCounter(dropped.clone());
+        let completed = completed.clone();
+        group.spawn(async move {
+            let _guard = guard;
+            sleep_ticks(1_000).await;
+            completed.fetch_add(1, Ordering::Relaxed);
+            Ok(())
+        });
+    }
+    group.spawn(async {
+        sleep_ticks(1).await;
+        Err("service failed")
+    });
+
+    assert_eq!(
+        group.try_join_all().await,
+        Err(GroupError::Failed("service failed"))
+    );
+    // 被取消的兄弟任务在下一轮调度时被移除，其 future 随之析构
+    sleep_ticks(1).await;
+    assert_eq!(dropped.load(Ordering::Relaxed), 3);
+    assert_eq!(completed.load(Ordering::Relaxed), 0);
+}
+
+async fn dropping_group_cancels_children(spawner: Spawner) {
+    let dropped = Arc::new(AtomicUsize::new(0));
+    let baseline = spawner.live_tasks();
+    let mut group = TaskGroup::new(spawner.clone());
+    for _ in 0..2 {
+        let guard = DropCounter(dropped.clone());
+        group.spawn(async move {
+            let _guard = guard;
+            loop {
+                sleep_ticks(1).await;
+            }
+        });
+    }
+    sleep_ticks(2).await;
+    assert_eq!(spawner.live_tasks(), baseline + 2);
+
+    drop(group);
+    sleep_ticks(1).await;
+    assert_eq!(dropped.load(Ordering::Relaxed), 2);
+    assert_eq!(spawner.live_tasks(), baseline);
+
+    // 显式取消的子任务得到 `Cancelled`
+    let mut group = TaskGroup::new(spawner);
+    group.spawn(async {
+        loop {
+            sleep_ticks(1).await;
+        }
+    });
+    group.abort_all();
+    assert_eq!(group.join_next().await, Some(Err(JoinError::Cancelled)));
+}
+
+struct DropCounter(Arc<AtomicUsize>);
+
+impl Drop for DropCounter {
+    fn drop(&mut self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    os_by_rust::test_panic_handler(info);
+}
@@ -0,0 +1,185 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(os_by_rust::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use bootloader::{entry_point, BootInfo};
+use core::cell::Cell;
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use os_by_rust::task::executor::Executor;
+use os_by_rust::task::group::TaskGroup;
+use os_by_rust::task::{yield_now, AccessError, Locals, Task, TaskPriority};
+use os_by_rust::task_local;
+use x86_64::VirtAddr;
+
+task_local! {
+    /// 日志前缀
+    static PREFIX: &'static str;
+    static DEPTH: Cell<u32>;
+    pub static GUARD: DropCounter;
+}
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use os_by_rust::allocator;
+    use os_by_rust::memory::{self, BootInfoFrameAllocator};
+
+    os_by_rust::init();
+    let physical_memory_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(physical_memory_offset) };
+    let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map) };
+    allocator::init_heap(&mut mapper, &mut frame_allocator).expect("heap initialization failed");
+
+    let mut executor = Executor::new();
+    each_task_sees_its_own_value(&mut executor);
+    unset_key_reports_error(&mut executor);
+    cell_value_is_mutable(&mut executor);
+    values_drop_with_task(&mut executor);
+    spawner_and_group_carry_locals(&mut executor);
+
+    os_by_rust::exit_qemu(os_by_rust::QemuExitCode::Success);
+    os_by_rust::hlt_loop();
+}
+
+// 深层的 future 不经参数传递即可读到，跨 `.await` 保持不变
+fn each_task_sees_its_own_value(executor: &mut Executor) {
+    let seen = Arc::new(spin::Mutex::new(Vec::new()));
+    for prefix in ["[net]", "[fs]"] {
+        let seen = seen.clone();
+        let task = Task::new(async move {
+            for _ in 0..2 {
+                let current = log_prefix().await;
+                seen.lock().push(current);
+                yield_now().await;
+            }
+        })
+        .with_local(&PREFIX, "[replaced]")
+        .with_local(&PREFIX, prefix);
+        executor.try_spawn(task).unwrap();
+    }
+    executor.run_until_idle();
+    assert_eq!(*seen.lock(), ["[net]", "[fs]", "[net]", "[fs]"]);
+}
+
+async fn log_prefix() -> &'static str {
+    yield_now().await;
+    PREFIX.get()
+}
+
+fn unset_key_reports_error(executor: &mut Executor) {
+    // 执行器之外没有当前任务
+    assert_eq!(PREFIX.try_with(|_| ()), Err(AccessError));
+    assert!(!PREFIX.is_set());
+
+    let results = Arc::new(spin::Mutex::new(None));
+    let task = {
+        let results = results.clone();
+        Task::new(async move {
+            *results.lock() = Some((PREFIX.is_set(), DEPTH.try_with(Cell::get)));
+        })
+        .with_local(&PREFIX, "[only prefix]")
+    };
+    executor.try_spawn(task).unwrap();
+    executor.run_until_idle();
+    assert_eq!(*results.lock(), Some((true, Err(AccessError))));
+    assert_eq!(PREFIX.name(), "PREFIX");
+}
+
+fn cell_value_is_mutable(executor: &mut Executor) {
+    let depth = Arc::new(AtomicUsize::new(0));
+    let task = {
+        let depth = depth.clone();
+        Task::new(async move {
+            for _ in 0..3 {
+                DEPTH.with(|value| value.set(value.get() + 1));
+                yield_now().await;
+            }
+            depth.store(DEPTH.with(Cell::get) as usize, Ordering::Relaxed);
+        })
+        .with_local(&DEPTH, Cell::new(10))
+    };
+    executor.try_spawn(task).unwrap();
+    executor.run_until_idle();
+    assert_eq!(depth.load(Ordering::Relaxed), 13);
+}
+
+// 任务完成或被取消后，局部变量随任务析构
+fn values_drop_with_task(executor: &mut Executor) {
+    let drops = Arc::new(AtomicUsize::new(0));
+
+    let finished = Task::new(async {
+        GUARD.with(|_| ());
+    })
+    .with_local(&GUARD, DropCounter(drops.clone()));
+    executor.try_spawn(finished).unwrap();
+    assert_eq!(drops.load(Ordering::Relaxed), 0);
+    executor.run_until_idle();
+    assert_eq!(drops.load(Ordering::Relaxed), 1);
+
+    let pending = Task::new(core::future::pending()).with_local(&GUARD, DropCounter(drops.clone()));
+    let abort = pending.abort_handle();
+    executor.try_spawn(pending).unwrap();
+    executor.run_until(|| true);
+    assert_eq!(drops.load(Ordering::Relaxed), 1);
+    abort.abort();
+    executor.run_until_idle();
+    assert_eq!(drops.load(Ordering::Relaxed), 2);
+}
+
+// 任务中派生的子任务不继承父任务的值，需要时通过 `Locals` 显式传递
+fn spawner_and_group_carry_locals(executor: &mut Executor) {
+    let spawner = executor.spawner();
+    let seen = executor
+        .spawner()
+        .try_spawn_with_locals(
+            async move {
+                let mut group = TaskGroup::new(spawner.clone());
+                group
+                    .try_spawn_with_locals(
+                        async { PREFIX.try_with(|prefix| *prefix) },
+                        TaskPriority::Normal,
+                        Locals::new().inherit(&PREFIX),
+                    )
+                    .unwrap();
+                group
+                    .try_spawn_with_locals(
+                        async { PREFIX.try_with(|prefix| *prefix) },
+                        TaskPriority::Normal,
+                        Locals::new().with(&PREFIX, "[child]"),
+                    )
+                    .unwrap();
+                group.spawn(async { PREFIX.try_with(|prefix| *prefix) });
+                let children = group.join_all().await;
+                (PREFIX.get(), children)
+            },
+            TaskPriority::Normal,
+            Locals::new().with(&PREFIX, "[parent]"),
+        )
+        .unwrap();
+    let (parent, children) = executor.block_on(seen).unwrap();
+    assert_eq!(parent, "[parent]");
+    assert_eq!(
+        children,
+        [Ok(Ok("[parent]")), Ok(Ok("[child]")), Ok(Err(AccessError))]
+    );
+}
+
+pub struct DropCounter(Arc<AtomicUsize>);
+
+impl Drop for DropCounter {
+    fn drop(&mut self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    os_by_rust::test_panic_handler(info);
+}
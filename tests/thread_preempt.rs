@@ -0,0 +1,71 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(os_by_rust::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use os_by_rust::task::{sleep_ticks, Task};
+use os_by_rust::thread;
+use x86_64::VirtAddr;
+
+static SPIN_COUNTER: AtomicU64 = AtomicU64::new(0);
+static JOINED_THREAD_RAN: AtomicBool = AtomicBool::new(false);
+static EXECUTOR_TASK_DONE: AtomicBool = AtomicBool::new(false);
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use os_by_rust::allocator;
+    use os_by_rust::memory::{self, BootInfoFrameAllocator};
+
+    os_by_rust::init();
+    let physical_memory_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(physical_memory_offset) };
+    let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map) };
+    allocator::init_heap(&mut mapper, &mut frame_allocator).expect("heap initialization failed");
+    thread::init();
+
+    // 永不让出的线程：只有时钟抢占才能让其他线程继续运行
+    thread::spawn(|| loop {
+        SPIN_COUNTER.fetch_add(1, Ordering::Relaxed);
+    })
+    .expect("failed to spawn spinning thread");
+
+    let short_lived = thread::spawn(|| JOINED_THREAD_RAN.store(true, Ordering::Relaxed))
+        .expect("failed to spawn short-lived thread");
+    thread::join(short_lived);
+    assert!(thread::is_finished(short_lived));
+    assert!(JOINED_THREAD_RAN.load(Ordering::Relaxed));
+
+    thread::spawn_executor(|executor| {
+        executor
+            .try_spawn(Task::new(async {
+                sleep_ticks(2).await;
+                EXECUTOR_TASK_DONE.store(true, Ordering::Relaxed);
+            }))
+            .expect("failed to spawn executor task");
+    })
+    .expect("failed to spawn executor thread");
+
+    // 启动线程同样不主动让出，依赖抢占推进其他线程
+    let counter_before = SPIN_COUNTER.load(Ordering::Relaxed);
+    while !EXECUTOR_TASK_DONE.load(Ordering::Relaxed)
+        || SPIN_COUNTER.load(Ordering::Relaxed) == counter_before
+    {
+        core::hint::spin_loop();
+    }
+    assert_eq!(thread::current_id().as_u64(), 0);
+
+    os_by_rust::exit_qemu(os_by_rust::QemuExitCode::Success);
+    os_by_rust::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    os_by_rust::test_panic_handler(info);
+}
@@ -0,0 +1,95 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(os_by_rust::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use os_by_rust::smp;
+use os_by_rust::task::accounting::read_tsc;
+use os_by_rust::task::executor::Executor;
+use os_by_rust::task::timer;
+use os_by_rust::task::{sleep_ticks, Task};
+use x86_64::VirtAddr;
+
+// 无滴答睡眠的长度
+const SLEEP_TICKS: u64 = 20;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use os_by_rust::allocator;
+    use os_by_rust::memory::{self, BootInfoFrameAllocator};
+
+    os_by_rust::init();
+    let physical_memory_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(physical_memory_offset) };
+    let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map) };
+    allocator::init_heap(&mut mapper, &mut frame_allocator).expect("heap initialization failed");
+
+    assert!(timer::enable_tickless().is_err());
+    smp::init(&mut mapper, &mut frame_allocator).expect("SMP bring-up failed");
+    assert!(!timer::is_tickless());
+    let counts_per_tick = timer::enable_tickless().expect("failed to enable tickless idle");
+    assert!(counts_per_tick > 0);
+    assert!(timer::is_tickless());
+
+    let mut executor = Executor::new();
+    executor
+        .try_spawn(Task::new(checker()))
+        .expect("failed to spawn checker");
+    executor.run();
+}
+
+async fn checker() {
+    // PIT 周期中断正常工作时每个 tick 的 TSC 周期数
+    let cycles_per_tick = measure_periodic_tick();
+
+    let before = timer::idle_stats();
+    let start_tick = timer::current_tick();
+    let start_tsc = read_tsc();
+    sleep_ticks(SLEEP_TICKS).await;
+    let elapsed_cycles = read_tsc() - start_tsc;
+    let after = timer::idle_stats();
+
+    assert!(timer::current_tick() >= start_tick + SLEEP_TICKS);
+    assert!(after.halts > before.halts);
+    assert!(after.tickless_halts > before.tickless_halts);
+    assert!(after.idle_cycles > before.idle_cycles);
+    // 睡眠期间的 tick 大多由唤醒后的补偿推进，而不是 PIT 中断
+    assert!(after.skipped_ticks - before.skipped_ticks >= SLEEP_TICKS / 2);
+
+    // 补上的 tick 与实际经过的时间一致
+    let elapsed_ticks = elapsed_cycles / cycles_per_tick;
+    assert!(
+        (SLEEP_TICKS * 7 / 10..=SLEEP_TICKS * 3 / 2).contains(&elapsed_ticks),
+        "tickless sleep of {} ticks took {} ticks of wall time",
+        SLEEP_TICKS,
+        elapsed_ticks
+    );
+
+    // 空闲结束后 PIT 周期中断已经恢复
+    measure_periodic_tick();
+    os_by_rust::exit_qemu(os_by_rust::QemuExitCode::Success);
+}
+
+// 忙等两个 PIT tick（执行器不空闲，周期中断没有被屏蔽），返回每个 tick 的 TSC 周期数
+fn measure_periodic_tick() -> u64 {
+    let start = timer::current_tick();
+    while timer::current_tick() == start {
+        core::hint::spin_loop();
+    }
+    let start_tsc = read_tsc();
+    while timer::current_tick() < start + 3 {
+        core::hint::spin_loop();
+    }
+    (read_tsc() - start_tsc) / 2
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    os_by_rust::test_panic_handler(info);
+}
@@ -0,0 +1,103 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(os_by_rust::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use futures_util::future::{self, Either};
+use futures_util::stream::StreamExt;
+use os_by_rust::task::executor::Executor;
+use os_by_rust::task::timer::{self, Elapsed};
+use os_by_rust::task::{interval, sleep_ticks, timeout, Task};
+use x86_64::VirtAddr;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use os_by_rust::allocator;
+    use os_by_rust::memory::{self, BootInfoFrameAllocator};
+
+    os_by_rust::init();
+    let physical_memory_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(physical_memory_offset) };
+    let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map) };
+    allocator::init_heap(&mut mapper, &mut frame_allocator).expect("heap initialization failed");
+
+    let mut executor = Executor::new();
+    executor
+        .try_spawn(Task::new(checks()))
+        .expect("failed to spawn checks");
+    executor.run();
+}
+
+async fn checks() {
+    two_sleeps_in_one_task().await;
+    timeout_expires_and_drops_inner().await;
+    timeout_passes_output_through().await;
+    interval_keeps_its_period().await;
+    os_by_rust::exit_qemu(os_by_rust::QemuExitCode::Success);
+}
+
+// 同一任务中的两个睡眠各自到期，先完成的一方不影响另一方
+async fn two_sleeps_in_one_task() {
+    let start = timer::current_tick();
+    let short = sleep_ticks(2);
+    let long = sleep_ticks(6);
+    let long = match future::select(short, long).await {
+        Either::Left(((), long)) => long,
+        Either::Right(_) => panic!("long sleep finished first"),
+    };
+    assert!(timer::current_tick() >= start + 2);
+    assert!(timer::current_tick() < start + 6);
+    assert_eq!(timer::pending_sleepers(), 1);
+
+    long.await;
+    assert!(timer::current_tick() >= start + 6);
+    assert_eq!(timer::pending_sleepers(), 0);
+}
+
+async fn timeout_expires_and_drops_inner() {
+    let start = timer::current_tick();
+    let result = timeout(3, sleep_ticks(1000)).await;
+    assert_eq!(result, Err(Elapsed));
+    assert!(timer::current_tick() >= start + 3);
+    // 内部睡眠随 `Timeout` 一起丢弃，登记被删除
+    assert_eq!(timer::pending_sleepers(), 0);
+}
+
+async fn timeout_passes_output_through() {
+    let result = timeout(1000, async {
+        sleep_ticks(2).await;
+        42
+    })
+    .await;
+    assert_eq!(result, Ok(42));
+    assert_eq!(timer::pending_sleepers(), 0);
+}
+
+async fn interval_keeps_its_period() {
+    let mut ticks = interval(2);
+    let first = ticks.tick().await;
+    let second = ticks.next().await.expect("interval ended");
+    let third = ticks.tick().await;
+    assert_eq!(second - first, 2);
+    assert_eq!(third - second, 2);
+    assert_eq!(ticks.period(), 2);
+
+    // 错过的期限只触发一次，之后跳过错过的周期，期限仍在周期网格上
+    sleep_ticks(7).await;
+    let late = ticks.tick().await;
+    assert_eq!(late - third, 2);
+    let next = ticks.tick().await;
+    assert!(next - late >= 6);
+    assert_eq!((next - late) % 2, 0);
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    os_by_rust::test_panic_handler(info);
+}
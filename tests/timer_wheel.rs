@@ -0,0 +1,161 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(os_by_rust::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::sync::Arc;
+use alloc::task::Wake;
+use alloc::vec::Vec;
+use bootloader::{entry_point, BootInfo};
+use core::future::Future;
+use core::panic::PanicInfo;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use core::task::{Context, Poll, Waker};
+use os_by_rust::task::accounting::read_tsc;
+use os_by_rust::task::sleep_ticks;
+use os_by_rust::task::timer::{self, Sleep};
+use x86_64::instructions::interrupts;
+use x86_64::VirtAddr;
+
+// 同时登记的睡眠者数量
+const SLEEPERS: usize = 2000;
+
+static WOKEN: AtomicUsize = AtomicUsize::new(0);
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use os_by_rust::allocator;
+    use os_by_rust::memory::{self, BootInfoFrameAllocator};
+
+    os_by_rust::init();
+    let physical_memory_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(physical_memory_offset) };
+    let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map) };
+    allocator::init_heap(&mut mapper, &mut frame_allocator).expect("heap initialization failed");
+
+    // 关闭时钟中断，由测试手动推进 tick
+    interrupts::disable();
+    test_main();
+    os_by_rust::exit_qemu(os_by_rust::QemuExitCode::Success);
+    os_by_rust::hlt_loop();
+}
+
+// 第 `index` 个睡眠者的延迟，严格递增并覆盖时间轮的前三层
+fn delay(index: usize) -> u64 {
+    1 + (index as u64 * 9) / 4
+}
+
+#[test_case]
+fn thousands_of_sleepers_expire_on_their_tick() {
+    let waker = CountingWake::waker();
+    let mut context = Context::from_waker(&waker);
+    WOKEN.store(0, Ordering::Relaxed);
+
+    let mut sleepers: Vec<Sleep> = (0..SLEEPERS)
+        .map(|index| sleep_ticks(delay(index)))
+        .collect();
+    for sleeper in sleepers.iter_mut() {
+        assert_eq!(Pin::new(sleeper).poll(&mut context), Poll::Pending);
+    }
+    // 重复轮询只更新登记，不会重复登记
+    for sleeper in sleepers.iter_mut() {
+        assert_eq!(Pin::new(sleeper).poll(&mut context), Poll::Pending);
+    }
+    assert_eq!(timer::pending_sleepers(), SLEEPERS);
+
+    let mut expected = 0;
+    let mut total_cycles = 0;
+    let mut max_cycles = 0;
+    for elapsed in 1..=delay(SLEEPERS - 1) {
+        let start = read_tsc();
+        timer::tick();
+        let cycles = read_tsc().wrapping_sub(start);
+        total_cycles += cycles;
+        max_cycles = max_cycles.max(cycles);
+
+        while expected < SLEEPERS && delay(expected) <= elapsed {
+            expected += 1;
+        }
+        assert_eq!(WOKEN.load(Ordering::Relaxed), expected);
+        assert_eq!(timer::pending_sleepers(), SLEEPERS - expected);
+    }
+    os_by_rust::serial_println!(
+        "[bench] {} sleepers over {} ticks: mean={} max={} cycles per tick",
+        SLEEPERS,
+        delay(SLEEPERS - 1),
+        total_cycles / delay(SLEEPERS - 1),
+        max_cycles,
+    );
+
+    for sleeper in sleepers.iter_mut() {
+        assert_eq!(Pin::new(sleeper).poll(&mut context), Poll::Ready(()));
+    }
+    assert_eq!(timer::pending_sleepers(), 0);
+}
+
+#[test_case]
+fn dropped_sleepers_are_never_woken() {
+    let waker = CountingWake::waker();
+    let mut context = Context::from_waker(&waker);
+    WOKEN.store(0, Ordering::Relaxed);
+
+    let mut sleepers: Vec<Sleep> = (0..SLEEPERS)
+        .map(|index| sleep_ticks(delay(index)))
+        .collect();
+    for sleeper in sleepers.iter_mut() {
+        assert_eq!(Pin::new(sleeper).poll(&mut context), Poll::Pending);
+    }
+
+    // 丢弃后一半，剩下的在最后一个到期前全部唤醒
+    sleepers.truncate(SLEEPERS / 2);
+    assert_eq!(timer::pending_sleepers(), SLEEPERS / 2);
+    for _ in 0..delay(SLEEPERS - 1) {
+        timer::tick();
+    }
+    assert_eq!(WOKEN.load(Ordering::Relaxed), SLEEPERS / 2);
+    assert_eq!(timer::pending_sleepers(), 0);
+}
+
+#[test_case]
+fn long_sleep_cascades_through_every_level() {
+    let waker = CountingWake::waker();
+    let mut context = Context::from_waker(&waker);
+    WOKEN.store(0, Ordering::Relaxed);
+
+    // 超过前三层的范围，需要从第四层逐层分配下来
+    const LONG: u64 = 64 * 64 * 64 + 100;
+    let mut sleeper = sleep_ticks(LONG);
+    assert_eq!(Pin::new(&mut sleeper).poll(&mut context), Poll::Pending);
+    for _ in 1..LONG {
+        timer::tick();
+    }
+    assert_eq!(WOKEN.load(Ordering::Relaxed), 0);
+    assert_eq!(Pin::new(&mut sleeper).poll(&mut context), Poll::Pending);
+    timer::tick();
+    assert_eq!(WOKEN.load(Ordering::Relaxed), 1);
+    assert_eq!(Pin::new(&mut sleeper).poll(&mut context), Poll::Ready(()));
+}
+
+struct CountingWake;
+
+impl CountingWake {
+    fn waker() -> Waker {
+        Waker::from(Arc::new(Self))
+    }
+}
+
+impl Wake for CountingWake {
+    fn wake(self: Arc<Self>) {
+        WOKEN.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    os_by_rust::test_panic_handler(info);
+}
@@ -0,0 +1,121 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(os_by_rust::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use bootloader::{entry_point, BootInfo};
+use core::arch::global_asm;
+use core::panic::PanicInfo;
+use os_by_rust::usermode::{self, USER_SPACE_START};
+use x86_64::structures::paging::{Page, PageTableFlags};
+use x86_64::VirtAddr;
+
+const EXPECTED_OUTPUT: &[u8] = b"hello from ring 3\n";
+const EXPECTED_EXIT_CODE: u64 = 42;
+
+// 位置无关的用户程序：先用未映射的缓冲区和跨入未映射页的缓冲区调用 `write`，
+// 两次都必须得到 EFAULT（-14），否则以退出码 1 结束；然后打印一行文字后以退出码 42 结束
+global_asm!(
+    ".section .rodata.user_program",
+    ".global user_program_start",
+    ".global user_program_end",
+    "user_program_start:",
+    "mov rax, 4",
+    "syscall",
+    // 用户地址空间第 16 页没有映射
+    "mov rax, 0",
+    "mov rdi, 1",
+    "mov rsi, 0x200000010000",
+    "mov rdx, 8",
+    "syscall",
+    "cmp rax, -14",
+    "jne user_program_fail",
+    // 从栈页的最后 4 个字节跨入未映射的第 2 页
+    "mov rax, 0",
+    "mov rdi, 1",
+    "mov rsi, 0x200000001ffc",
+    "mov rdx, 8",
+    "syscall",
+    "cmp rax, -14",
+    "jne user_program_fail",
+    "mov rax, 0",
+    "mov rdi, 1",
+    "lea rsi, [rip + user_program_message]",
+    "mov rdx, 18",
+    "syscall",
+    "mov rax, 1",
+    "mov rdi, 42",
+    "syscall",
+    "ud2",
+    "user_program_fail:",
+    "mov rax, 1",
+    "mov rdi, 1",
+    "syscall",
+    "ud2",
+    "user_program_message:",
+    ".ascii \"hello from ring 3\\n\"",
+    "user_program_end:",
+    ".previous",
+);
+
+extern "C" {
+    static user_program_start: u8;
+    static user_program_end: u8;
+}
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use os_by_rust::allocator;
+    use os_by_rust::memory::{self, BootInfoFrameAllocator};
+
+    os_by_rust::init();
+    let physical_memory_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(physical_memory_offset) };
+    let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map) };
+    allocator::init_heap(&mut mapper, &mut frame_allocator).expect("heap initialization failed");
+
+    let program = unsafe {
+        let start = core::ptr::addr_of!(user_program_start);
+        let end = core::ptr::addr_of!(user_program_end);
+        core::slice::from_raw_parts(start, end as usize - start as usize)
+    };
+    let code_page = Page::containing_address(VirtAddr::new(USER_SPACE_START));
+    let stack_page = code_page + 1;
+    usermode::map_user_page(
+        &mut mapper,
+        &mut frame_allocator,
+        code_page,
+        PageTableFlags::empty(),
+        program,
+    )
+    .expect("failed to map user program");
+    usermode::map_user_page(
+        &mut mapper,
+        &mut frame_allocator,
+        stack_page,
+        PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE,
+        &[],
+    )
+    .expect("failed to map user stack");
+
+    let exit_code = unsafe {
+        usermode::enter_user_mode(
+            code_page.start_address(),
+            stack_page.start_address() + stack_page.size(),
+        )
+    };
+    assert_eq!(exit_code, EXPECTED_EXIT_CODE);
+    assert_eq!(usermode::take_captured_output(), EXPECTED_OUTPUT);
+
+    os_by_rust::exit_qemu(os_by_rust::QemuExitCode::Success);
+    os_by_rust::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    os_by_rust::test_panic_handler(info);
+}
@@ -0,0 +1,119 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(os_by_rust::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use bootloader::{entry_point, BootInfo};
+use core::future::Future;
+use core::panic::PanicInfo;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use core::task::{Context, Poll, Waker};
+use os_by_rust::task::executor::{self, Executor, SpawnError, MAX_TASKS};
+use os_by_rust::task::{yield_now, Task};
+use spin::Mutex;
+use x86_64::VirtAddr;
+
+// 通知任务之外的所有任务都在等待
+const WAITERS: usize = MAX_TASKS - 1;
+// 每个等待者被重复唤醒的次数，总唤醒数远超就绪队列容量
+const WAKES_PER_WAITER: usize = 10;
+
+static RELEASED: AtomicBool = AtomicBool::new(false);
+static WAITER_POLLS: AtomicU64 = AtomicU64::new(0);
+static WAITERS_DONE: AtomicU64 = AtomicU64::new(0);
+static WAITER_WAKERS: Mutex<Vec<Waker>> = Mutex::new(Vec::new());
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use os_by_rust::allocator;
+    use os_by_rust::memory::{self, BootInfoFrameAllocator};
+
+    os_by_rust::init();
+    let physical_memory_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(physical_memory_offset) };
+    let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map) };
+    allocator::init_heap(&mut mapper, &mut frame_allocator).expect("heap initialization failed");
+
+    let mut executor = Executor::new();
+    for _ in 0..WAITERS {
+        executor
+            .try_spawn(Task::new(async {
+                Released.await;
+                WAITERS_DONE.fetch_add(1, Ordering::Relaxed);
+            }))
+            .expect("failed to spawn waiter");
+    }
+    executor
+        .try_spawn(Task::new(notifier()))
+        .expect("failed to spawn notifier");
+    assert_eq!(
+        executor.try_spawn(Task::new(async {})),
+        Err(SpawnError::QueueFull)
+    );
+    executor.run();
+}
+
+async fn notifier() {
+    while WAITER_WAKERS.lock().len() < WAITERS {
+        yield_now().await;
+    }
+    let polls_before = WAITER_POLLS.load(Ordering::Relaxed);
+
+    // 同一个任务被反复唤醒只会入队一次
+    RELEASED.store(true, Ordering::Relaxed);
+    let wakers = core::mem::take(&mut *WAITER_WAKERS.lock());
+    for _ in 0..WAKES_PER_WAITER {
+        for waker in &wakers {
+            waker.wake_by_ref();
+        }
+    }
+
+    while WAITERS_DONE.load(Ordering::Relaxed) < WAITERS as u64 {
+        yield_now().await;
+    }
+    assert_eq!(
+        WAITER_POLLS.load(Ordering::Relaxed) - polls_before,
+        WAITERS as u64
+    );
+    assert_eq!(executor::global_stats_snapshot().dropped_wakes, 0);
+
+    // 已结束任务残留的唤醒器不再入队
+    for waker in &wakers {
+        waker.wake_by_ref();
+    }
+    yield_now().await;
+    assert_eq!(
+        WAITER_POLLS.load(Ordering::Relaxed) - polls_before,
+        WAITERS as u64
+    );
+    assert_eq!(executor::global_stats_snapshot().dropped_wakes, 0);
+
+    os_by_rust::exit_qemu(os_by_rust::QemuExitCode::Success);
+}
+
+struct Released;
+
+impl Future for Released {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<Self::Output> {
+        WAITER_POLLS.fetch_add(1, Ordering::Relaxed);
+        if RELEASED.load(Ordering::Relaxed) {
+            Poll::Ready(())
+        } else {
+            WAITER_WAKERS.lock().push(context.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    os_by_rust::test_panic_handler(info);
+}
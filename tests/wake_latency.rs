@@ -0,0 +1,97 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(os_by_rust::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use os_by_rust::task::executor::{
+    self, Executor, LatencyHistogram, FIRST_BUCKET_CYCLES, LATENCY_BUCKETS,
+};
+use os_by_rust::task::{sleep_ticks, yield_now, Task, TaskPriority};
+use x86_64::VirtAddr;
+
+// 高优先级任务让出执行权的次数
+const YIELDS: u64 = 8;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use os_by_rust::allocator;
+    use os_by_rust::memory::{self, BootInfoFrameAllocator};
+
+    os_by_rust::init();
+    let physical_memory_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(physical_memory_offset) };
+    let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map) };
+    allocator::init_heap(&mut mapper, &mut frame_allocator).expect("heap initialization failed");
+
+    assert_eq!(LatencyHistogram::bucket_upper_bound(0), FIRST_BUCKET_CYCLES);
+    assert_eq!(
+        LatencyHistogram::bucket_upper_bound(1),
+        2 * FIRST_BUCKET_CYCLES
+    );
+    assert_eq!(
+        LatencyHistogram::bucket_upper_bound(LATENCY_BUCKETS - 1),
+        u64::MAX
+    );
+
+    let mut executor = Executor::new();
+    assert!(executor
+        .stats()
+        .wake_latency
+        .iter()
+        .all(|latency| latency.count == 0));
+
+    let (yielder, yielder_handle) = Task::joinable_with_priority(yielder(), TaskPriority::High);
+    let (sleeper, sleeper_handle) = Task::joinable_with_priority(sleep_ticks(2), TaskPriority::Low);
+    executor
+        .try_spawn(yielder)
+        .expect("failed to spawn yielder");
+    executor
+        .try_spawn(sleeper)
+        .expect("failed to spawn sleeper");
+    executor
+        .try_spawn(Task::new(async move {
+            yielder_handle.await.expect("yielder cancelled");
+            sleeper_handle.await.expect("sleeper cancelled");
+            check_histograms();
+            os_by_rust::exit_qemu(os_by_rust::QemuExitCode::Success);
+        }))
+        .expect("failed to spawn checker");
+    executor.run();
+}
+
+fn check_histograms() {
+    let stats = executor::global_stats_snapshot();
+
+    // 派生时的入队加上每次让出后的唤醒
+    let high = stats.wake_latency[TaskPriority::High.level()];
+    assert_eq!(high.count, YIELDS + 1);
+    assert_eq!(high.buckets.iter().sum::<u64>(), high.count);
+    assert!(high.max_cycles > 0);
+    assert!(high.mean_cycles() <= high.max_cycles);
+    assert!(high.percentile(50) <= high.percentile(99));
+    assert!(high.percentile(99) <= high.max_cycles);
+
+    // 派生时入队一次，定时器到期唤醒一次
+    let low = stats.wake_latency[TaskPriority::Low.level()];
+    assert_eq!(low.count, 2);
+
+    assert_eq!(stats.wake_latency[TaskPriority::Realtime.level()].count, 0);
+    assert_eq!(stats.wake_latency[TaskPriority::Idle.level()].count, 0);
+}
+
+async fn yielder() {
+    for _ in 0..YIELDS {
+        yield_now().await;
+    }
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    os_by_rust::test_panic_handler(info);
+}
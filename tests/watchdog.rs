@@ -0,0 +1,128 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(os_by_rust::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use os_by_rust::task::executor::Executor;
+use os_by_rust::task::watchdog::{self, StallKind};
+use os_by_rust::task::{sleep_ticks, timer, Task};
+use x86_64::instructions::interrupts;
+use x86_64::VirtAddr;
+
+const THRESHOLD: u64 = 3;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use os_by_rust::allocator;
+    use os_by_rust::memory::{self, BootInfoFrameAllocator};
+
+    os_by_rust::init();
+    let physical_memory_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(physical_memory_offset) };
+    let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map) };
+    allocator::init_heap(&mut mapper, &mut frame_allocator).expect("heap initialization failed");
+
+    watchdog::set_stall_threshold(THRESHOLD);
+    let mut executor = Executor::new();
+    reports_task_stuck_in_poll(&mut executor);
+    reports_stuck_block_on(&mut executor);
+    reports_executor_not_iterating(&mut executor);
+    idle_executor_is_not_reported(&mut executor);
+    reports_masked_timer_interrupts();
+    watchdog::set_stall_threshold(watchdog::DEFAULT_STALL_TICKS);
+
+    os_by_rust::exit_qemu(os_by_rust::QemuExitCode::Success);
+    os_by_rust::hlt_loop();
+}
+
+// 开着中断忙等，时钟中断照常到来
+fn spin_ticks(ticks: u64) {
+    let start = timer::current_tick();
+    while timer::current_tick() < start + ticks {
+        core::hint::spin_loop();
+    }
+}
+
+fn reports_task_stuck_in_poll(executor: &mut Executor) {
+    let before = watchdog::stats();
+    let task = Task::new(async { spin_ticks(THRESHOLD * 2) }).named("spinner");
+    let task_id = task.id;
+    executor.try_spawn(task).unwrap();
+    executor.run_until_idle();
+
+    assert_eq!(watchdog::stats().stuck_polls, before.stuck_polls + 1);
+    let report = watchdog::last_report().unwrap();
+    assert_eq!(report.kind, StallKind::TaskPoll);
+    assert_eq!(report.task_id, Some(task_id));
+    assert_eq!(report.name, Some("spinner"));
+    assert!(report.ticks >= THRESHOLD);
+    assert!(report.instruction_pointer.is_some_and(|rip| rip != 0));
+}
+
+fn reports_stuck_block_on(executor: &mut Executor) {
+    let before = watchdog::stats();
+    executor.block_on(async { spin_ticks(THRESHOLD * 2) });
+
+    assert_eq!(watchdog::stats().stuck_polls, before.stuck_polls + 1);
+    let report = watchdog::last_report().unwrap();
+    assert_eq!(report.kind, StallKind::BlockOn);
+    assert_eq!(report.task_id, None);
+}
+
+// `run_until` 的条件在调度循环中执行，卡在其中时执行器没有进入下一轮
+fn reports_executor_not_iterating(executor: &mut Executor) {
+    let before = watchdog::stats();
+    executor.run_until(|| {
+        spin_ticks(THRESHOLD * 2);
+        true
+    });
+
+    assert_eq!(
+        watchdog::stats().executor_stalls,
+        before.executor_stalls + 1
+    );
+    assert_eq!(watchdog::last_report().unwrap().kind, StallKind::Executor);
+}
+
+// 停机等待定时器的执行器，以及已经交还控制权的执行器，都不算停顿
+fn idle_executor_is_not_reported(executor: &mut Executor) {
+    let before = watchdog::stats();
+    executor.block_on(sleep_ticks(THRESHOLD * 3));
+    spin_ticks(THRESHOLD * 2);
+
+    let after = watchdog::stats();
+    assert_eq!(after.stuck_polls, before.stuck_polls);
+    assert_eq!(after.executor_stalls, before.executor_stalls);
+}
+
+fn reports_masked_timer_interrupts() {
+    while watchdog::cycles_per_tick().is_none() {
+        x86_64::instructions::hlt();
+    }
+    let before = watchdog::stats();
+    let cycles = watchdog::cycles_per_tick().unwrap() * 8;
+    interrupts::without_interrupts(|| {
+        let start = os_by_rust::task::accounting::read_tsc();
+        while os_by_rust::task::accounting::read_tsc() - start < cycles {
+            core::hint::spin_loop();
+        }
+    });
+    // 被挂起的时钟中断在重新开中断后立即送达
+    x86_64::instructions::hlt();
+
+    assert!(watchdog::stats().missed_ticks >= before.missed_ticks + 4);
+    let report = watchdog::last_report().unwrap();
+    assert_eq!(report.kind, StallKind::TimerInterrupts);
+    assert!(report.instruction_pointer.is_some());
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    os_by_rust::test_panic_handler(info);
+}
@@ -0,0 +1,113 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(os_by_rust::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use os_by_rust::task::executor::Executor;
+use os_by_rust::task::workqueue::{self, Work, WorkQueue, WORK_QUEUE_CAPACITY};
+use os_by_rust::task::{yield_now, Task, TaskPriority};
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+use x86_64::VirtAddr;
+
+const EXTRA_ITEMS: usize = 3;
+
+static QUEUE: WorkQueue = WorkQueue::new("test");
+static EXECUTED: Mutex<Vec<usize>> = Mutex::new(Vec::new());
+// 观察者在工作者两次运行之间看到的、介于空与满之间的队列长度次数
+static PARTIAL_OBSERVATIONS: AtomicUsize = AtomicUsize::new(0);
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use os_by_rust::allocator;
+    use os_by_rust::memory::{self, BootInfoFrameAllocator};
+
+    os_by_rust::init();
+    let physical_memory_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(physical_memory_offset) };
+    let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map) };
+    allocator::init_heap(&mut mapper, &mut frame_allocator).expect("heap initialization failed");
+
+    // 和中断处理程序一样，在关中断时入队；超出容量的部分被丢弃
+    let accepted = interrupts::without_interrupts(|| {
+        (0..WORK_QUEUE_CAPACITY + EXTRA_ITEMS)
+            .filter(|&index| QUEUE.queue(Work::new(record, index)))
+            .count()
+    });
+    assert_eq!(accepted, WORK_QUEUE_CAPACITY);
+    assert_eq!(QUEUE.len(), WORK_QUEUE_CAPACITY);
+
+    let mut executor = Executor::new();
+    executor
+        .try_spawn(Task::new_with_priority(QUEUE.worker(), TaskPriority::High).named("kworker"))
+        .expect("failed to spawn worker");
+    executor
+        .try_spawn(Task::new_with_priority(observer_task(), TaskPriority::High))
+        .expect("failed to spawn observer");
+    executor
+        .try_spawn(Task::new_with_priority(check_task(), TaskPriority::Normal))
+        .expect("failed to spawn check task");
+    executor.run();
+}
+
+fn record(index: usize) {
+    assert!(
+        interrupts::are_enabled(),
+        "deferred work should run with interrupts enabled"
+    );
+    EXECUTED.lock().push(index);
+}
+
+// 与工作者同优先级的任务：工作者每批执行有限的工作后让出，观察者可以看到部分执行的队列
+async fn observer_task() {
+    while !QUEUE.is_empty() {
+        let pending = QUEUE.len();
+        if pending > 0 && pending < WORK_QUEUE_CAPACITY {
+            PARTIAL_OBSERVATIONS.fetch_add(1, Ordering::Relaxed);
+        }
+        yield_now().await;
+    }
+}
+
+async fn check_task() {
+    while QUEUE.stats().completed < WORK_QUEUE_CAPACITY as u64 {
+        yield_now().await;
+    }
+    let executed = EXECUTED.lock().clone();
+    assert_eq!(executed, (0..WORK_QUEUE_CAPACITY).collect::<Vec<_>>());
+    assert!(PARTIAL_OBSERVATIONS.load(Ordering::Relaxed) > 0);
+
+    let stats = QUEUE.stats();
+    assert_eq!(stats.name, "test");
+    assert_eq!(stats.queued, WORK_QUEUE_CAPACITY as u64);
+    assert_eq!(stats.completed, WORK_QUEUE_CAPACITY as u64);
+    assert_eq!(stats.dropped, EXTRA_ITEMS as u64);
+    assert_eq!(stats.pending, 0);
+    assert_eq!(stats.high_watermark, WORK_QUEUE_CAPACITY);
+    assert!(stats.max_delay_cycles > 0);
+    assert!(workqueue::queue_stats()
+        .iter()
+        .any(|queue| queue.name == "test"));
+
+    // 空闲的工作者在新工作入队时被唤醒
+    interrupts::without_interrupts(|| assert!(QUEUE.queue(Work::new(record, 1000))));
+    while QUEUE.stats().completed == WORK_QUEUE_CAPACITY as u64 {
+        yield_now().await;
+    }
+    assert_eq!(EXECUTED.lock().last(), Some(&1000));
+
+    os_by_rust::exit_qemu(os_by_rust::QemuExitCode::Success);
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    os_by_rust::test_panic_handler(info);
+}